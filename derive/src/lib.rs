@@ -0,0 +1,118 @@
+//! Derive macro for `env_vars_to_json::FromEnvVars`, enabled by the `derive` feature of `env-vars-to-json`
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derive `FromEnvVars` for a struct with named fields.
+///
+/// Container attributes:
+/// - `#[env(prefix = "APP__")]`: the prefix of the variables
+/// - `#[env(separator = "__")]`: the separator of the key parts
+///
+/// Field attributes:
+/// - `#[env(rename = "db")]`: read the field from the given key part instead of the field name
+/// - `#[env(secret)]`: mark the field as secret, so it is redacted by `FromEnvVars::redact` and
+///   from the diagnostics of the parser
+#[proc_macro_derive(FromEnvVars, attributes(env))]
+pub fn derive_from_env_vars(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut prefix = None;
+    let mut separator = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("env"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                prefix = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("separator") {
+                separator = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `prefix` or `separator`"))
+            }
+        })?;
+    }
+
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            name,
+            "FromEnvVars can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            name,
+            "FromEnvVars can only be derived for structs with named fields",
+        ));
+    };
+
+    let mut renames = vec![];
+    let mut secrets = vec![];
+
+    for field in &fields.named {
+        let field_name = field
+            .ident
+            .as_ref()
+            .expect("named fields have identifiers")
+            .to_string();
+        let mut rename = None;
+        let mut secret = false;
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("env"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else if meta.path.is_ident("secret") {
+                    secret = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `rename` or `secret`"))
+                }
+            })?;
+        }
+
+        if let Some(rename) = rename {
+            renames.push(quote! { (#rename, #field_name) });
+        }
+        if secret {
+            secrets.push(field_name);
+        }
+    }
+
+    let with_prefix = prefix.map(|prefix| quote! { .with_prefix(#prefix) });
+    let with_separator = separator.map(|separator| quote! { .with_separator(#separator) });
+    let with_secrets = secrets
+        .iter()
+        .map(|secret| quote! { .with_secret(#secret) });
+
+    Ok(quote! {
+        impl #impl_generics ::env_vars_to_json::FromEnvVars for #name #ty_generics #where_clause {
+            const RENAMES: &'static [(&'static str, &'static str)] = &[#(#renames),*];
+            const SECRETS: &'static [&'static str] = &[#(#secrets),*];
+
+            fn parser() -> ::env_vars_to_json::Parser {
+                ::env_vars_to_json::Parser::default() #with_prefix #with_separator #(#with_secrets)*
+            }
+        }
+    })
+}
@@ -0,0 +1,160 @@
+//! Layering of command line arguments over environment variables
+//! Requires the `clap` feature
+
+use std::borrow::Cow;
+
+use clap::{parser::ValueSource, ArgMatches};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{merge::merge, Error, Parser, Provenance};
+
+/// Convert the arguments given on the command line into json.
+///
+/// Argument ids are split on `.` into paths, e.g. `#[arg(id = "db.port")]` sets `{"db": {"port": ...}}`,
+/// values are coerced like variable values and arguments with several values become arrays.
+/// Arguments set from clap default values are left out.
+pub fn args_to_json(parser: &Parser, matches: &ArgMatches) -> Result<Value, Error> {
+    matches_to_json(parser, matches, &Value::Null, |source| {
+        source != ValueSource::DefaultValue
+    })
+}
+
+/// Parse variables and layer command line arguments over them, with the precedence
+/// arguments > variables > defaults, where defaults are the parser's json and clap default values
+pub fn parse_with_args(
+    parser: &Parser,
+    matches: &ArgMatches,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<Value, Error> {
+    let mut defaults = parser.json.clone();
+    merge(
+        &mut defaults,
+        matches_to_json(parser, matches, &parser.json, |source| {
+            source == ValueSource::DefaultValue
+        })?,
+    );
+
+    let mut json = parser.parse_iter_to(defaults, vars)?;
+    let args = matches_to_json(parser, matches, &json, |source| {
+        source != ValueSource::DefaultValue
+    })?;
+    merge(&mut json, args);
+
+    Ok(json)
+}
+
+/// Parse variables, layer command line arguments over them and deserialize the result into `T`,
+/// see [`parse_with_args`]
+pub fn parse_with_args_into<T: DeserializeOwned>(
+    parser: &Parser,
+    matches: &ArgMatches,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<T, Error> {
+    Parser::deserialize(parse_with_args(parser, matches, vars)?, &Provenance::new())
+}
+
+/// Convert the arguments selected by `include` into json, resolving end-relative indices like `-1`
+/// against `base`, the document the result is merged into
+fn matches_to_json(
+    parser: &Parser,
+    matches: &ArgMatches,
+    base: &Value,
+    include: impl Fn(ValueSource) -> bool,
+) -> Result<Value, Error> {
+    let mut json = Value::Object(Default::default());
+
+    for id in matches.ids() {
+        let id = id.as_str();
+
+        // Groups have no value source
+        if !matches.value_source(id).is_some_and(&include) {
+            continue;
+        }
+        let Ok(Some(raw)) = matches.try_get_raw(id) else {
+            continue;
+        };
+
+        let key_parts = id.split('.').map(Cow::Borrowed).collect::<Vec<_>>();
+        let keep_leading_zeros =
+            parser.keep_leading_zeros_at_path(&key_parts.join(&parser.separator));
+
+        let mut values = raw
+            .map(|value| parser.coerce_value(value.to_string_lossy(), keep_leading_zeros))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let value = match values.len() {
+            1 => values.remove(0),
+            _ => Value::Array(values),
+        };
+        crate::tree::insert(&mut json, &parser.json_path_in(base, &key_parts), value)?;
+    }
+
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Arg, ArgAction, Command};
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    fn command() -> Command {
+        Command::new("app")
+            .arg(
+                Arg::new("db.host")
+                    .long("db-host")
+                    .default_value("default.example.com"),
+            )
+            .arg(Arg::new("db.port").long("db-port").default_value("5432"))
+            .arg(Arg::new("debug").long("debug").action(ArgAction::SetTrue))
+            .arg(Arg::new("tags").long("tag").action(ArgAction::Append))
+    }
+
+    #[test]
+    fn test_parse_with_args() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("APP__")
+            .with_json(json!({ "db": { "user": "admin" } }));
+        let matches =
+            command().get_matches_from(["app", "--db-port", "6543", "--tag", "a", "--tag", "b"]);
+        let vars = test_util::vars(&[
+            ("APP__DB__HOST", "env.example.com"),
+            ("APP__DB__PORT", "7654"),
+        ]);
+
+        assert_eq!(
+            parse_with_args(&parser, &matches, vars)?,
+            json!({
+                "db": { "host": "env.example.com", "port": 6543, "user": "admin" },
+                "debug": false,
+                "tags": ["a", "b"]
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_with_args_negative_indices() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("APP__")
+            .with_negative_indices(true)
+            .with_json(json!({ "tags": ["a"] }));
+        let matches = Command::new("app")
+            .arg(Arg::new("tags.-1").long("extra-tag"))
+            .get_matches_from(["app", "--extra-tag", "c"]);
+        let vars = [("APP__TAGS__-1".to_string(), "b".to_string())].into_iter();
+
+        // The argument appends to the list the variables were merged into
+        assert_eq!(
+            parse_with_args(&parser, &matches, vars)?,
+            json!({ "tags": ["a", "b", "c"] })
+        );
+        assert_eq!(args_to_json(&parser, &matches)?, json!({ "tags": ["c"] }));
+
+        Ok(())
+    }
+}
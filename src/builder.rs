@@ -0,0 +1,320 @@
+//! Fallible construction of a [`Parser`]
+
+use std::{ops::RangeBounds, sync::Arc};
+
+use serde_json::Value;
+
+#[cfg(feature = "filter")]
+use crate::filter::{compile, FilterSetBuilder};
+#[cfg(feature = "unicode")]
+use crate::KeyNormalization;
+use crate::{
+    segment::KeySegmenter, spec::EnvSpec, validate::Validator, EmptyKeyParts, EmptyValues, Error,
+    Parser, ValueParser,
+};
+#[cfg(feature = "filter")]
+use crate::{FilterSet, Rule};
+
+/// Collect parser options and build a [`Parser`].
+///
+/// Unlike the `with_*` methods of [`Parser`], the builder does not consume itself and reports
+/// invalid options such as malformed regex patterns from [`ParserBuilder::build`] instead of panicking.
+/// The fields of the built parser are private, so its options can be read but not changed in place
+#[derive(Debug, Clone, Default)]
+pub struct ParserBuilder {
+    parser: Parser,
+
+    #[cfg(feature = "filter")]
+    key_filters: FilterSetBuilder,
+
+    #[cfg(feature = "filter")]
+    value_include: Vec<String>,
+
+    #[cfg(feature = "filter")]
+    value_exclude: Vec<String>,
+
+    #[cfg(feature = "filter")]
+    value_patterns: Vec<(String, String)>,
+
+    #[cfg(feature = "json5")]
+    json5: Option<String>,
+}
+
+impl ParserBuilder {
+    /// Return a new builder with default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the prefix
+    pub fn prefix(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.parser.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set the separator
+    pub fn separator(&mut self, separator: impl Into<String>) -> &mut Self {
+        self.parser.separator = separator.into();
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add include patterns, compiled when building
+    /// Requires the `filter` feature
+    pub fn include<S: AsRef<str>>(&mut self, patterns: &[S]) -> &mut Self {
+        self.key_filters.include(patterns);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add exclude patterns, compiled when building
+    /// Requires the `filter` feature
+    pub fn exclude<S: AsRef<str>>(&mut self, patterns: &[S]) -> &mut Self {
+        self.key_filters.exclude(patterns);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add include globs, e.g. `PREFIX__DB__*`, see [`crate::filter::glob_to_regex`]
+    /// Requires the `filter` feature
+    pub fn include_globs<S: AsRef<str>>(&mut self, globs: &[S]) -> &mut Self {
+        self.key_filters.include_globs(globs);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add exclude globs, e.g. `*_SECRET`, see [`crate::filter::glob_to_regex`]
+    /// Requires the `filter` feature
+    pub fn exclude_globs<S: AsRef<str>>(&mut self, globs: &[S]) -> &mut Self {
+        self.key_filters.exclude_globs(globs);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add include patterns for values, compiled when building
+    /// Requires the `filter` feature
+    pub fn value_include<S: AsRef<str>>(&mut self, patterns: &[S]) -> &mut Self {
+        self.value_include
+            .extend(patterns.iter().map(|pattern| pattern.as_ref().to_string()));
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add exclude patterns for values, compiled when building
+    /// Requires the `filter` feature
+    pub fn value_exclude<S: AsRef<str>>(&mut self, patterns: &[S]) -> &mut Self {
+        self.value_exclude
+            .extend(patterns.iter().map(|pattern| pattern.as_ref().to_string()));
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Add a pattern which values at the path must match, compiled when building,
+    /// see [`Parser::with_value_pattern`]
+    /// Requires the `filter` feature
+    pub fn value_pattern(
+        &mut self,
+        path: impl Into<String>,
+        pattern: impl Into<String>,
+    ) -> &mut Self {
+        self.value_patterns.push((path.into(), pattern.into()));
+        self
+    }
+
+    /// Set the json object to merge the parsed environment variables into
+    pub fn json(&mut self, json: Value) -> &mut Self {
+        self.parser.json = json;
+        #[cfg(feature = "json5")]
+        {
+            self.json5 = None;
+        }
+        self
+    }
+
+    #[cfg(feature = "json5")]
+    /// Set the json object from JSON5 text, e.g. a defaults file with comments and trailing commas,
+    /// parsed when building
+    /// Requires the `json5` feature
+    pub fn json5(&mut self, text: impl Into<String>) -> &mut Self {
+        self.json5 = Some(text.into());
+        self
+    }
+
+    /// Apply any other infallible option, e.g. `builder.configure(|options| options.with_trim_values(true))`.
+    /// Options taking patterns are set with the methods of the builder instead
+    pub fn configure(&mut self, configure: impl FnOnce(Options) -> Options) -> &mut Self {
+        self.parser = configure(Options(std::mem::take(&mut self.parser))).0;
+        self
+    }
+
+    /// Check the options and build the parser
+    pub fn build(&self) -> Result<Parser, Error> {
+        #[allow(unused_mut)]
+        let mut parser = self.parser.clone();
+
+        #[cfg(feature = "filter")]
+        {
+            parser.key_filters.extend(&self.key_filters)?;
+            parser
+                .value_include
+                .extend(compile(&self.value_include, false)?);
+            parser
+                .value_exclude
+                .extend(compile(&self.value_exclude, false)?);
+
+            for (path, pattern) in &self.value_patterns {
+                let pattern = regex::Regex::new(pattern).map_err(Error::Regex)?;
+                parser.rules.push((path.clone(), Rule::Pattern(pattern)));
+            }
+        }
+
+        #[cfg(feature = "json5")]
+        if let Some(text) = &self.json5 {
+            parser.json = json5::from_str(text).map_err(Error::Json5)?;
+        }
+
+        parser.validate()?;
+
+        Ok(parser)
+    }
+}
+
+/// The infallible options of a [`Parser`], see [`ParserBuilder::configure`]
+#[derive(Debug)]
+pub struct Options(Parser);
+
+/// Forward infallible `with_*` methods of [`Parser`] to [`Options`]
+macro_rules! forward {
+    ($($(#[$attr:meta])* $name:ident $([$($generics:tt)*])? ($($arg:ident: $ty:ty),*);)*) => {
+        impl Options {
+            $(
+                $(#[$attr])*
+                #[doc = concat!("See [`Parser::", stringify!($name), "`]")]
+                pub fn $name $(<$($generics)*>)? (self, $($arg: $ty),*) -> Self {
+                    Self(self.0.$name($($arg),*))
+                }
+            )*
+        }
+    };
+}
+
+forward! {
+    #[cfg(feature = "filter")]
+    with_case_insensitive_filters(case_insensitive: bool);
+    #[cfg(feature = "filter")]
+    with_filter_set(filters: FilterSet);
+    #[cfg(feature = "filter")]
+    with_filters_without_prefix(without_prefix: bool);
+    with_skip_empty_values(skip_empty_values: bool);
+    with_tombstone(tombstone: impl Into<String>);
+    with_empty_values(empty_values: EmptyValues);
+    with_radix_literals(radix_literals: bool);
+    with_big_ints_as_strings(big_ints_as_strings: bool);
+    with_strict_numbers(strict_numbers: bool);
+    with_keep_leading_zeros(keep_leading_zeros: bool);
+    with_keep_leading_zeros_at(path: impl Into<String>, keep: bool);
+    with_trim_values(trim_values: bool);
+    with_trim_trailing_newline(trim_trailing_newline: bool);
+    with_declaration_order(declaration_order: bool);
+    with_sort_keys(sort_keys: bool);
+    with_key_segmenter(key_segmenter: Box<dyn KeySegmenter>);
+    with_bracket_indices(bracket_indices: bool);
+    with_value_parser(value_parser: Box<dyn ValueParser>);
+    with_value_parsers(value_parsers: Vec<Arc<dyn ValueParser>>);
+    with_compact_arrays(compact_arrays: bool);
+    with_compact_arrays_at(path: impl Into<String>, compact: bool);
+    with_negative_indices(negative_indices: bool);
+    with_lowercase_keys(lowercase_keys: bool);
+    with_numeric_object_keys(numeric_object_keys: bool);
+    with_numeric_object_keys_at(path: impl Into<String>, object_keys: bool);
+    with_secret(path: impl Into<String>);
+    with_known_paths(paths: impl IntoIterator<Item = impl AsRef<str>>);
+    with_strict_spec(spec: &EnvSpec);
+    with_filter(predicate: impl Fn(&str, &str) -> bool + Send + Sync + 'static);
+    with_prefix_map[P: Into<String>, S: Into<String>](prefix_map: impl IntoIterator<Item = (P, S)>);
+    with_mount(mount: impl Into<String>);
+    with_profiles[P: Into<String>](profiles: impl IntoIterator<Item = P>);
+    with_active_profile(profile: impl Into<String>);
+    with_secret_vars[K: AsRef<str>](keys: impl IntoIterator<Item = K>);
+    with_max_depth(max_depth: usize);
+    with_overrides[K: Into<String>, V: Into<String>](overrides: impl IntoIterator<Item = (K, V)>);
+    with_require_match(require_match: bool);
+    with_empty_key_parts(empty_key_parts: EmptyKeyParts);
+    with_allowed[S: AsRef<str>](path: impl Into<String>, allowed: &[S]);
+    with_range[N: Into<f64> + Copy](path: impl Into<String>, range: impl RangeBounds<N>);
+    with_validator(path_glob: impl Into<String>, validator: Box<dyn Validator>);
+    #[cfg(feature = "uuid")]
+    with_uuid(path_glob: impl Into<String>);
+    with_ip_addr(path_glob: impl Into<String>);
+    with_socket_addr(path_glob: impl Into<String>);
+    with_cidr(path_glob: impl Into<String>);
+    #[cfg(feature = "base64")]
+    with_base64_values(base64_values: bool);
+    #[cfg(feature = "unicode")]
+    with_nfc_values(nfc_values: bool);
+    #[cfg(feature = "unicode")]
+    with_key_normalization(form: KeyNormalization);
+}
+
+impl Parser {
+    /// Return a new builder
+    pub fn builder() -> ParserBuilder {
+        ParserBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_build() -> Result<(), Error> {
+        let mut builder = Parser::builder();
+        builder
+            .prefix("PREFIX__")
+            .json(json!({ "int": 0 }))
+            .configure(|options| options.with_trim_values(true));
+
+        let parser = builder.build()?;
+        assert_eq!(parser.prefix(), Some("PREFIX__"));
+        assert_eq!(parser.separator(), "__");
+        assert_eq!(parser.json(), &json!({ "int": 0 }));
+
+        let actual =
+            parser.parse_iter([("PREFIX__INT".to_string(), " 1 ".to_string())].into_iter())?;
+        assert_eq!(actual, json!({ "int": 1 }));
+
+        // The builder can be reused after building
+        builder.separator("");
+        assert!(matches!(builder.build(), Err(Error::Config(_))));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn test_build_invalid_regex() {
+        let mut builder = Parser::builder();
+        builder.include(&["("]);
+
+        assert!(matches!(builder.build(), Err(Error::Regex(_))));
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn test_build_json5() -> Result<(), Error> {
+        let mut builder = Parser::builder();
+        builder.json5("{\n  // defaults\n  int: 0,\n  list: ['a',],\n}");
+
+        let parser = builder.build()?;
+        let actual = parser.parse_iter([("INT".to_string(), "1".to_string())].into_iter())?;
+        assert_eq!(actual, json!({ "int": 1, "list": ["a"] }));
+
+        builder.json5("{ int: }");
+        assert!(matches!(builder.build(), Err(Error::Json5(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! Coercion of values into json scalars by an ordered chain of value parsers
+
+use std::{fmt, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{is_canonical_number, is_integer_literal, parse_radix_literal, Error, Parser};
+
+/// A step of the coercion chain, see [`Parser::with_value_parser`].
+///
+/// Parsers are tried in order and the first one returning a value wins. Values no parser accepts
+/// become strings. Implemented for closures taking the text of the value:
+///
+/// ```
+/// use env_vars_to_json::Parser;
+/// use serde_json::{json, Value};
+///
+/// // Durations like `30s` become seconds
+/// let parser = Parser::default().with_value_parser(Box::new(|text: &str| {
+///     let seconds = text.strip_suffix('s')?.parse::<u64>().ok()?;
+///     Some(Value::from(seconds))
+/// }));
+///
+/// let vars = [("TIMEOUT".to_string(), "30s".to_string())];
+/// assert_eq!(parser.parse_iter(vars.into_iter())?, json!({ "timeout": 30 }));
+/// # Ok::<(), env_vars_to_json::Error>(())
+/// ```
+pub trait ValueParser: Send + Sync {
+    /// Parse the text of a value, `None` to leave it to the next parser of the chain.
+    /// The parser's options, e.g. `strict_numbers`, are available through `parser`
+    fn parse(&self, text: &str, parser: &Parser) -> Result<Option<Value>, Error>;
+}
+
+impl<F: Fn(&str) -> Option<Value> + Send + Sync> ValueParser for F {
+    fn parse(&self, text: &str, _parser: &Parser) -> Result<Option<Value>, Error> {
+        Ok(self(text))
+    }
+}
+
+impl fmt::Debug for dyn ValueParser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ValueParser")
+    }
+}
+
+/// Return the built-in chain: [`Integer`], [`RadixLiteral`], [`BigIntString`], [`Float`], [`Bool`]
+pub fn default_chain() -> Vec<Arc<dyn ValueParser>> {
+    vec![
+        Arc::new(Integer),
+        Arc::new(RadixLiteral),
+        Arc::new(BigIntString),
+        Arc::new(Float),
+        Arc::new(Bool),
+    ]
+}
+
+/// Whether the text may be coerced into a decimal number, in strict mode only canonical ones,
+/// e.g. `1.10`, `1e5` and `08` stay strings
+fn is_decimal(text: &str, parser: &Parser) -> bool {
+    !parser.strict_numbers || is_canonical_number(text)
+}
+
+/// Integers which fit into `i64` or `u64`, e.g. `-1` or `18446744073709551615`
+#[derive(Debug, Clone, Copy)]
+pub struct Integer;
+
+impl ValueParser for Integer {
+    fn parse(&self, text: &str, parser: &Parser) -> Result<Option<Value>, Error> {
+        if !is_decimal(text, parser) {
+            return Ok(None);
+        }
+
+        Ok(text
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| text.parse::<u64>().map(Value::from))
+            .ok())
+    }
+}
+
+/// Hexadecimal, octal and binary literals like `0xff`, if `radix_literals` is enabled
+#[derive(Debug, Clone, Copy)]
+pub struct RadixLiteral;
+
+impl ValueParser for RadixLiteral {
+    fn parse(&self, text: &str, parser: &Parser) -> Result<Option<Value>, Error> {
+        Ok(parser
+            .radix_literals
+            .then(|| parse_radix_literal(text))
+            .flatten()
+            .map(Value::from))
+    }
+}
+
+/// Integers too large for `u64` as strings, if `big_ints_as_strings` is enabled
+#[derive(Debug, Clone, Copy)]
+pub struct BigIntString;
+
+impl ValueParser for BigIntString {
+    fn parse(&self, text: &str, parser: &Parser) -> Result<Option<Value>, Error> {
+        Ok((parser.big_ints_as_strings && is_integer_literal(text))
+            .then(|| Value::String(text.to_string())))
+    }
+}
+
+/// Other numbers, exactly with the `arbitrary_precision` feature and as `f64` otherwise.
+/// In strict mode only numbers which survive the round trip through `f64` unchanged
+#[derive(Debug, Clone, Copy)]
+pub struct Float;
+
+impl ValueParser for Float {
+    fn parse(&self, text: &str, parser: &Parser) -> Result<Option<Value>, Error> {
+        if !is_decimal(text, parser) {
+            return Ok(None);
+        }
+
+        if let Some(number) = Parser::parse_exact_number(text) {
+            return Ok(Some(Value::Number(number)));
+        }
+
+        Ok(parser.parse_float(text)?.map(Value::Number))
+    }
+}
+
+/// `true` and `false`
+#[derive(Debug, Clone, Copy)]
+pub struct Bool;
+
+impl ValueParser for Bool {
+    fn parse(&self, text: &str, _parser: &Parser) -> Result<Option<Value>, Error> {
+        Ok(text.parse::<bool>().ok().map(Value::Bool))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_value_parsers() -> Result<(), Error> {
+        let vars = || {
+            test_util::vars(&[
+                ("SIZE", "2k"),
+                ("FLAG", "yes"),
+                ("PORT", "80"),
+                ("NAME", "app"),
+            ])
+        };
+        let size = |text: &str| {
+            let kilobytes = text.strip_suffix('k')?.parse::<u64>().ok()?;
+            Some(Value::from(kilobytes * 1024))
+        };
+        let yes = |text: &str| (text == "yes").then_some(Value::Bool(true));
+
+        let parser = Parser::default()
+            .with_value_parser(Box::new(size))
+            .with_value_parser(Box::new(yes));
+        assert_eq!(
+            parser.parse_iter(vars())?,
+            json!({ "size": 2048, "flag": true, "port": 80, "name": "app" })
+        );
+
+        // Without the built-in parsers, numbers stay strings
+        let parser = Parser::default().with_value_parsers(vec![Arc::new(yes)]);
+        assert_eq!(
+            parser.parse_iter(vars())?,
+            json!({ "size": "2k", "flag": true, "port": "80", "name": "app" })
+        );
+
+        Ok(())
+    }
+}
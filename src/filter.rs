@@ -0,0 +1,283 @@
+//! Patterns selecting variables by key
+//! Requires the `filter` feature
+
+use std::sync::Arc;
+
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+
+use crate::{Error, SkipReason};
+
+/// Validated include/exclude patterns for keys, cheap to clone and share between parsers.
+///
+/// A key is valid if it matches one of the include patterns, or there are none,
+/// and it matches none of the exclude patterns.
+#[derive(Debug, Clone, Default)]
+pub struct FilterSet(Arc<Patterns>);
+
+#[derive(Debug, Clone, Default)]
+struct Patterns {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    case_insensitive: bool,
+
+    /// The include patterns compiled into one set, so a key is matched in a single pass
+    include_set: RegexSet,
+
+    /// The exclude patterns compiled into one set
+    exclude_set: RegexSet,
+}
+
+impl Patterns {
+    /// Cache the sets of the patterns
+    fn new(include: Vec<Regex>, exclude: Vec<Regex>, case_insensitive: bool) -> Self {
+        let set = |patterns: &[Regex]| {
+            RegexSetBuilder::new(patterns.iter().map(Regex::as_str))
+                .case_insensitive(case_insensitive)
+                .build()
+                .expect("patterns have been compiled before")
+        };
+
+        Self {
+            include_set: set(&include),
+            exclude_set: set(&exclude),
+            include,
+            exclude,
+            case_insensitive,
+        }
+    }
+}
+
+impl FilterSet {
+    /// Compile the include and exclude patterns
+    pub fn new<S: AsRef<str>>(include: &[S], exclude: &[S]) -> Result<Self, Error> {
+        Self::builder().include(include).exclude(exclude).build()
+    }
+
+    /// Return a new builder
+    pub fn builder() -> FilterSetBuilder {
+        FilterSetBuilder::default()
+    }
+
+    /// Whether the key is selected by the patterns
+    pub fn is_key_valid(&self, key: &str) -> bool {
+        self.skip_reason(key).is_none()
+    }
+
+    /// The include patterns
+    pub fn include(&self) -> &[Regex] {
+        &self.0.include
+    }
+
+    /// The exclude patterns
+    pub fn exclude(&self) -> &[Regex] {
+        &self.0.exclude
+    }
+
+    /// Whether the patterns match case-insensitively
+    pub fn is_case_insensitive(&self) -> bool {
+        self.0.case_insensitive
+    }
+
+    /// Whether there are no patterns, so every key is valid
+    pub fn is_empty(&self) -> bool {
+        self.0.include.is_empty() && self.0.exclude.is_empty()
+    }
+
+    /// Return why the key is skipped by the patterns, `None` if it is valid
+    pub(crate) fn skip_reason(&self, key: &str) -> Option<SkipReason> {
+        // If include is empty, key is valid, else key must match at least one of the patterns
+        if !self.0.include.is_empty() && !self.0.include_set.is_match(key) {
+            return Some(SkipReason::Include);
+        }
+
+        // If exclude is empty, key is valid, else key must not match any of the patterns
+        if self.0.exclude_set.is_match(key) {
+            return Some(SkipReason::Exclude);
+        }
+
+        None
+    }
+
+    /// Replace the include patterns
+    pub(crate) fn set_include(&mut self, include: Vec<Regex>) {
+        self.0 = Arc::new(Patterns::new(
+            include,
+            self.0.exclude.clone(),
+            self.0.case_insensitive,
+        ));
+    }
+
+    /// Replace the exclude patterns
+    pub(crate) fn set_exclude(&mut self, exclude: Vec<Regex>) {
+        self.0 = Arc::new(Patterns::new(
+            self.0.include.clone(),
+            exclude,
+            self.0.case_insensitive,
+        ));
+    }
+
+    /// Compile and add the patterns of a builder, with the case sensitivity of this set
+    pub(crate) fn extend(&mut self, builder: &FilterSetBuilder) -> Result<(), Error> {
+        let case_insensitive = self.0.case_insensitive;
+        let include = compile(&builder.include, case_insensitive)?;
+        let exclude = compile(&builder.exclude, case_insensitive)?;
+
+        self.0 = Arc::new(Patterns::new(
+            self.0.include.iter().cloned().chain(include).collect(),
+            self.0.exclude.iter().cloned().chain(exclude).collect(),
+            case_insensitive,
+        ));
+        Ok(())
+    }
+
+    /// Recompile the patterns with the given case sensitivity
+    pub(crate) fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        let recompile = |patterns: &[Regex]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    key_regex(pattern.as_str(), case_insensitive)
+                        .expect("patterns have been compiled before")
+                })
+                .collect()
+        };
+
+        self.0 = Arc::new(Patterns::new(
+            recompile(&self.0.include),
+            recompile(&self.0.exclude),
+            case_insensitive,
+        ));
+    }
+}
+
+/// Collect patterns and build a validated [`FilterSet`]
+#[derive(Debug, Clone, Default)]
+pub struct FilterSetBuilder {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    case_insensitive: bool,
+}
+
+impl FilterSetBuilder {
+    /// Add include patterns, compiled when building
+    pub fn include<S: AsRef<str>>(&mut self, patterns: &[S]) -> &mut Self {
+        self.include
+            .extend(patterns.iter().map(|pattern| pattern.as_ref().to_string()));
+        self
+    }
+
+    /// Add exclude patterns, compiled when building
+    pub fn exclude<S: AsRef<str>>(&mut self, patterns: &[S]) -> &mut Self {
+        self.exclude
+            .extend(patterns.iter().map(|pattern| pattern.as_ref().to_string()));
+        self
+    }
+
+    /// Add include globs, e.g. `PREFIX__DB__*`, see [`glob_to_regex`]
+    pub fn include_globs<S: AsRef<str>>(&mut self, globs: &[S]) -> &mut Self {
+        self.include
+            .extend(globs.iter().map(|glob| glob_to_regex(glob.as_ref())));
+        self
+    }
+
+    /// Add exclude globs, e.g. `*_SECRET`, see [`glob_to_regex`]
+    pub fn exclude_globs<S: AsRef<str>>(&mut self, globs: &[S]) -> &mut Self {
+        self.exclude
+            .extend(globs.iter().map(|glob| glob_to_regex(glob.as_ref())));
+        self
+    }
+
+    /// Set whether the patterns match case-insensitively
+    pub fn case_insensitive(&mut self, case_insensitive: bool) -> &mut Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Compile the patterns
+    pub fn build(&self) -> Result<FilterSet, Error> {
+        Ok(FilterSet(Arc::new(Patterns::new(
+            compile(&self.include, self.case_insensitive)?,
+            compile(&self.exclude, self.case_insensitive)?,
+            self.case_insensitive,
+        ))))
+    }
+}
+
+/// Compile patterns, reporting the first invalid one
+pub(crate) fn compile(patterns: &[String], case_insensitive: bool) -> Result<Vec<Regex>, Error> {
+    patterns
+        .iter()
+        .map(|pattern| key_regex(pattern, case_insensitive).map_err(Error::Regex))
+        .collect()
+}
+
+/// Compile a pattern
+pub(crate) fn key_regex(pattern: &str, case_insensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+}
+
+/// Compile a glob, whose translated pattern is always a valid regex
+pub(crate) fn glob_regex(glob: &str, case_insensitive: bool) -> Regex {
+    key_regex(&glob_to_regex(glob), case_insensitive).expect("globs translate into valid regexes")
+}
+
+/// Translate a glob into an anchored regex pattern matching the whole key.
+///
+/// `*` matches any characters, `?` matches a single character and any other character matches itself,
+/// e.g. `DB_*` matches `DB_HOST` but not `MY_DB_HOST`.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::with_capacity(glob.len() + 2);
+    pattern.push('^');
+
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    pattern.push('$');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_set() -> Result<(), Error> {
+        let filters = FilterSet::builder()
+            .include(&["^DB__"])
+            .exclude_globs(&["*_SECRET"])
+            .case_insensitive(true)
+            .build()?;
+        let shared = filters.clone();
+
+        assert!(shared.is_key_valid("db__host"));
+        assert!(!shared.is_key_valid("DB__API_SECRET"));
+        assert!(!shared.is_key_valid("HTTP__HOST"));
+        assert!(Arc::ptr_eq(&filters.0, &shared.0));
+        assert!(matches!(FilterSet::new(&["("], &[]), Err(Error::Regex(_))));
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("DB_*", "DB_HOST", true)]
+    #[case("DB_*", "MY_DB_HOST", false)]
+    #[case("*_SECRET", "API_SECRET", true)]
+    #[case("*_SECRET", "API_SECRET_KEY", false)]
+    #[case("PORT_?", "PORT_1", true)]
+    #[case("PORT_?", "PORT_10", false)]
+    #[case("A.B", "AxB", false)]
+    fn test_glob_to_regex(#[case] glob: &str, #[case] key: &str, #[case] expected: bool) {
+        let regex = Regex::new(&glob_to_regex(glob)).unwrap();
+
+        assert_eq!(regex.is_match(key), expected);
+    }
+}
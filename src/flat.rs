@@ -0,0 +1,334 @@
+//! The flat representation of preprocessed variables, before they are merged into the base json
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet},
+};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use serde_json::Value;
+
+use crate::{owned, tree, Error, JsonIndex, JsonPath, Parser, Provenance, ValueOps, Var};
+
+/// A preprocessed variable, split into key segments with its coerced value
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatEntry {
+    /// The variable the value came from, e.g. `PREFIX__DB__PORT`
+    pub var: String,
+
+    /// The key segments, lowercase unless disabled, e.g. `["db", "port"]`
+    pub segments: Vec<String>,
+
+    /// The coerced value, `None` if the variable removes the path from the base json
+    pub value: Option<Value>,
+}
+
+/// The entries of preprocessed variables in the order they are merged, later entries overriding
+/// earlier ones, see [`Parser::flat_entries`]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FlatEntries {
+    /// The entries
+    pub entries: Vec<FlatEntry>,
+}
+
+impl FlatEntries {
+    /// Keep only the last entry of each key segments, which is the one that would win the merge
+    pub fn dedup(&mut self) {
+        let mut seen = HashSet::new();
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.reverse();
+        entries.retain(|entry| seen.insert(entry.segments.clone()));
+        entries.reverse();
+        self.entries = entries;
+    }
+}
+
+impl FromIterator<FlatEntry> for FlatEntries {
+    fn from_iter<I: IntoIterator<Item = FlatEntry>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl IntoIterator for FlatEntries {
+    type Item = FlatEntry;
+    type IntoIter = std::vec::IntoIter<FlatEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl Parser {
+    /// Parse iterator of String tuples into flat entries without merging them, e.g. to reorder,
+    /// dedupe or rewrite them before merging with [`Parser::parse_flat_entries`].
+    /// Values are validated against the rules here
+    pub fn flat_entries(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<FlatEntries, Error> {
+        self.validate()?;
+        self.flat_vars(owned(vars))
+    }
+
+    /// Merge flat entries into the parser's json object in order
+    pub fn parse_flat_entries(&self, entries: FlatEntries) -> Result<Value, Error> {
+        let mut json = self.json.clone();
+        self.merge_entries(&mut json, entries, None)?;
+        Ok(json)
+    }
+
+    /// Preprocess variables into flat entries, checking the coerced values against the rules
+    pub(crate) fn flat_vars<'a>(
+        &self,
+        vars: impl Iterator<Item = Var<'a>>,
+    ) -> Result<FlatEntries, Error> {
+        let (keys, values): (Vec<_>, Vec<_>) = self.preprocess_vars(vars)?.into_iter().unzip();
+        debug!(count = keys.len(), "preprocessed variables");
+        if self.require_match && keys.is_empty() {
+            return Err(self.no_match());
+        }
+
+        let erased = values
+            .iter()
+            .map(|value| self.erases(value))
+            .collect::<Vec<_>>();
+
+        #[cfg(feature = "rayon")]
+        let assignments = keys.par_iter().zip(values);
+        #[cfg(not(feature = "rayon"))]
+        let assignments = keys.iter().zip(values);
+
+        let assignments = assignments
+            .map(|(key, value)| self.assignment(key, value))
+            .collect::<Result<Vec<_>, Error>>()?;
+        self.check_rules(
+            keys.iter()
+                .zip(&assignments)
+                .zip(&erased)
+                .filter(|(_, erased)| !**erased)
+                .map(|((key, (key_parts, value)), _)| (key.as_ref(), key_parts.as_slice(), value)),
+        )?;
+
+        #[cfg(feature = "uuid")]
+        let assignments = assignments
+            .into_iter()
+            .map(|(key_parts, mut value)| {
+                self.canonicalize(&key_parts, &mut value);
+                (key_parts, value)
+            })
+            .collect::<Vec<_>>();
+
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        Ok(keys
+            .iter()
+            .zip(assignments)
+            .zip(erased)
+            .map(|((key, (key_parts, value)), erased)| FlatEntry {
+                var: format!("{prefix}{key}"),
+                segments: key_parts.into_iter().map(Cow::into_owned).collect(),
+                value: (!erased).then_some(value),
+            })
+            .collect())
+    }
+
+    /// Merge flat entries into a document in order, recording the variable of each value
+    pub(crate) fn merge_entries<V: ValueOps>(
+        &self,
+        json: &mut V,
+        entries: FlatEntries,
+        mut provenance: Option<&mut Provenance>,
+    ) -> Result<(), Error> {
+        let paths = self.entry_paths(json, &entries.entries);
+        let mut compacted = BTreeSet::new();
+
+        for (entry, path) in entries.into_iter().zip(paths) {
+            self.merge_entry(json, &entry, &path, &mut compacted)?;
+
+            if let (Some(provenance), Some(_)) = (provenance.as_deref_mut(), &entry.value) {
+                provenance.insert(path, entry.var);
+            }
+        }
+
+        self.finish_merge(json, compacted);
+        Ok(())
+    }
+
+    /// Return the json paths of entries, resolving end-relative indices against the document
+    /// before any of them is merged
+    pub(crate) fn entry_paths<V: ValueOps>(
+        &self,
+        json: &mut V,
+        entries: &[FlatEntry],
+    ) -> Vec<JsonPath> {
+        entries
+            .iter()
+            .map(|entry| self.json_path_with(&entry.segments, |path| tree::array_len(json, path)))
+            .collect()
+    }
+
+    /// Merge an entry into a document at its json path, collecting the arrays to compact
+    pub(crate) fn merge_entry<V: ValueOps>(
+        &self,
+        json: &mut V,
+        entry: &FlatEntry,
+        path: &JsonPath,
+        compacted: &mut BTreeSet<JsonPath>,
+    ) -> Result<(), Error> {
+        let Some(value) = &entry.value else {
+            tree::remove(json, path);
+            debug!(var = %entry.var, path = %entry.segments.join("."), "erased path");
+            return Ok(());
+        };
+
+        tree::insert(json, path, V::from_scalar(value.clone().into())).map_err(
+            |err| match err {
+                Error::MissingItems { path, var: None } => Error::MissingItems {
+                    path,
+                    var: Some(entry.var.clone()),
+                },
+                err => err,
+            },
+        )?;
+        debug!(var = %entry.var, path = %entry.segments.join("."), "merged variable");
+
+        compacted.extend(
+            (0..path.len())
+                .filter(|i| matches!(path[*i], JsonIndex::Usize(_)))
+                .filter(|i| self.compacts_array_at(&entry.segments[..*i]))
+                .map(|i| JsonPath::from(&path[..i])),
+        );
+        Ok(())
+    }
+
+    /// Compact the collected arrays and sort the keys of a document after merging entries
+    pub(crate) fn finish_merge<V: ValueOps>(&self, json: &mut V, compacted: BTreeSet<JsonPath>) {
+        // Deeper arrays first, so removing items doesn't shift the paths of arrays below them
+        let mut compacted = compacted.into_iter().collect::<Vec<_>>();
+        compacted.sort_by_key(|path| std::cmp::Reverse(path.len()));
+        for path in compacted {
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            let removed = tree::compact(json, &path);
+            debug!(path = %path, removed, "compacted array");
+        }
+
+        if self.sort_keys {
+            json.sort_keys();
+        }
+    }
+
+    /// The error of a parser requiring a match when no variable is selected
+    pub(crate) fn no_match(&self) -> Error {
+        Error::NoMatch {
+            prefix: self.prefix.clone(),
+        }
+    }
+
+    /// Whether the array at the key parts is compacted
+    fn compacts_array_at(&self, key_parts: &[String]) -> bool {
+        if self.compact_arrays_at.is_empty() {
+            return self.compact_arrays;
+        }
+
+        self.compact_arrays_at
+            .get(&key_parts.join(&self.separator))
+            .copied()
+            .unwrap_or(self.compact_arrays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_flat_entries() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "db": { "host": "localhost" } }))
+            .with_tombstone("__UNSET__");
+        let vars = test_util::vars(&[
+            ("PREFIX__DB__PORT", "5432"),
+            ("PREFIX__DB__HOST", "__UNSET__"),
+            ("PREFIX__LIST__0", "a"),
+        ]);
+
+        let mut entries = parser.flat_entries(vars.into_iter())?;
+        assert_eq!(
+            entries.entries,
+            [
+                FlatEntry {
+                    var: "PREFIX__LIST__0".to_string(),
+                    segments: vec!["list".to_string(), "0".to_string()],
+                    value: Some(json!("a")),
+                },
+                FlatEntry {
+                    var: "PREFIX__DB__PORT".to_string(),
+                    segments: vec!["db".to_string(), "port".to_string()],
+                    value: Some(json!(5432)),
+                },
+                FlatEntry {
+                    var: "PREFIX__DB__HOST".to_string(),
+                    segments: vec!["db".to_string(), "host".to_string()],
+                    value: None,
+                },
+            ]
+        );
+
+        // Rewrite an entry and add one overriding it, before merging
+        entries.entries[0].value = Some(json!("b"));
+        entries.entries.push(FlatEntry {
+            var: "OVERRIDE".to_string(),
+            segments: vec!["db".to_string(), "port".to_string()],
+            value: Some(json!(6543)),
+        });
+        entries.dedup();
+        assert_eq!(entries.entries.len(), 3);
+        assert_eq!(
+            parser.parse_flat_entries(entries)?,
+            json!({ "db": { "port": 6543 }, "list": ["b"] })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_arrays() -> Result<(), Error> {
+        let vars = test_util::vars(&[
+            ("LIST__2", "c"),
+            ("LIST__0", "a"),
+            ("NESTED__4__TAGS__3", "x"),
+            ("HOSTS__1", "b"),
+        ]);
+
+        let parser = Parser::default()
+            .with_json(json!({ "base": [null] }))
+            .with_compact_arrays(true);
+        assert_eq!(
+            parser.parse_iter(vars.clone())?,
+            json!({
+                "base": [null],
+                "list": ["a", "c"],
+                "nested": [{ "tags": ["x"] }],
+                "hosts": ["b"]
+            })
+        );
+
+        let parser = Parser::default().with_compact_arrays_at("hosts", true);
+        assert_eq!(
+            parser.parse_iter(vars.into_iter())?,
+            json!({
+                "list": ["a", null, "c"],
+                "nested": [null, null, null, null, { "tags": [null, null, null, "x"] }],
+                "hosts": ["b"]
+            })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! Flattening of json documents into separator-joined keys, the inverse of parsing
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::{Error, Parser};
+
+/// Flatten a json value into `key -> value` pairs, joining object keys and array indices with the separator.
+///
+/// Strings are used as they are, other scalars are serialized as json and `null` becomes an empty
+/// string. `null` array items are skipped, as they are the placeholders parsing creates for missing indices.
+///
+/// ```
+/// use env_vars_to_json::flatten;
+/// use serde_json::json;
+///
+/// let flat = flatten(&json!({ "struct": { "int": 1, "list": ["a", null, "c"] } }), "__");
+///
+/// assert_eq!(flat["struct__int"], "1");
+/// assert_eq!(flat["struct__list__2"], "c");
+/// assert!(!flat.contains_key("struct__list__1"));
+/// ```
+pub fn flatten(value: &Value, separator: &str) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+    flatten_into(value, separator, String::new(), &mut vars);
+    vars
+}
+
+/// Flatten a json value into `key -> value` pairs, joining key parts with the separator
+fn flatten_into(value: &Value, separator: &str, key: String, vars: &mut BTreeMap<String, String>) {
+    let join = |part: &str| {
+        if key.is_empty() {
+            part.to_string()
+        } else {
+            format!("{key}{separator}{part}")
+        }
+    };
+
+    match value {
+        Value::Object(obj) => {
+            for (k, v) in obj {
+                flatten_into(v, separator, join(k), vars);
+            }
+        }
+        Value::Array(arr) => {
+            for (i, v) in arr.iter().enumerate() {
+                if !v.is_null() {
+                    flatten_into(v, separator, join(&i.to_string()), vars);
+                }
+            }
+        }
+        Value::Null => {
+            vars.insert(key, String::new());
+        }
+        Value::String(s) => {
+            vars.insert(key, s.clone());
+        }
+        Value::Bool(_) | Value::Number(_) => {
+            vars.insert(key, value.to_string());
+        }
+    }
+}
+
+/// Flatten a json value into `key -> value` pairs like [`flatten`], keeping the leaf values as json,
+/// e.g. `struct.bool_list.0 -> true` with separator `.`. Empty objects and arrays are kept as values
+pub fn flatten_values(value: &Value, separator: &str) -> BTreeMap<String, Value> {
+    let mut values = BTreeMap::new();
+    flatten_values_into(value, separator, String::new(), &mut values);
+    values
+}
+
+fn flatten_values_into(
+    value: &Value,
+    separator: &str,
+    key: String,
+    values: &mut BTreeMap<String, Value>,
+) {
+    let join = |part: &str| {
+        if key.is_empty() {
+            part.to_string()
+        } else {
+            format!("{key}{separator}{part}")
+        }
+    };
+
+    match value {
+        Value::Object(obj) if !obj.is_empty() => {
+            for (k, v) in obj {
+                flatten_values_into(v, separator, join(k), values);
+            }
+        }
+        Value::Array(arr) if !arr.is_empty() => {
+            for (i, v) in arr.iter().enumerate() {
+                if !v.is_null() {
+                    flatten_values_into(v, separator, join(&i.to_string()), values);
+                }
+            }
+        }
+        value => {
+            values.insert(key, value.clone());
+        }
+    }
+}
+
+impl Parser {
+    /// Parse iterator of String tuples into a flat map of dotted paths to values instead of a nested
+    /// document, e.g. `struct.bool_list.0 -> true`, for sinks which want flat keys
+    pub fn parse_to_flat_map(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<BTreeMap<String, Value>, Error> {
+        Ok(flatten_values(&self.parse_iter(vars)?, "."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_flatten_round_trip() -> Result<(), Error> {
+        let value = json!({
+            "int_list": [1, 2],
+            "struct": {
+                "float": 1.1,
+                "bool_list": [true, false],
+                "struct": { "string": "string" }
+            }
+        });
+
+        let vars = flatten(&value, "__");
+        assert_eq!(vars.len(), 6);
+        assert_eq!(vars["struct__struct__string"], "string");
+
+        let parsed = Parser::default().parse_iter(vars.into_iter())?;
+        assert_eq!(parsed, value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_to_flat_map() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "tags": {} }));
+        let vars = test_util::vars(&[
+            ("PREFIX__STRUCT__BOOL_LIST__0", "true"),
+            ("PREFIX__STRUCT__BOOL_LIST__2", "false"),
+            ("PREFIX__STRUCT__NAME", "app"),
+        ]);
+
+        assert_eq!(
+            parser.parse_to_flat_map(vars.into_iter())?,
+            BTreeMap::from([
+                ("struct.bool_list.0".to_string(), json!(true)),
+                ("struct.bool_list.2".to_string(), json!(false)),
+                ("struct.name".to_string(), json!("app")),
+                ("tags".to_string(), json!({})),
+            ])
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,573 @@
+//! Output formats for parsed json
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::{flatten::flatten, Error};
+
+/// A format the parsed json can be serialized into
+pub trait OutputFormat {
+    /// The name used to look up the format in a [`Formats`] registry, e.g. `json`
+    fn name(&self) -> &str;
+
+    /// Serialize the json value into a string
+    fn serialize(&self, value: &Value) -> Result<String, Error>;
+}
+
+/// Serialize into json
+#[derive(Debug, Default, Clone)]
+pub struct Json {
+    /// Whether to pretty-print the output
+    pub pretty: bool,
+}
+
+impl OutputFormat for Json {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        if self.pretty {
+            serde_json::to_string_pretty(value).map_err(Error::SerdeJson)
+        } else {
+            serde_json::to_string(value).map_err(Error::SerdeJson)
+        }
+    }
+}
+
+#[cfg(feature = "yaml")]
+/// Serialize into yaml
+/// Requires the `yaml` feature
+#[derive(Debug, Default, Clone)]
+pub struct Yaml;
+
+#[cfg(feature = "yaml")]
+impl OutputFormat for Yaml {
+    fn name(&self) -> &str {
+        "yaml"
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        serde_yaml::to_string(value).map_err(Error::SerdeYaml)
+    }
+}
+
+#[cfg(feature = "yaml")]
+/// Serialize into a Helm `values.yaml`, optionally nested under the key of a chart, e.g. the key of a
+/// subchart in the values of an umbrella chart
+/// Requires the `yaml` feature
+#[derive(Debug, Default, Clone)]
+pub struct HelmValues {
+    /// The key the values are nested under, `None` for the top level
+    pub chart: Option<String>,
+}
+
+#[cfg(feature = "yaml")]
+impl OutputFormat for HelmValues {
+    fn name(&self) -> &str {
+        "helm"
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        if !value.is_object() {
+            return Err("Helm values must be an object".into());
+        }
+
+        match &self.chart {
+            Some(chart) => Yaml.serialize(&serde_json::json!({ chart: value })),
+            None => Yaml.serialize(value),
+        }
+    }
+}
+
+#[cfg(feature = "toml")]
+/// Serialize into toml
+/// Requires the `toml` feature. The value must be an object and must not contain nulls.
+#[derive(Debug, Default, Clone)]
+pub struct Toml;
+
+#[cfg(feature = "toml")]
+impl OutputFormat for Toml {
+    fn name(&self) -> &str {
+        "toml"
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        toml::to_string(value).map_err(Error::Toml)
+    }
+}
+
+/// Serialize into dotenv `KEY=VALUE` lines
+#[derive(Debug, Clone)]
+pub struct Dotenv {
+    /// The prefix prepended to every key
+    pub prefix: Option<String>,
+
+    /// The separator used to join key parts
+    pub separator: String,
+}
+
+impl Default for Dotenv {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            separator: "__".to_string(),
+        }
+    }
+}
+
+impl OutputFormat for Dotenv {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        let vars = flatten(value, &self.separator);
+
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        let mut output = String::new();
+
+        for (key, value) in vars {
+            output.push_str(prefix);
+            output.push_str(&key.to_uppercase());
+            output.push('=');
+            output.push_str(&quote(&value));
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+}
+
+/// Double-quote a dotenv value if it contains characters that need escaping
+fn quote(value: &str) -> String {
+    let needs_quotes = value
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '#' | '$' | '`' | '='));
+
+    if !needs_quotes {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '$' => quoted.push_str("\\$"),
+            '`' => quoted.push_str("\\`"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Serialize into a docker compose `env_file`, whose dialect differs from dotenv: values are
+/// unquoted unless they need to be, keeping spaces as they are, and `$` is escaped as `$$`
+/// against interpolation
+#[derive(Debug, Clone)]
+pub struct ComposeEnv {
+    /// The prefix prepended to every key
+    pub prefix: Option<String>,
+
+    /// The separator used to join key parts
+    pub separator: String,
+
+    /// Comments written as `#` lines above variables, keyed by the key parts joined by the separator,
+    /// e.g. where the value came from, see [`crate::Parser::parse_to_compose_env`]
+    pub comments: BTreeMap<String, String>,
+}
+
+impl Default for ComposeEnv {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            separator: "__".to_string(),
+            comments: BTreeMap::new(),
+        }
+    }
+}
+
+impl OutputFormat for ComposeEnv {
+    fn name(&self) -> &str {
+        "compose"
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        let vars = flatten(value, &self.separator);
+
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        let mut output = String::new();
+
+        for (key, value) in vars {
+            if let Some(comment) = self.comments.get(&key) {
+                for line in comment.lines() {
+                    output.push_str("# ");
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+
+            output.push_str(prefix);
+            output.push_str(&key.to_uppercase());
+            output.push('=');
+            output.push_str(&compose_quote(&value));
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+}
+
+/// Quote a compose env file value only if compose would change it unquoted: unquoted values are
+/// trimmed, cut at ` #` comments and interpolated. Single quotes keep the value literal, double
+/// quotes are used if it also contains a single quote or a line break
+fn compose_quote(value: &str) -> String {
+    let is_literal = !value.contains(['$', '\n', '\r'])
+        && value.trim() == value
+        && !value.starts_with(['\'', '"'])
+        && !value.contains(" #")
+        && !value.contains("\t#");
+    if is_literal {
+        return value.to_string();
+    }
+
+    if !value.contains(['\'', '\n', '\r']) {
+        return format!("'{value}'");
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '$' => quoted.push_str("$$"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// The shell a script of exports is written for, see [`ShellExports`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shell {
+    /// `export KEY='value'`, for sh, bash, zsh and other POSIX shells
+    #[default]
+    Posix,
+
+    /// `set -gx KEY 'value'`
+    Fish,
+
+    /// `$env:KEY = 'value'`
+    PowerShell,
+}
+
+impl Shell {
+    /// Return the statement exporting the variable, with the value single-quoted so the shell
+    /// expands nothing inside it
+    fn export(self, key: &str, value: &str) -> String {
+        match self {
+            Self::Posix => format!("export {key}='{}'", value.replace('\'', r"'\''")),
+            Self::Fish => format!(
+                "set -gx {key} '{}'",
+                value.replace('\\', r"\\").replace('\'', r"\'")
+            ),
+            // PowerShell also closes single-quoted strings at typographic single quotes
+            Self::PowerShell => format!(
+                "$env:{key} = '{}'",
+                value
+                    .chars()
+                    .flat_map(|c| match c {
+                        '\'' | '\u{2018}'..='\u{201B}' => vec![c, c],
+                        c => vec![c],
+                    })
+                    .collect::<String>()
+            ),
+        }
+    }
+}
+
+/// Serialize into a script exporting `KEY=VALUE` variables into the environment of a shell
+#[derive(Debug, Clone)]
+pub struct ShellExports {
+    /// The shell the script is written for
+    pub shell: Shell,
+
+    /// The prefix prepended to every key
+    pub prefix: Option<String>,
+
+    /// The separator used to join key parts
+    pub separator: String,
+}
+
+impl Default for ShellExports {
+    fn default() -> Self {
+        Self {
+            shell: Shell::Posix,
+            prefix: None,
+            separator: "__".to_string(),
+        }
+    }
+}
+
+impl OutputFormat for ShellExports {
+    fn name(&self) -> &str {
+        match self.shell {
+            Shell::Posix => "sh",
+            Shell::Fish => "fish",
+            Shell::PowerShell => "ps1",
+        }
+    }
+
+    fn serialize(&self, value: &Value) -> Result<String, Error> {
+        let vars = flatten(value, &self.separator);
+
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        let mut output = String::new();
+
+        for (key, value) in vars {
+            let key = format!("{prefix}{}", key.to_uppercase());
+            if !is_shell_name(&key) {
+                return Err(format!(
+                    "`{key}` is not a valid shell variable name, expected letters, digits and `_`"
+                )
+                .into());
+            }
+
+            output.push_str(&self.shell.export(&key, &value));
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+}
+
+/// Serialize a json value into a script exporting its values as variables with the prefix, e.g.
+/// `export PREFIX__DB__PORT='5432'`, with key parts joined by `__`. See [`ShellExports`] for
+/// another separator
+pub fn to_shell_exports(value: &Value, prefix: &str, shell: Shell) -> Result<String, Error> {
+    ShellExports {
+        shell,
+        prefix: Some(prefix.to_string()),
+        ..Default::default()
+    }
+    .serialize(value)
+}
+
+/// Whether the name can be assigned by every shell: ASCII letters, digits and `_`, not starting
+/// with a digit
+fn is_shell_name(name: &str) -> bool {
+    !name.starts_with(|c: char| c.is_ascii_digit())
+        && !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A registry of output formats, looked up by name
+pub struct Formats {
+    formats: BTreeMap<String, Box<dyn OutputFormat + Send + Sync>>,
+}
+
+impl Default for Formats {
+    /// Return a registry containing all built-in formats
+    fn default() -> Self {
+        let formats = Self::empty()
+            .with(Json { pretty: true })
+            .with(Dotenv::default())
+            .with(ComposeEnv::default());
+
+        #[cfg(feature = "yaml")]
+        let formats = formats.with(Yaml).with(HelmValues::default());
+
+        #[cfg(feature = "toml")]
+        let formats = formats.with(Toml);
+
+        formats
+    }
+}
+
+impl std::fmt::Debug for Formats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.formats.keys()).finish()
+    }
+}
+
+impl Formats {
+    /// Return a registry without any formats
+    pub fn empty() -> Self {
+        Self {
+            formats: BTreeMap::new(),
+        }
+    }
+
+    /// Return the registry with the given format added, replacing any format with the same name
+    pub fn with(mut self, format: impl OutputFormat + Send + Sync + 'static) -> Self {
+        self.register(format);
+        self
+    }
+
+    /// Add a format, replacing any format with the same name
+    pub fn register(&mut self, format: impl OutputFormat + Send + Sync + 'static) {
+        self.formats
+            .insert(format.name().to_string(), Box::new(format));
+    }
+
+    /// Get a format by name
+    pub fn get(&self, name: &str) -> Option<&(dyn OutputFormat + Send + Sync)> {
+        self.formats.get(name).map(|format| format.as_ref())
+    }
+
+    /// Names of all registered formats
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.formats.keys().map(String::as_str)
+    }
+
+    /// Serialize the value with the format registered under the given name
+    pub fn serialize(&self, name: &str, value: &Value) -> Result<String, Error> {
+        self.get(name)
+            .ok_or_else(|| format!("unknown output format: {name}"))?
+            .serialize(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use serde_json::json;
+
+    use super::*;
+
+    struct Upper;
+
+    impl OutputFormat for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn serialize(&self, value: &Value) -> Result<String, Error> {
+            Ok(value.to_string().to_uppercase())
+        }
+    }
+
+    #[test]
+    fn test_dotenv() -> Result<(), Error> {
+        let value = json!({
+            "int_list": [1, null, 3],
+            "struct": {
+                "string": "hello world",
+                "bool": true
+            }
+        });
+        let format = Dotenv {
+            prefix: Some("PREFIX__".to_string()),
+            separator: "__".to_string(),
+        };
+
+        assert_eq!(
+            format.serialize(&value)?,
+            "PREFIX__INT_LIST__0=1\n\
+             PREFIX__INT_LIST__2=3\n\
+             PREFIX__STRUCT__BOOL=true\n\
+             PREFIX__STRUCT__STRING=\"hello world\"\n"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_helm_values() -> Result<(), Error> {
+        let value = json!({ "image": { "tag": "1.2.3" } });
+        let format = HelmValues {
+            chart: Some("api".to_string()),
+        };
+
+        assert_eq!(
+            format.serialize(&value)?,
+            "api:\n  image:\n    tag: 1.2.3\n"
+        );
+        assert_eq!(
+            HelmValues::default().serialize(&value)?,
+            "image:\n  tag: 1.2.3\n"
+        );
+        assert!(format.serialize(&json!([1])).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compose_env() -> Result<(), Error> {
+        let value = json!({
+            "greeting": "hello world",
+            "motd": "it's $HOME",
+            "password": "pa$$word",
+            "padded": " x ",
+            "note": "a # b",
+            "multiline": "a\nb"
+        });
+        let format = ComposeEnv {
+            comments: BTreeMap::from([("greeting".to_string(), "from GREETING".to_string())]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format.serialize(&value)?,
+            "# from GREETING\n\
+             GREETING=hello world\n\
+             MOTD=\"it's $$HOME\"\n\
+             MULTILINE=\"a\\nb\"\n\
+             NOTE='a # b'\n\
+             PADDED=' x '\n\
+             PASSWORD='pa$$word'\n"
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(
+        Shell::Posix,
+        "export PREFIX__MOTD='it'\\''s $HOME'\nexport PREFIX__PORTS__0='80'\n"
+    )]
+    #[case(
+        Shell::Fish,
+        "set -gx PREFIX__MOTD 'it\\'s $HOME'\nset -gx PREFIX__PORTS__0 '80'\n"
+    )]
+    #[case(
+        Shell::PowerShell,
+        "$env:PREFIX__MOTD = 'it''s $HOME'\n$env:PREFIX__PORTS__0 = '80'\n"
+    )]
+    fn test_shell_exports(#[case] shell: Shell, #[case] expected: &str) -> Result<(), Error> {
+        let value = json!({ "motd": "it's $HOME", "ports": [80] });
+
+        assert_eq!(to_shell_exports(&value, "PREFIX__", shell)?, expected);
+        assert!(to_shell_exports(&json!({ "a-b": 1 }), "PREFIX__", shell).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_registry() -> Result<(), Error> {
+        let formats = Formats::default().with(Upper);
+        let value = json!({ "key": "value" });
+
+        assert_eq!(formats.serialize("upper", &value)?, r#"{"KEY":"VALUE"}"#);
+        assert_eq!(formats.serialize("env", &value)?, "KEY=value\n");
+        assert!(formats.serialize("unknown", &value).is_err());
+
+        Ok(())
+    }
+}
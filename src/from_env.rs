@@ -0,0 +1,167 @@
+//! Typed construction of structs from environment variables, see [`FromEnvVars`]
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{Error, JsonIndex, Parser};
+
+/// The placeholder secret values are replaced with by [`FromEnvVars::redact`]
+pub const REDACTED: &str = "***";
+
+/// Construct a struct from environment variables.
+///
+/// Usually derived with `#[derive(FromEnvVars)]`, which requires the `derive` feature:
+///
+/// ```
+/// # #[cfg(feature = "derive")] {
+/// use env_vars_to_json::{
+///     source::{EnvSource, FakeEnv},
+///     FromEnvVars,
+/// };
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Deserialize, FromEnvVars)]
+/// #[env(prefix = "APP__")]
+/// struct Config {
+///     #[env(rename = "db")]
+///     database: Database,
+///     #[env(secret)]
+///     token: String,
+/// }
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Database {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// let env = FakeEnv::new()
+///     .with("APP__DB__HOST", "localhost")
+///     .with("APP__DB__PORT", "5432")
+///     .with("APP__TOKEN", "hunter2");
+/// let config = Config::from_vars(env.vars()?.into_iter())?;
+///
+/// assert_eq!(config.database.port, 5432);
+/// assert_eq!(Config::SECRETS, ["token"]);
+/// # }
+/// # Ok::<(), env_vars_to_json::Error>(())
+/// ```
+pub trait FromEnvVars: DeserializeOwned {
+    /// Field renames as `(key part, field name)` pairs, e.g. `("db", "database")` reads the
+    /// `database` field from `PREFIX__DB__*` variables
+    const RENAMES: &'static [(&'static str, &'static str)] = &[];
+
+    /// Names of the fields holding secrets
+    const SECRETS: &'static [&'static str] = &[];
+
+    /// Return the parser reading the variables
+    fn parser() -> Parser;
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Construct from the environment variables of the current process
+    fn from_env() -> Result<Self, Error> {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Construct from an iterator of String tuples
+    fn from_vars(vars: impl Iterator<Item = (String, String)>) -> Result<Self, Error> {
+        let (mut json, mut provenance) = Self::parser().parse_with_provenance(vars)?;
+
+        if let Some(obj) = json.as_object_mut() {
+            for (from, to) in Self::RENAMES {
+                if let Some(value) = obj.remove(*from) {
+                    obj.insert(to.to_string(), value);
+                }
+            }
+        }
+
+        provenance = provenance
+            .into_iter()
+            .map(|(mut path, var)| {
+                if let Some(JsonIndex::String(key)) = path.first_mut() {
+                    if let Some((_, to)) = Self::RENAMES.iter().find(|(from, _)| from == key) {
+                        *key = to.to_string();
+                    }
+                }
+                (path, var)
+            })
+            .collect();
+
+        Parser::deserialize(json, &provenance)
+    }
+
+    /// Replace the values of secret fields in json, e.g. before logging it
+    fn redact(json: &mut Value) {
+        if let Some(obj) = json.as_object_mut() {
+            for secret in Self::SECRETS {
+                if let Some(value) = obj.get_mut(*secret) {
+                    *value = Value::String(REDACTED.to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        database: Database,
+        token: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Database {
+        port: u16,
+    }
+
+    impl FromEnvVars for Config {
+        const RENAMES: &'static [(&'static str, &'static str)] = &[("db", "database")];
+        const SECRETS: &'static [&'static str] = &["token"];
+
+        fn parser() -> Parser {
+            Parser::default().with_prefix("APP__")
+        }
+    }
+
+    fn vars(port: &str) -> impl Iterator<Item = (String, String)> {
+        test_util::vars(&[("APP__DB__PORT", port), ("APP__TOKEN", "hunter2")])
+    }
+
+    #[test]
+    fn test_from_vars() -> Result<(), Error> {
+        assert_eq!(
+            Config::from_vars(vars("5432"))?,
+            Config {
+                database: Database { port: 5432 },
+                token: "hunter2".to_string(),
+            }
+        );
+
+        let err = Config::from_vars(vars("invalid")).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Deserialize { path, var: Some(var), .. }
+                if path == "database.port" && var == "APP__DB__PORT"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut json = json!({ "database": { "port": 5432 }, "token": "hunter2" });
+        Config::redact(&mut json);
+
+        assert_eq!(
+            json,
+            json!({ "database": { "port": 5432 }, "token": "***" })
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Configuration merged from several layers with a fixed precedence
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use serde_json::Value;
+
+use crate::{
+    merge::merge,
+    source::{DotenvFile, EnvSource},
+    Error, JsonIndex, JsonPath, Parser,
+};
+
+/// Where a value of a layered document came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// A json value given in code
+    Json,
+
+    /// A json file
+    File(PathBuf),
+
+    /// A variable of a `.env` file
+    Dotenv { path: PathBuf, var: String },
+
+    /// A variable of the process environment
+    Env { var: String },
+}
+
+#[derive(Debug, Clone)]
+enum Layer {
+    Json(Value),
+    File(PathBuf),
+    Dotenv(PathBuf),
+    Env(String),
+}
+
+/// Layers of configuration merged into one json document.
+///
+/// Every layer overrides the layers added before it: objects are merged recursively and any other
+/// value replaces the value below it, so add defaults first and the environment last:
+///
+/// ```no_run
+/// use env_vars_to_json::Layers;
+///
+/// let json = Layers::new()
+///     .file("defaults.json")
+///     .dotenv(".env")
+///     .env("APP__")
+///     .finish()?;
+/// # Ok::<(), env_vars_to_json::Error>(())
+/// ```
+///
+/// Variables of `.env` files are parsed with the parser of the layers, and variables of the
+/// environment with that parser and the prefix of the layer.
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    parser: Parser,
+    layers: Vec<Layer>,
+}
+
+impl Layers {
+    /// Return layers without any layer, parsing variables with the default parser
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the layers parsing variables with the given parser, e.g. to set the separator
+    /// or the prefix of `.env` files. The json of the parser is not used, add it as a layer instead
+    pub fn with_parser(mut self, parser: Parser) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Add a json value
+    pub fn json(mut self, json: Value) -> Self {
+        self.layers.push(Layer::Json(json));
+        self
+    }
+
+    /// Add a json file, which must exist.
+    /// With the `json5` feature, files with the `.json5` extension are parsed as JSON5
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layers.push(Layer::File(path.into()));
+        self
+    }
+
+    /// Add the variables of a `.env` file, which must exist
+    pub fn dotenv(mut self, path: impl Into<PathBuf>) -> Self {
+        self.layers.push(Layer::Dotenv(path.into()));
+        self
+    }
+
+    /// Add the variables of the process environment starting with the prefix
+    pub fn env(mut self, prefix: impl Into<String>) -> Self {
+        self.layers.push(Layer::Env(prefix.into()));
+        self
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Merge the layers into one json document
+    pub fn finish(&self) -> Result<Value, Error> {
+        self.finish_with(&crate::source::ProcessEnv)
+            .map(|(json, _)| json)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Merge the layers into one json document and return the origin of each of its values
+    pub fn finish_with_origins(&self) -> Result<(Value, BTreeMap<JsonPath, Origin>), Error> {
+        self.finish_with(&crate::source::ProcessEnv)
+    }
+
+    /// Merge the layers into one json document and return the origin of each of its values,
+    /// reading the environment layers from the given source instead of the process environment
+    pub fn finish_with(
+        &self,
+        env: &impl EnvSource,
+    ) -> Result<(Value, BTreeMap<JsonPath, Origin>), Error> {
+        let mut json = Value::Object(Default::default());
+        let mut origins = BTreeMap::new();
+
+        // Variable layers only hold their own variables, the base json would override lower layers
+        let parser = self
+            .parser
+            .clone()
+            .with_json(Value::Object(Default::default()));
+
+        for layer in &self.layers {
+            let layer_json = match layer {
+                Layer::Json(value) => {
+                    leaves(value, &mut JsonPath::new(), &mut |path| {
+                        origins.insert(path, Origin::Json);
+                    });
+                    value.clone()
+                }
+                Layer::File(path) => {
+                    let text = fs::read_to_string(path).map_err(Error::Io)?;
+                    let value = parse_file(path, &text)?;
+                    leaves(&value, &mut JsonPath::new(), &mut |json_path| {
+                        origins.insert(json_path, Origin::File(path.clone()));
+                    });
+                    value
+                }
+                Layer::Dotenv(path) => {
+                    let vars = DotenvFile::new(path).vars()?;
+                    let (value, provenance) = parser.parse_with_provenance(vars.into_iter())?;
+                    origins.extend(provenance.into_iter().map(|(json_path, var)| {
+                        let path = path.clone();
+                        (json_path, Origin::Dotenv { path, var })
+                    }));
+                    value
+                }
+                Layer::Env(prefix) => {
+                    let parser = parser.clone().with_prefix(prefix);
+                    let (value, provenance) =
+                        parser.parse_with_provenance(env.vars()?.into_iter())?;
+                    origins.extend(
+                        provenance
+                            .into_iter()
+                            .map(|(json_path, var)| (json_path, Origin::Env { var })),
+                    );
+                    value
+                }
+            };
+
+            merge(&mut json, layer_json);
+        }
+
+        // Values of lower layers may have been replaced by a container or a value above it
+        origins.retain(|path, _| {
+            crate::path::get(&json, path)
+                .is_some_and(|value| !value.is_object() && !value.is_array())
+        });
+
+        Ok((json, origins))
+    }
+}
+
+/// Parse the text of a json file, or of a JSON5 file with the `json5` feature
+#[cfg_attr(not(feature = "json5"), allow(unused_variables))]
+fn parse_file(path: &std::path::Path, text: &str) -> Result<Value, Error> {
+    #[cfg(feature = "json5")]
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json5")
+    {
+        return json5::from_str(text).map_err(Error::Json5);
+    }
+
+    serde_json::from_str(text).map_err(Error::SerdeJson)
+}
+
+/// Call `f` with the path of every value which is neither an object nor an array,
+/// skipping `null` array items which don't override the layers below
+fn leaves(value: &Value, path: &mut JsonPath, f: &mut impl FnMut(JsonPath)) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                path.push(JsonIndex::String(key.clone()));
+                leaves(value, path, f);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                if !value.is_null() {
+                    path.push(JsonIndex::Usize(i));
+                    leaves(value, path, f);
+                    path.pop();
+                }
+            }
+        }
+        _ => f(path.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::FakeEnv;
+
+    #[test]
+    fn test_layers() -> Result<(), Error> {
+        let dir =
+            std::env::temp_dir().join(format!("env-vars-to-json-layers-{}", std::process::id()));
+        fs::create_dir_all(&dir).map_err(Error::Io)?;
+        fs::write(
+            dir.join("defaults.json"),
+            r#"{ "db": { "host": "localhost", "port": 5432 }, "debug": false }"#,
+        )
+        .map_err(Error::Io)?;
+        fs::write(dir.join(".env"), "DB__PORT=5433\nDEBUG=true\n").map_err(Error::Io)?;
+
+        let env = FakeEnv::new()
+            .with("APP__DB__PORT", "6543")
+            .with("OTHER__DEBUG", "false");
+        let layers = Layers::new()
+            .json(json!({ "name": "app" }))
+            .file(dir.join("defaults.json"))
+            .dotenv(dir.join(".env"))
+            .env("APP__");
+        let result = layers.finish_with(&env);
+        fs::remove_dir_all(&dir).map_err(Error::Io)?;
+        let (json, origins) = result?;
+
+        assert_eq!(
+            json,
+            json!({ "name": "app", "db": { "host": "localhost", "port": 6543 }, "debug": true })
+        );
+        assert_eq!(
+            origins,
+            BTreeMap::from([
+                (JsonIndex::from_vec(vec!["name"]).into(), Origin::Json),
+                (
+                    JsonIndex::from_vec(vec!["db", "host"]).into(),
+                    Origin::File(dir.join("defaults.json"))
+                ),
+                (
+                    JsonIndex::from_vec(vec!["db", "port"]).into(),
+                    Origin::Env {
+                        var: "APP__DB__PORT".to_string()
+                    }
+                ),
+                (
+                    JsonIndex::from_vec(vec!["debug"]).into(),
+                    Origin::Dotenv {
+                        path: dir.join(".env"),
+                        var: "DEBUG".to_string()
+                    }
+                ),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_layers_ignore_parser_json() -> Result<(), Error> {
+        let env = FakeEnv::new().with("APP__DEBUG", "true");
+        let layers = Layers::new()
+            .with_parser(Parser::default().with_json(json!({ "db": { "port": 1 } })))
+            .json(json!({ "db": { "port": 5432 } }))
+            .env("APP__");
+        let (json, origins) = layers.finish_with(&env)?;
+
+        assert_eq!(json, json!({ "db": { "port": 5432 }, "debug": true }));
+        assert_eq!(
+            origins.get(&JsonIndex::from_vec(vec!["db", "port"]).into()),
+            Some(&Origin::Json)
+        );
+
+        Ok(())
+    }
+}
@@ -1,21 +1,192 @@
-#![cfg_attr(docsrs, feature(doc_auto_cfg))]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 #![doc = include_str!("../README.md")]
 
-use core::panic;
-use std::env;
+// Lets the derive macro refer to `::env_vars_to_json` from within this crate
+extern crate self as env_vars_to_json;
 
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+#[cfg(feature = "base64")]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 #[cfg(feature = "filter")]
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde_json::{json, Number, Value};
 use thiserror::Error;
+#[cfg(feature = "unicode")]
+use unicode_normalization::{is_nfc, is_nfkc, UnicodeNormalization};
+
+/// Emit a debug event with the `tracing` feature, do nothing otherwise
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+#[cfg(feature = "clap")]
+pub mod args;
+pub mod builder;
+pub mod coerce;
+#[cfg(feature = "filter")]
+pub mod filter;
+pub mod flat;
+pub mod flatten;
+pub mod format;
+pub mod from_env;
+pub mod layers;
+pub mod merge;
+pub mod patch;
+pub mod path;
+pub mod report;
+pub mod segment;
+pub mod source;
+pub mod spec;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod testing;
+pub mod tree;
+pub mod validate;
+pub mod value;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+pub use builder::ParserBuilder;
+pub use coerce::ValueParser;
+#[cfg(feature = "derive")]
+pub use env_vars_to_json_derive::FromEnvVars;
+#[cfg(feature = "filter")]
+pub use filter::FilterSet;
+pub use flat::{FlatEntries, FlatEntry};
+pub use flatten::{flatten, flatten_values};
+use format::OutputFormat;
+pub use from_env::FromEnvVars;
+pub use layers::Layers;
+pub use path::{CreatePolicy, JsonIndex, JsonPath};
+pub use report::ParseReport;
+use segment::{BracketSegmenter, KeySegmenter, SeparatorSegmenter};
+use source::{AsyncEnvSource, EnvSource};
+#[allow(deprecated)]
+pub use tree::{ArrayItem, PartValue};
+pub use tree::{Entry, Scalar};
+pub use validate::{Rule, ValidationError};
+pub use value::ValueOps;
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("serde_json error: {0}")]
     SerdeJson(serde_json::Error),
 
+    #[cfg(feature = "yaml")]
+    #[error("serde_yaml error: {0}")]
+    SerdeYaml(serde_yaml::Error),
+
+    #[cfg(feature = "toml")]
+    #[error("toml error: {0}")]
+    Toml(toml::ser::Error),
+
+    #[cfg(feature = "json5")]
+    #[error("json5 error: {0}")]
+    Json5(json5::Error),
+
+    #[cfg(feature = "cbor")]
+    #[error("cbor error: {0}")]
+    Cbor(String),
+
+    #[cfg(feature = "msgpack")]
+    #[error("msgpack error: {0}")]
+    Msgpack(rmp_serde::encode::Error),
+
     #[error("Encountered error while parsing environment variables: {0}")]
     Internal(String),
+
+    #[error(
+        "failed to deserialize `{path}`{}: {source}",
+        var.as_ref().map(|var| format!(" (from environment variable `{var}`)")).unwrap_or_default()
+    )]
+    Deserialize {
+        /// Path of the value that failed to deserialize, e.g. `struct.int_list[1]`
+        path: String,
+        /// The environment variable the value came from, if any
+        var: Option<String>,
+        source: serde_json::Error,
+    },
+
+    #[error("invalid parser configuration: {0}")]
+    Config(String),
+
+    #[cfg(feature = "filter")]
+    #[error("invalid regex: {0}")]
+    Regex(regex::Error),
+
+    #[error("io error: {0}")]
+    Io(std::io::Error),
+
+    #[error("syntax error on line {line}: {message}")]
+    Syntax { line: usize, message: String },
+
+    #[error("conflicting variables: found {found} at `{path}`")]
+    Conflict {
+        /// Path of the conflicting value, e.g. `struct.int_list`
+        path: JsonPath,
+        /// What was found at the path, e.g. `an object`
+        found: &'static str,
+    },
+
+    #[error(
+        "missing array items before `{path}`{}: the array can't hold them, e.g. a TOML array, which has no null",
+        var.as_ref().map(|var| format!(" (from environment variable `{var}`)")).unwrap_or_default()
+    )]
+    MissingItems {
+        /// Path of the first value after the missing items, e.g. `list[1]`
+        path: JsonPath,
+        /// The environment variable of the value, if any
+        var: Option<String>,
+    },
+
+    #[error(
+        "environment variable `{var}` has {depth} key parts, more than the maximum of {max_depth}"
+    )]
+    TooDeep {
+        var: String,
+        depth: usize,
+        max_depth: usize,
+    },
+
+    #[error("environment variable `{var}` has an empty key part at index {index}, from {cause}")]
+    EmptyKeyPart {
+        var: String,
+        /// Position of the empty key part, e.g. `1` for `PREFIX__FOO____BAR`
+        index: usize,
+        /// What produced the empty key part, e.g. `consecutive separators`
+        cause: &'static str,
+    },
+
+    #[error(
+        "no environment variables selected{}",
+        prefix.as_ref().map(|prefix| format!(" with prefix `{prefix}`")).unwrap_or_default()
+    )]
+    NoMatch {
+        /// The prefix of the parser, if any
+        prefix: Option<String>,
+    },
+
+    #[error("unknown environment variables: {}", .0.join(", "))]
+    UnknownVars(Vec<String>),
+
+    #[error("invalid values: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Validation(Vec<ValidationError>),
+
+    #[error("source error: {0}")]
+    Source(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("failed to parse variables with prefix `{prefix}`: {source}")]
+    Prefix { prefix: String, source: Box<Error> },
 }
 
 impl From<&str> for Error {
@@ -30,336 +201,1914 @@ impl From<String> for Error {
     }
 }
 
+/// What an empty value like `FOO=` becomes, see [`Parser::with_empty_values`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyValues {
+    /// An empty string
+    #[default]
+    String,
+
+    /// `null`
+    Null,
+
+    /// Nothing: the variable is skipped, keeping the value of the base json
+    Skip,
+
+    /// Nothing: the value at the path is removed from the base json.
+    /// Array items after a removed item shift down
+    Erase,
+}
+
+/// What empty key parts, from leading, trailing or consecutive separators like `FOO____BAR` or
+/// `FOO__`, become, see [`Parser::with_empty_key_parts`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmptyKeyParts {
+    /// Empty object keys, e.g. `FOO____BAR` becomes `{"foo": {"": {"bar": ...}}}`
+    #[default]
+    Keep,
+
+    /// Nothing: empty key parts are dropped, e.g. `FOO____BAR` becomes `{"foo": {"bar": ...}}`
+    Collapse,
+
+    /// An [`Error::EmptyKeyPart`] naming the variable and the position of the empty key part
+    Error,
+}
+
+#[cfg(feature = "unicode")]
+/// The Unicode normalization form of keys, see [`Parser::with_key_normalization`]
+/// Requires the `unicode` feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyNormalization {
+    /// Canonical composition, e.g. `e` followed by a combining acute accent becomes `é`
+    Nfc,
+
+    /// Compatibility composition, which also folds compatibility characters,
+    /// e.g. the full-width `Ｐ` becomes `P`
+    Nfkc,
+}
+
+/// Why a variable was skipped, see [`Parser::skipped`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The key does not start with the prefix
+    Prefix,
+
+    /// The key matches none of the include patterns
+    Include,
+
+    /// The key matches one of the exclude patterns
+    Exclude,
+
+    /// The key belongs to a profile which is not active
+    Profile,
+
+    /// The variable is rejected by a filter predicate
+    Filter,
+
+    /// The value is empty and empty values are skipped
+    Empty,
+
+    /// The value matches none of the value include patterns or one of the value exclude patterns
+    Value,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prefix => f.write_str("key does not start with the prefix"),
+            Self::Include => f.write_str("key matches no include pattern"),
+            Self::Exclude => f.write_str("key matches an exclude pattern"),
+            Self::Profile => f.write_str("key belongs to an inactive profile"),
+            Self::Filter => f.write_str("variable rejected by a filter predicate"),
+            Self::Empty => f.write_str("value is empty"),
+            Self::Value => f.write_str("value is rejected by the value patterns"),
+        }
+    }
+}
+
 /// Parse environment variables into json
-#[derive(Debug)]
+///
+/// Parsing takes `&self`, and a parser is `Send + Sync`, so one parser can be shared between
+/// threads, e.g. behind an `Arc`, without a lock. Cloning shares the compiled key patterns
+#[derive(Debug, Clone)]
 pub struct Parser {
     /// The prefix to use when parsing environment variables
-    pub prefix: Option<String>,
+    prefix: Option<String>,
 
     /// The separator to use when parsing environment variables
-    pub separator: String,
+    separator: String,
+
+    #[cfg(feature = "filter")]
+    /// Include/exclude patterns for keys, applied to variables with the prefix
+    key_filters: FilterSet,
+
+    #[cfg(feature = "filter")]
+    /// Whether include/exclude patterns are matched against keys without the prefix
+    filters_without_prefix: bool,
 
     #[cfg(feature = "filter")]
-    /// List of regex patterns to include.
+    /// List of regex patterns matched against values.
     /// One of the patterns must match for the variable to be included
-    pub include: Vec<Regex>,
+    value_include: Vec<Regex>,
 
     #[cfg(feature = "filter")]
-    /// List of regex patterns to exclude.
+    /// List of regex patterns matched against values.
     /// All of the patterns must not match for the variable to be included
-    pub exclude: Vec<Regex>,
+    value_exclude: Vec<Regex>,
+
+    /// What empty values like `FOO=` become
+    empty_values: EmptyValues,
+
+    /// The value removing the path of its variable from the base json instead of setting it, e.g. `__UNSET__`
+    tombstone: Option<String>,
 
     /// The json object to merge the parsed environment variables into
-    pub json: Value,
+    json: Value,
+
+    /// Whether to coerce hexadecimal (`0x1F`), octal (`0o755`) and binary (`0b1010`) literals into integers
+    radix_literals: bool,
+
+    /// Whether integers that don't fit into `i64`/`u64` are kept as strings instead of being parsed as floats
+    big_ints_as_strings: bool,
+
+    /// Whether only canonical decimal numbers are coerced, keeping strings like `1.10`, `1e5` or `08` as they are
+    strict_numbers: bool,
+
+    /// Whether integers with leading zeros like `007` are kept as strings
+    keep_leading_zeros: bool,
+
+    /// Per-path overrides of `keep_leading_zeros`, keyed by the key parts joined by the separator, e.g. `struct__zip`
+    keep_leading_zeros_at: BTreeMap<String, bool>,
+
+    /// Whether to trim surrounding whitespace from values before coercion
+    trim_values: bool,
+
+    /// Whether to strip a single trailing newline (`\n` or `\r\n`) from values before coercion
+    trim_trailing_newline: bool,
+
+    /// Whether variables are merged in the order they are given instead of in reverse order of their keys.
+    /// With the `preserve_order` feature this is the order new object keys appear in
+    declaration_order: bool,
+
+    /// Whether object keys of the result are sorted recursively, regardless of insertion order
+    sort_keys: bool,
+
+    /// The maximum number of key parts a variable may split into, unlimited if `None`
+    max_depth: Option<usize>,
+
+    /// Synthetic variables, keyed by their full name including the prefix, which are added to the
+    /// variables being parsed and replace variables with the same name
+    overrides: BTreeMap<String, String>,
+
+    /// Whether parsing fails with [`Error::NoMatch`] when no variable is selected, e.g. because none
+    /// has the prefix, instead of returning the base json unchanged
+    require_match: bool,
+
+    /// What empty key parts from leading, trailing or consecutive separators become
+    empty_key_parts: EmptyKeyParts,
+
+    /// Splits keys after the prefix into key parts, `None` for the built-in segmenters: at every
+    /// separator, or with [`BracketSegmenter`] if `bracket_indices` is enabled
+    key_segmenter: Option<Arc<dyn KeySegmenter>>,
+
+    /// Whether only bracketed key parts like `[0]` are array indices, while numeric key parts are
+    /// object keys, see [`Parser::with_bracket_indices`]
+    bracket_indices: bool,
+
+    /// The chain of value parsers coercing values, tried in order, see [`ValueParser`].
+    /// Values no parser accepts become strings
+    value_parsers: Vec<Arc<dyn ValueParser>>,
+
+    /// Whether `null` items are removed from arrays written by variables, e.g. the placeholders
+    /// before `LIST__2` when `LIST__0` and `LIST__1` are missing
+    compact_arrays: bool,
+
+    /// Per-path overrides of `compact_arrays`, keyed by the path of the array, e.g. `hosts`
+    compact_arrays_at: BTreeMap<String, bool>,
+
+    /// Whether key parts like `-1` are end-relative array indices, counting the items appended after
+    /// the array in the document merged into: `-1` is the first appended item, `-2` the second
+    negative_indices: bool,
+
+    /// Whether key parts are lowercased. If not, keys pass through verbatim and the paths of other
+    /// options must match their case
+    lowercase_keys: bool,
+
+    /// Whether numeric key parts become object keys (`{"8080": ...}`) instead of array indices
+    numeric_object_keys: bool,
+
+    /// Per-path overrides of `numeric_object_keys`, keyed by the path of the container whose numeric
+    /// key parts are affected, e.g. `listeners` for `LISTENERS__8080__TLS`
+    numeric_object_keys_at: BTreeMap<String, bool>,
+
+    /// Paths of secret values, keyed by the key parts joined by the separator, e.g. `db__password`.
+    /// Secret values and the values below them are redacted from diagnostics
+    secrets: BTreeSet<String>,
+
+    /// Prefixes of variables, after the prefix, and the subtree their variables are parsed into,
+    /// e.g. `("DB__", "database")`. If not empty, variables matching none of the prefixes are skipped
+    prefix_map: Vec<(String, String)>,
+
+    /// Key parts joined by the separator under which all variables are parsed, e.g. `services__api`
+    /// parses `PORT` into `services.api.port` of the base json. Paths of other options include it
+    mount: Option<String>,
+
+    /// Predicates on the key and value of variables, all of which must accept a variable for it to be
+    /// included. Unlike the include/exclude patterns they apply with or without a prefix
+    filters: Vec<VarFilter>,
+
+    /// Lowercase names of profiles, e.g. `prod` for `PREFIX__PROD__PORT`. Variables whose first key part
+    /// after the prefix is a profile are skipped unless it is the active profile
+    profiles: BTreeSet<String>,
+
+    /// The lowercase name of the active profile, whose variables override the variables without a profile
+    active_profile: Option<String>,
+
+    /// Lowercase paths of the known variables without the prefix, keyed by the key parts joined by the
+    /// separator, e.g. `server__timeout`. If set, selected variables whose path is neither known nor
+    /// below a known path are rejected with [`Error::UnknownVars`]
+    known_paths: Option<BTreeSet<String>>,
+
+    /// Checks of coerced values, keyed by globs of the key parts joined by the separator, e.g. `log__level`
+    /// or `*__url`.
+    /// Invalid values of all variables are reported together as [`Error::Validation`]
+    rules: Vec<(String, Rule)>,
+
+    #[cfg(feature = "base64")]
+    /// Whether to base64-decode values marked by a `B64` last key part (`KEY__B64`) or a `base64:` value prefix
+    base64_values: bool,
+
+    #[cfg(feature = "unicode")]
+    /// Whether to NFC-normalize values before coercion
+    nfc_values: bool,
+
+    #[cfg(feature = "unicode")]
+    /// The normalization form of keys after the prefix, `None` to keep them as they are
+    key_normalization: Option<KeyNormalization>,
 }
 
+// Parsers are shared between threads, keep them `Send + Sync`
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Parser>();
+};
+
 impl Default for Parser {
     fn default() -> Self {
         Self {
             prefix: None,
             separator: "__".to_string(),
             #[cfg(feature = "filter")]
-            include: vec![],
+            key_filters: FilterSet::default(),
+            #[cfg(feature = "filter")]
+            filters_without_prefix: false,
+            #[cfg(feature = "filter")]
+            value_include: vec![],
             #[cfg(feature = "filter")]
-            exclude: vec![],
+            value_exclude: vec![],
+            empty_values: EmptyValues::String,
+            tombstone: None,
             json: json!({}),
+            radix_literals: false,
+            big_ints_as_strings: false,
+            strict_numbers: false,
+            keep_leading_zeros: true,
+            keep_leading_zeros_at: BTreeMap::new(),
+            trim_values: false,
+            trim_trailing_newline: false,
+            declaration_order: false,
+            sort_keys: false,
+            max_depth: None,
+            overrides: BTreeMap::new(),
+            require_match: false,
+            empty_key_parts: EmptyKeyParts::Keep,
+            key_segmenter: None,
+            bracket_indices: false,
+            value_parsers: coerce::default_chain(),
+            compact_arrays: false,
+            compact_arrays_at: BTreeMap::new(),
+            negative_indices: false,
+            lowercase_keys: true,
+            numeric_object_keys: false,
+            numeric_object_keys_at: BTreeMap::new(),
+            secrets: BTreeSet::new(),
+            prefix_map: vec![],
+            mount: None,
+            filters: vec![],
+            profiles: BTreeSet::new(),
+            active_profile: None,
+            known_paths: None,
+            rules: vec![],
+            #[cfg(feature = "base64")]
+            base64_values: false,
+            #[cfg(feature = "unicode")]
+            nfc_values: false,
+            #[cfg(feature = "unicode")]
+            key_normalization: None,
         }
     }
 }
 
-type ArrayIndex = usize;
+/// A predicate on the key and value of a variable
+type Predicate = dyn Fn(&str, &str) -> bool + Send + Sync;
+
+/// A predicate on the key and value of a variable, see [`Parser::with_filter`]
+#[derive(Clone)]
+pub struct VarFilter(Arc<Predicate>);
+
+impl VarFilter {
+    /// Return a filter accepting the variables the predicate returns `true` for
+    pub fn new(predicate: impl Fn(&str, &str) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(predicate))
+    }
 
-/// A part of a json path which can be either an object or an array item
-#[derive(Debug)]
-pub enum PartValue {
-    Object(Value),
-    ArrayItem(ArrayItem),
+    /// Whether the variable is accepted
+    pub fn accepts(&self, key: &str, value: &str) -> bool {
+        (self.0)(key, value)
+    }
 }
 
-impl PartValue {
-    pub fn into_json_value(self) -> Value {
-        match self {
-            Self::Object(value) => value,
-            Self::ArrayItem(item) => item.into_array_value(),
+impl std::fmt::Debug for VarFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VarFilter")
+    }
+}
+
+/// A variable as `(key, value)`, borrowed from the input where possible
+type Var<'a> = (Cow<'a, str>, Cow<'a, str>);
+
+/// Original environment variable names of parsed values, keyed by json path
+pub(crate) type Provenance = BTreeMap<JsonPath, String>;
+
+impl Parser {
+    ///  Return a new parser with the given prefix
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Return a new parser with the given separator
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given include patterns
+    /// Requires the `filter` feature
+    pub fn with_include(mut self, include: &[&str]) -> Self {
+        let case_insensitive = self.key_filters.is_case_insensitive();
+        self.key_filters.set_include(
+            include
+                .iter()
+                .map(|pattern| {
+                    filter::key_regex(pattern, case_insensitive).expect("Failed to compile regex")
+                })
+                .collect(),
+        );
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given exclude patterns
+    pub fn with_exclude(mut self, exclude: &[&str]) -> Self {
+        let case_insensitive = self.key_filters.is_case_insensitive();
+        self.key_filters.set_exclude(
+            exclude
+                .iter()
+                .map(|pattern| {
+                    filter::key_regex(pattern, case_insensitive).expect("Failed to compile regex")
+                })
+                .collect(),
+        );
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given glob patterns added to the include patterns, e.g. `PREFIX__DB__*`.
+    /// Globs match the whole key, see [`filter::glob_to_regex`]
+    /// Requires the `filter` feature
+    pub fn with_include_globs(mut self, globs: &[&str]) -> Self {
+        let case_insensitive = self.key_filters.is_case_insensitive();
+        let mut include = self.key_filters.include().to_vec();
+        include.extend(
+            globs
+                .iter()
+                .map(|glob| filter::glob_regex(glob, case_insensitive)),
+        );
+        self.key_filters.set_include(include);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given glob patterns added to the exclude patterns, e.g. `*_SECRET`.
+    /// Globs match the whole key, see [`filter::glob_to_regex`]
+    /// Requires the `filter` feature
+    pub fn with_exclude_globs(mut self, globs: &[&str]) -> Self {
+        let case_insensitive = self.key_filters.is_case_insensitive();
+        let mut exclude = self.key_filters.exclude().to_vec();
+        exclude.extend(
+            globs
+                .iter()
+                .map(|glob| filter::glob_regex(glob, case_insensitive)),
+        );
+        self.key_filters.set_exclude(exclude);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser whose include/exclude patterns, including those already given, match keys
+    /// case-insensitively without writing `(?i)` in every pattern
+    /// Requires the `filter` feature
+    pub fn with_case_insensitive_filters(mut self, case_insensitive: bool) -> Self {
+        self.key_filters.set_case_insensitive(case_insensitive);
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given include/exclude patterns for keys, replacing any patterns
+    /// already given. The set is shared, not copied, so it can be built once for many parsers
+    /// Requires the `filter` feature
+    pub fn with_filter_set(mut self, filters: FilterSet) -> Self {
+        self.key_filters = filters;
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser whose include/exclude patterns are matched against keys without the prefix,
+    /// e.g. `^DB__` instead of `^PREFIX__DB__`
+    /// Requires the `filter` feature
+    pub fn with_filters_without_prefix(mut self, without_prefix: bool) -> Self {
+        self.filters_without_prefix = without_prefix;
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given include patterns for values, e.g. `^https://`
+    /// Requires the `filter` feature
+    pub fn with_value_include(mut self, include: &[&str]) -> Self {
+        self.value_include = include
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("Failed to compile regex"))
+            .collect();
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser with the given exclude patterns for values, e.g. `^vault:`
+    /// Requires the `filter` feature
+    pub fn with_value_exclude(mut self, exclude: &[&str]) -> Self {
+        self.value_exclude = exclude
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("Failed to compile regex"))
+            .collect();
+        self
+    }
+
+    /// Return a new parser which skips variables with empty values, see [`EmptyValues::Skip`]
+    pub fn with_skip_empty_values(mut self, skip_empty_values: bool) -> Self {
+        self.empty_values = match skip_empty_values {
+            true => EmptyValues::Skip,
+            false => EmptyValues::String,
+        };
+        self
+    }
+
+    /// Return a new parser which removes the path of variables with the given value from the base json,
+    /// e.g. `LIST__0=__UNSET__` removes the first item of `list`. Array items after it shift down
+    pub fn with_tombstone(mut self, tombstone: impl Into<String>) -> Self {
+        self.tombstone = Some(tombstone.into());
+        self
+    }
+
+    /// Return a new parser turning empty values like `FOO=` into what the policy says,
+    /// e.g. [`EmptyValues::Erase`] to remove `foo` from the base json
+    pub fn with_empty_values(mut self, empty_values: EmptyValues) -> Self {
+        self.empty_values = empty_values;
+        self
+    }
+
+    /// Return a new parser with the given json object
+    pub fn with_json(mut self, json: Value) -> Self {
+        self.json = json;
+        self
+    }
+
+    /// Return a new parser which coerces hexadecimal, octal and binary literals into integers
+    pub fn with_radix_literals(mut self, radix_literals: bool) -> Self {
+        self.radix_literals = radix_literals;
+        self
+    }
+
+    /// Return a new parser which keeps integers too large for `i64`/`u64` as strings
+    pub fn with_big_ints_as_strings(mut self, big_ints_as_strings: bool) -> Self {
+        self.big_ints_as_strings = big_ints_as_strings;
+        self
+    }
+
+    /// Return a new parser which only coerces canonical decimal numbers
+    pub fn with_strict_numbers(mut self, strict_numbers: bool) -> Self {
+        self.strict_numbers = strict_numbers;
+        self
+    }
+
+    /// Return a new parser which keeps (or coerces) integers with leading zeros like `007` as strings
+    pub fn with_keep_leading_zeros(mut self, keep_leading_zeros: bool) -> Self {
+        self.keep_leading_zeros = keep_leading_zeros;
+        self
+    }
+
+    /// Return a new parser which keeps (or coerces) integers with leading zeros at the given path as strings,
+    /// overriding `keep_leading_zeros`
+    pub fn with_keep_leading_zeros_at(mut self, path: impl Into<String>, keep: bool) -> Self {
+        self.keep_leading_zeros_at.insert(path.into(), keep);
+        self
+    }
+
+    /// Return a new parser which trims surrounding whitespace from values
+    pub fn with_trim_values(mut self, trim_values: bool) -> Self {
+        self.trim_values = trim_values;
+        self
+    }
+
+    /// Return a new parser which strips a single trailing newline from values
+    pub fn with_trim_trailing_newline(mut self, trim_trailing_newline: bool) -> Self {
+        self.trim_trailing_newline = trim_trailing_newline;
+        self
+    }
+
+    /// Return a new parser which merges variables in the order they are given, e.g. the order of a
+    /// `.env` file, instead of in reverse order of their keys.
+    /// Later variables override earlier ones, so a `KEY` after `KEY__NESTED` is a conflict while
+    /// `KEY__NESTED` after `KEY` replaces its value
+    pub fn with_declaration_order(mut self, declaration_order: bool) -> Self {
+        self.declaration_order = declaration_order;
+        self
+    }
+
+    /// Return a new parser which sorts object keys of the result recursively, so repeated runs
+    /// produce byte-identical output even with the `preserve_order` feature
+    pub fn with_sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Return a new parser which splits keys after the prefix into key parts with the segmenter
+    /// instead of at every separator, e.g. to support escaped separators
+    pub fn with_key_segmenter(mut self, key_segmenter: Box<dyn KeySegmenter>) -> Self {
+        self.key_segmenter = Some(Arc::from(key_segmenter));
+        self
+    }
+
+    /// Return a new parser which reads array indices from brackets, e.g. `SERVERS[0]__HOST` for
+    /// `servers[0].host`, while numeric key parts like `PORTS__8080` become object keys.
+    /// Bracketed indices are their own key parts, so paths of other options include them, e.g.
+    /// `servers__[0]__password`. Keys are split with [`BracketSegmenter`] unless a key segmenter is
+    /// set, which then has to split bracketed indices off itself
+    pub fn with_bracket_indices(mut self, bracket_indices: bool) -> Self {
+        self.bracket_indices = bracket_indices;
+        self
+    }
+
+    /// Return a new parser which tries the value parser before the other value parsers, e.g. to
+    /// coerce durations or sizes. Parsers added later take precedence
+    pub fn with_value_parser(mut self, value_parser: Box<dyn ValueParser>) -> Self {
+        self.value_parsers.insert(0, Arc::from(value_parser));
+        self
+    }
+
+    /// Return a new parser with the given chain of value parsers instead of the built-in ones,
+    /// see [`coerce::default_chain`]. An empty chain keeps all values as strings
+    pub fn with_value_parsers(mut self, value_parsers: Vec<Arc<dyn ValueParser>>) -> Self {
+        self.value_parsers = value_parsers;
+        self
+    }
+
+    /// Return a new parser which removes `null` items from the arrays variables write to after
+    /// merging, so skipped indices leave no placeholders, e.g. `["a", "c"]` for `LIST__0=a` and
+    /// `LIST__2=c`. Explicit `null` items of those arrays are removed too
+    pub fn with_compact_arrays(mut self, compact_arrays: bool) -> Self {
+        self.compact_arrays = compact_arrays;
+        self
+    }
+
+    /// Return a new parser which compacts the array at the given path or not, overriding
+    /// `compact_arrays`, e.g. `with_compact_arrays_at("hosts", true)`.
+    /// Paths are key parts joined by the separator
+    pub fn with_compact_arrays_at(mut self, path: impl Into<String>, compact: bool) -> Self {
+        self.compact_arrays_at.insert(path.into(), compact);
+        self
+    }
+
+    /// Return a new parser which turns key parts like `-1` into array indices relative to the end of
+    /// the array at their path in the document merged into, e.g. the parser's json or the base of
+    /// [`Parser::parse_iter_to`], to extend a default list without knowing its
+    /// length. `-n` is the n-th item appended after the items of the base array, so with a base
+    /// `{"hosts": ["a", "b"]}`, `HOSTS__-1` sets `hosts[2]` and `HOSTS__-2` sets `hosts[3]`.
+    /// Without a base array, `-1` is the first item
+    pub fn with_negative_indices(mut self, negative_indices: bool) -> Self {
+        self.negative_indices = negative_indices;
+        self
+    }
+
+    /// Return a new parser which lowercases key parts, or keeps them verbatim, e.g. `logLevel` for
+    /// `PREFIX__logLevel`. Enabled by default
+    pub fn with_lowercase_keys(mut self, lowercase_keys: bool) -> Self {
+        self.lowercase_keys = lowercase_keys;
+        self
+    }
+
+    /// Return a new parser which turns numeric key parts into object keys instead of array indices
+    pub fn with_numeric_object_keys(mut self, numeric_object_keys: bool) -> Self {
+        self.numeric_object_keys = numeric_object_keys;
+        self
+    }
+
+    /// Return a new parser which turns numeric key parts directly below the given path into object keys
+    /// (or array indices), overriding `numeric_object_keys`
+    pub fn with_numeric_object_keys_at(
+        mut self,
+        path: impl Into<String>,
+        object_keys: bool,
+    ) -> Self {
+        self.numeric_object_keys_at.insert(path.into(), object_keys);
+        self
+    }
+
+    /// Return a new parser which treats the value at the given path, and the values below it, as secret
+    pub fn with_secret(mut self, path: impl Into<String>) -> Self {
+        self.secrets.insert(path.into());
+        self
+    }
+
+    /// Return a new parser which rejects variables with the prefix whose path is not one of the given
+    /// paths or below one of them, e.g. to catch a misspelled `PREFIX__SERVER__TIMOUT`.
+    /// Paths are key parts joined by the separator, e.g. `server__timeout`
+    pub fn with_known_paths(mut self, paths: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.known_paths
+            .get_or_insert_with(BTreeSet::new)
+            .extend(paths.into_iter().map(|path| path.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Return a new parser which only includes variables the predicate returns `true` for, given the
+    /// key and the value, e.g. `|key, value| !value.is_empty()`. Every added predicate must accept a variable
+    pub fn with_filter(
+        mut self,
+        predicate: impl Fn(&str, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.filters.push(VarFilter::new(predicate));
+        self
+    }
+
+    /// Return a new parser which parses the variables of each prefix into the given subtree, e.g.
+    /// `[("DB__", "database"), ("HTTP__", "server")]` parses `DB__HOST` into `database.host`.
+    /// Variables matching none of the prefixes are skipped, the longest matching prefix wins
+    pub fn with_prefix_map<P: Into<String>, S: Into<String>>(
+        mut self,
+        prefix_map: impl IntoIterator<Item = (P, S)>,
+    ) -> Self {
+        self.prefix_map.extend(
+            prefix_map
+                .into_iter()
+                .map(|(prefix, subtree)| (prefix.into(), subtree.into())),
+        );
+        self
+    }
+
+    /// Return a new parser which parses all variables under the given key parts joined by the separator,
+    /// e.g. `services__api`, instead of the root of the json
+    pub fn with_mount(mut self, mount: impl Into<String>) -> Self {
+        self.mount = Some(mount.into());
+        self
+    }
+
+    /// Return a new parser which treats the first key part after the prefix as a profile if it is
+    /// one of the given names, e.g. `dev` and `prod`
+    pub fn with_profiles<P: Into<String>>(mut self, profiles: impl IntoIterator<Item = P>) -> Self {
+        self.profiles.extend(
+            profiles
+                .into_iter()
+                .map(|profile| profile.into().to_lowercase()),
+        );
+        self
+    }
+
+    /// Return a new parser where the variables of the given profile, e.g. `PREFIX__PROD__PORT` for `prod`,
+    /// override the variables without a profile, e.g. `PREFIX__PORT`. The profile is added to the profiles
+    pub fn with_active_profile(mut self, profile: impl Into<String>) -> Self {
+        let profile = profile.into().to_lowercase();
+        self.profiles.insert(profile.clone());
+        self.active_profile = Some(profile);
+        self
+    }
+
+    /// Return a new parser which treats the values of the given variables as secret,
+    /// e.g. the variables read from a secret store. Keys without the prefix are ignored
+    pub fn with_secret_vars<K: AsRef<str>>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        let prefix = self.prefix.clone().unwrap_or_default();
+
+        for key in keys {
+            if let Some(path) = key.as_ref().strip_prefix(&prefix) {
+                self.secrets.insert(path.to_lowercase());
+            }
+        }
+
+        self
+    }
+
+    /// Return a new parser which rejects variables whose key splits into more than `max_depth` key parts
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Return a new parser which adds the given variables, e.g. `PREFIX__FEATURE__BETA=true`, to the
+    /// variables being parsed, replacing variables with the same name, so a few values can be
+    /// overridden without touching the process environment.
+    /// Overrides are selected by the prefix and filters like any other variable
+    pub fn with_overrides<K: Into<String>, V: Into<String>>(
+        mut self,
+        overrides: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.overrides.extend(
+            overrides
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Return a new parser which fails with [`Error::NoMatch`] when no variable is selected, which
+    /// usually means a misspelled prefix or a missing deployment config.
+    /// [`Parser::parse_iter_report`] reports it as a warning instead
+    pub fn with_require_match(mut self, require_match: bool) -> Self {
+        self.require_match = require_match;
+        self
+    }
+
+    /// Return a new parser turning empty key parts, e.g. of `FOO____BAR` or `FOO__`, into what the
+    /// policy says, e.g. [`EmptyKeyParts::Error`] to reject them
+    pub fn with_empty_key_parts(mut self, empty_key_parts: EmptyKeyParts) -> Self {
+        self.empty_key_parts = empty_key_parts;
+        self
+    }
+
+    #[cfg(feature = "base64")]
+    /// Return a new parser which base64-decodes values marked by a `B64` last key part or a `base64:` value prefix.
+    /// Decoded values are inserted as strings without coercion.
+    /// Requires the `base64` feature
+    pub fn with_base64_values(mut self, base64_values: bool) -> Self {
+        self.base64_values = base64_values;
+        self
+    }
+
+    #[cfg(feature = "unicode")]
+    /// Return a new parser which NFC-normalizes values before coercion
+    /// Requires the `unicode` feature
+    pub fn with_nfc_values(mut self, nfc_values: bool) -> Self {
+        self.nfc_values = nfc_values;
+        self
+    }
+
+    #[cfg(feature = "unicode")]
+    /// Return a new parser which normalizes keys after the prefix to the given form before they
+    /// are matched and split, so keys with accents or full-width characters parse the same on
+    /// every platform. Key parts are then lowercased by Unicode rules as usual
+    /// Requires the `unicode` feature
+    pub fn with_key_normalization(mut self, form: KeyNormalization) -> Self {
+        self.key_normalization = Some(form);
+        self
+    }
+
+    #[cfg(feature = "unicode")]
+    /// Normalize a key to the configured form, reusing it if already normalized
+    fn normalize_key<'a>(&self, key: Cow<'a, str>) -> Cow<'a, str> {
+        match self.key_normalization {
+            Some(KeyNormalization::Nfc) if !is_nfc(&key) => Cow::Owned(key.nfc().collect()),
+            Some(KeyNormalization::Nfkc) if !is_nfkc(&key) => Cow::Owned(key.nfkc().collect()),
+            _ => key,
+        }
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Parse environment variables into json
+    pub fn parse_from_env(&self) -> Result<serde_json::Value, Error> {
+        self.parse_iter(std::env::vars())
+    }
+
+    /// Parse a map of variables into json, e.g. variables injected where there is no process environment
+    pub fn parse_map<K: AsRef<str>, V: AsRef<str>>(
+        &self,
+        vars: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<Value, Error> {
+        self.parse_iter(
+            vars.into_iter()
+                .map(|(key, value)| (key.as_ref().to_string(), value.as_ref().to_string())),
+        )
+    }
+
+    /// Parse the variables of a source into json
+    pub fn parse_source(&self, source: &(impl EnvSource + ?Sized)) -> Result<Value, Error> {
+        self.parse_iter(source.vars()?.into_iter())
+    }
+
+    /// Parse the variables of a snapshot into json
+    pub fn parse_snapshot(&self, snapshot: &source::EnvSnapshot) -> Result<Value, Error> {
+        self.parse_source(snapshot)
+    }
+
+    /// Whether a difference between snapshots changes any variable selected by the parser,
+    /// so unrelated changes don't trigger a re-parse
+    pub fn is_affected_by(&self, diff: &source::EnvDiff) -> bool {
+        diff.iter().any(|(key, old, new)| {
+            [old, new]
+                .into_iter()
+                .flatten()
+                .any(|value| self.skip_reason(key, value).is_none())
+        })
+    }
+
+    /// Parse the variables of an asynchronous source into json
+    pub async fn parse_from_async(
+        &self,
+        source: &(impl AsyncEnvSource + ?Sized),
+    ) -> Result<Value, Error> {
+        self.parse_iter(source.vars().await?.into_iter())
+    }
+
+    /// Parse the variables of an asynchronous source into json, treating all their values as secret,
+    /// e.g. the variables of a secret store
+    pub async fn parse_secrets_from_async(
+        &self,
+        source: &(impl AsyncEnvSource + ?Sized),
+    ) -> Result<Value, Error> {
+        let vars = source.vars().await?;
+
+        self.clone()
+            .with_secret_vars(vars.iter().map(|(key, _)| key))
+            .parse_iter(vars.into_iter())
+    }
+
+    /// Parse a block of `KEY=VALUE` lines, see [`source::DotenvText`] for the supported syntax
+    pub fn parse_str(&self, text: &str) -> Result<Value, Error> {
+        self.parse_iter(source::parse_dotenv(text)?.into_iter())
+    }
+
+    /// Serialize a json value into dotenv `KEY=VALUE` lines using the prefix and separator of the parser,
+    /// the inverse of [`Parser::parse_str`]
+    pub fn to_dotenv(&self, value: &Value) -> Result<String, Error> {
+        format::Dotenv {
+            prefix: self.prefix.clone(),
+            separator: self.separator.clone(),
+        }
+        .serialize(value)
+    }
+
+    /// Parse iterator of String tuples into a docker compose `env_file` using the prefix and
+    /// separator of the parser, with a comment above each variable naming where its value came
+    /// from, see [`format::ComposeEnv`]
+    pub fn parse_to_compose_env(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<String, Error> {
+        let (json, provenance) = self.parse_with_provenance(vars)?;
+        let vars = provenance
+            .into_iter()
+            .map(|(path, var)| {
+                let key = path
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(&self.separator);
+                (key, var)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let comments = flatten(&json, &self.separator)
+            .into_keys()
+            .map(|key| {
+                let comment = match vars.get(&key) {
+                    Some(var) => format!("from {var}"),
+                    None => "from the base json".to_string(),
+                };
+                (key, comment)
+            })
+            .collect();
+
+        format::ComposeEnv {
+            prefix: self.prefix.clone(),
+            separator: self.separator.clone(),
+            comments,
+        }
+        .serialize(&json)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Parse environment variables and serialize the result with the given output format
+    pub fn parse_from_env_as(&self, format: &dyn OutputFormat) -> Result<String, Error> {
+        format.serialize(&self.parse_from_env()?)
+    }
+
+    /// Return the prefix to use when parsing environment variables
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Return the separator to use when parsing environment variables
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+
+    /// Return the json object the parsed environment variables are merged into
+    pub fn json(&self) -> &Value {
+        &self.json
+    }
+
+    /// Check that the prefix and separator can produce meaningful keys
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.separator.is_empty() {
+            return Err(Error::Config("separator must not be empty".to_string()));
+        }
+
+        if self.prefix.as_deref() == Some("") {
+            return Err(Error::Config(
+                "prefix must not be empty, use no prefix instead".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Return warnings about a configuration that is valid but likely a mistake
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+
+        if let Some(prefix) = &self.prefix {
+            if !self.separator.is_empty() && !prefix.ends_with(&self.separator) {
+                warnings.push(format!(
+                    "prefix `{prefix}` does not end with separator `{}`, keys like `{prefix}{}KEY` \
+                     will be nested under an empty key",
+                    self.separator, self.separator
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    /// Preprocess environment variables by filtering, normalizing and sorting them
+    fn preprocess_vars<'a>(
+        &self,
+        vars: impl Iterator<Item = Var<'a>>,
+    ) -> Result<Vec<Var<'a>>, Error> {
+        let mut vars = self.select_vars(vars)?;
+
+        #[cfg(feature = "rayon")]
+        let values = vars.par_iter_mut().map(|(_, value)| value);
+        #[cfg(not(feature = "rayon"))]
+        let values = vars.iter_mut().map(|(_, value)| value);
+
+        values.for_each(|value| {
+            if self.trim_values {
+                shrink(value, str::trim);
+            } else if self.trim_trailing_newline {
+                shrink(value, strip_trailing_newline);
+            }
+
+            #[cfg(feature = "unicode")]
+            if self.nfc_values && !is_nfc(value) {
+                *value = Cow::Owned(value.nfc().collect());
+            }
+        });
+
+        if self.declaration_order {
+            return Ok(vars);
+        }
+
+        // Sort in reverse order to ensure that the longest keys are processed first
+        #[cfg(feature = "rayon")]
+        vars.par_sort_by(|(key_a, _), (key_b, _)| key_b.cmp(key_a));
+        #[cfg(not(feature = "rayon"))]
+        vars.sort_by(|(key_a, _), (key_b, _)| key_b.cmp(key_a));
+
+        Ok(vars)
+    }
+
+    /// Select environment variables matching the prefix and filters, stripping the prefix
+    fn select_vars<'a>(&self, vars: impl Iterator<Item = Var<'a>>) -> Result<Vec<Var<'a>>, Error> {
+        let vars = vars
+            .filter(|(key, _)| !self.overrides.contains_key(key.as_ref()))
+            .chain(
+                self.overrides
+                    .iter()
+                    .map(|(key, value)| (Cow::Owned(key.clone()), Cow::Owned(value.clone()))),
+            );
+
+        #[cfg(feature = "rayon")]
+        let vars = vars.collect::<Vec<_>>().into_par_iter();
+
+        let vars = vars
+            .filter(|(key, value)| match self.skip_reason(key, value) {
+                #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                Some(reason) => {
+                    debug!(var = %key, %reason, "skipped variable");
+                    false
+                }
+                None => true,
+            })
+            .map(|(key, value)| {
+                let key = match &self.prefix {
+                    Some(prefix) => strip_prefix(key, prefix)?,
+                    None => key,
+                };
+                #[cfg(feature = "unicode")]
+                let key = self.normalize_key(key);
+
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut vars = self.apply_active_profile(vars)?;
+        self.check_known(&vars)?;
+
+        if !self.prefix_map.is_empty() {
+            vars = vars
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let (prefix, subtree) = self.mapped_prefix(&key)?;
+                    let key = format!("{subtree}{}{}", self.separator, &key[prefix.len()..]);
+                    Some((Cow::Owned(key), value))
+                })
+                .collect();
+        }
+
+        if let Some(mount) = &self.mount {
+            for (key, _) in &mut vars {
+                *key = Cow::Owned(format!("{mount}{}{key}", self.separator));
+            }
+        }
+
+        Ok(vars)
+    }
+
+    /// Reject variables whose key is not a known path or below one, if known paths are set
+    fn check_known(&self, vars: &[Var]) -> Result<(), Error> {
+        let Some(known_paths) = &self.known_paths else {
+            return Ok(());
+        };
+
+        let unknown = vars
+            .iter()
+            .filter(|(key, _)| {
+                let path = key.to_lowercase();
+                !known_paths.iter().any(|known| {
+                    path.strip_prefix(known.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(&self.separator))
+                })
+            })
+            .map(|(key, _)| format!("{}{key}", self.prefix.as_deref().unwrap_or_default()))
+            .collect::<Vec<_>>();
+
+        match unknown.is_empty() {
+            true => Ok(()),
+            false => Err(Error::UnknownVars(unknown)),
         }
     }
-}
 
-#[derive(Debug)]
-pub struct ArrayItem {
-    pub index: ArrayIndex,
-    pub value: Value,
-}
+    /// Whether a preprocessed value removes its path instead of setting it
+    fn erases(&self, value: &str) -> bool {
+        (value.is_empty() && self.empty_values == EmptyValues::Erase)
+            || self.tombstone.as_deref() == Some(value)
+    }
+
+    /// Return the longest prefix of the prefix map matching the key and its subtree
+    fn mapped_prefix(&self, key: &str) -> Option<(&str, &str)> {
+        self.prefix_map
+            .iter()
+            .filter(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, subtree)| (prefix.as_str(), subtree.as_str()))
+    }
+
+    /// Strip the active profile from the keys of its variables and drop the variables they override
+    fn apply_active_profile<'a>(&self, vars: Vec<Var<'a>>) -> Result<Vec<Var<'a>>, Error> {
+        let Some(active) = &self.active_profile else {
+            return Ok(vars);
+        };
+
+        let (overrides, base): (Vec<_>, Vec<_>) = vars
+            .into_iter()
+            .partition(|(key, _)| self.profile(key) == Some(active.as_str()));
+
+        let overrides = overrides
+            .into_iter()
+            .map(|(key, value)| {
+                let profile_len =
+                    key.find(&self.separator).unwrap_or_default() + self.separator.len();
+                let profile = key[..profile_len].to_string();
+                Ok((strip_prefix(key, &profile)?, value))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let overridden = overrides
+            .iter()
+            .map(|(key, _)| key.to_lowercase())
+            .collect::<BTreeSet<_>>();
+
+        Ok(base
+            .into_iter()
+            .filter(|(key, _)| !overridden.contains(&key.to_lowercase()))
+            .chain(overrides)
+            .collect())
+    }
+
+    /// Return the lowercase profile of a key without the prefix, `None` if it has no profile
+    fn profile(&self, key: &str) -> Option<&str> {
+        if self.profiles.is_empty() {
+            return None;
+        }
+
+        let (first, _) = key.split_once(&self.separator)?;
+        self.profiles
+            .get(first.to_lowercase().as_str())
+            .map(String::as_str)
+    }
+
+    /// Return the variables dropped by the prefix, the include/exclude filters and inactive profiles,
+    /// with the reason they were dropped, e.g. to spot a misspelled prefix
+    pub fn skipped(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Vec<(String, SkipReason)> {
+        vars.filter_map(|(key, value)| self.skip_reason(&key, &value).map(|reason| (key, reason)))
+            .collect()
+    }
+
+    /// Return why a variable is skipped, `None` if it is selected
+    fn skip_reason(&self, key: &str, value: &str) -> Option<SkipReason> {
+        self.key_skip_reason(key)
+            .or_else(|| self.value_skip_reason(value))
+            .or_else(|| {
+                (!self.filters.iter().all(|filter| filter.accepts(key, value)))
+                    .then_some(SkipReason::Filter)
+            })
+    }
+
+    /// Return why a variable is skipped by its value, `None` if it is selected
+    fn value_skip_reason(&self, value: &str) -> Option<SkipReason> {
+        if self.empty_values == EmptyValues::Skip && value.is_empty() {
+            return Some(SkipReason::Empty);
+        }
+
+        #[cfg(feature = "filter")]
+        if (!self.value_include.is_empty()
+            && !self
+                .value_include
+                .iter()
+                .any(|pattern| pattern.is_match(value)))
+            || self
+                .value_exclude
+                .iter()
+                .any(|pattern| pattern.is_match(value))
+        {
+            return Some(SkipReason::Value);
+        }
+
+        None
+    }
+
+    /// Return why a variable is skipped by its key, `None` if it is selected
+    fn key_skip_reason(&self, key: &str) -> Option<SkipReason> {
+        let Some(prefix) = self.prefix.as_deref() else {
+            return self.unprefixed_skip_reason(key);
+        };
+
+        let Some(rest) = key.strip_prefix(prefix) else {
+            return Some(SkipReason::Prefix);
+        };
+
+        #[cfg(feature = "filter")]
+        {
+            let key = if self.filters_without_prefix {
+                rest
+            } else {
+                key
+            };
+
+            if let Some(reason) = self.key_filters.skip_reason(key) {
+                return Some(reason);
+            }
+        }
+
+        self.unprefixed_skip_reason(rest)
+    }
+
+    /// Return why a variable is skipped by its key without the prefix: it belongs to an inactive
+    /// profile or matches none of the prefixes of the prefix map
+    fn unprefixed_skip_reason(&self, key: &str) -> Option<SkipReason> {
+        let key = match self.profile(key) {
+            Some(profile) if self.active_profile.as_deref() != Some(profile) => {
+                return Some(SkipReason::Profile);
+            }
+            Some(_) => key
+                .split_once(&self.separator)
+                .map_or(key, |(_, rest)| rest),
+            None => key,
+        };
+
+        if !self.prefix_map.is_empty() && self.mapped_prefix(key).is_none() {
+            return Some(SkipReason::Prefix);
+        }
+
+        None
+    }
+
+    #[cfg(feature = "unicode")]
+    /// Return the keys (without prefix) of the selected variables whose values are not in NFC form,
+    /// i.e. the variables whose values are changed when `nfc_values` is enabled
+    /// Requires the `unicode` feature
+    pub fn nfc_changes(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Vec<String>, Error> {
+        let mut keys = self
+            .select_vars(vars.map(|(key, value)| (Cow::Owned(key), Cow::Owned(value))))?
+            .into_iter()
+            .filter(|(_, value)| !is_nfc(value))
+            .map(|(key, _)| key.into_owned())
+            .collect::<Vec<_>>();
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Parse environment variables and deserialize them into `T`
+    pub fn parse_from_env_into<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        self.parse_into(std::env::vars())
+    }
+
+    /// Parse iterator of String tuples and deserialize the result into `T`.
+    /// If deserialization fails at a value that came from a variable, the error names that variable.
+    pub fn parse_into<T: DeserializeOwned>(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<T, Error> {
+        let (json, provenance) = self.parse_with_provenance(vars)?;
+
+        Self::deserialize(json, &provenance)
+    }
+
+    /// Parse iterator of String tuples into json and the original variable names of its values
+    pub(crate) fn parse_with_provenance(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<(Value, Provenance), Error> {
+        let mut provenance = Provenance::new();
+        let json = self.parse_vars(self.json.clone(), owned(vars), Some(&mut provenance))?;
+
+        Ok((json, provenance))
+    }
+
+    /// Deserialize json into `T`, naming the variable a failing value came from
+    pub(crate) fn deserialize<T: DeserializeOwned>(
+        json: Value,
+        provenance: &Provenance,
+    ) -> Result<T, Error> {
+        serde_path_to_error::deserialize(json).map_err(|err| {
+            let indices = err
+                .path()
+                .iter()
+                .filter_map(|segment| match segment {
+                    serde_path_to_error::Segment::Seq { index } => Some(JsonIndex::Usize(*index)),
+                    serde_path_to_error::Segment::Map { key } => {
+                        Some(JsonIndex::String(key.clone()))
+                    }
+                    _ => None,
+                })
+                .collect::<JsonPath>();
+
+            // The value itself came from a variable, or was built from variables below it
+            let var = provenance.get(&indices).cloned().or_else(|| {
+                provenance
+                    .range(indices.clone()..)
+                    .next()
+                    .filter(|(path, _)| path.starts_with(&indices))
+                    .map(|(_, var)| var.clone())
+            });
+
+            Error::Deserialize {
+                path: err.path().to_string(),
+                var,
+                source: err.into_inner(),
+            }
+        })
+    }
+
+    /// Parse iterator of String tuples into json
+    pub fn parse_iter(&self, vars: impl Iterator<Item = (String, String)>) -> Result<Value, Error> {
+        self.parse_vars(self.json.clone(), owned(vars), None)
+    }
+
+    /// Parse iterator of String tuples into json, consuming the parser to reuse its json object
+    /// instead of cloning it
+    pub fn parse_iter_into(
+        mut self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Value, Error> {
+        let json = std::mem::take(&mut self.json);
+        self.parse_vars(json, owned(vars), None)
+    }
+
+    /// Parse iterator of borrowed str tuples into json, avoiding copies of keys and values where possible
+    pub fn parse_iter_ref<'a>(
+        &self,
+        vars: impl Iterator<Item = (&'a str, &'a str)>,
+    ) -> Result<Value, Error> {
+        self.parse_vars(
+            self.json.clone(),
+            vars.map(|(key, value)| (Cow::Borrowed(key), Cow::Borrowed(value))),
+            None,
+        )
+    }
+
+    /// Parse iterator of String tuples into a document of any [`ValueOps`] type, e.g. `toml::Value`,
+    /// merging them into `base` instead of the parser's json object
+    pub fn parse_iter_to<V: ValueOps>(
+        &self,
+        base: V,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<V, Error> {
+        self.parse_vars(base, owned(vars), None)
+    }
+
+    #[cfg(feature = "cbor")]
+    /// Parse iterator of String tuples into CBOR bytes, merging them into the parser's json object.
+    /// Integers stay integers rather than going through a json string.
+    /// Requires the `cbor` feature
+    pub fn parse_to_cbor(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Vec<u8>, Error> {
+        let base: ciborium::Value = value::from_json(self.json.clone())?;
+        let value = self.parse_iter_to(base, vars)?;
+
+        let mut bytes = vec![];
+        ciborium::into_writer(&value, &mut bytes).map_err(|err| Error::Cbor(err.to_string()))?;
+        Ok(bytes)
+    }
+
+    #[cfg(feature = "msgpack")]
+    /// Parse iterator of String tuples into MessagePack bytes, merging them into the parser's json
+    /// object without going through a json string.
+    /// Requires the `msgpack` feature
+    pub fn parse_to_msgpack(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Vec<u8>, Error> {
+        let base: rmpv::Value = value::from_json(self.json.clone())?;
+        let value = self.parse_iter_to(base, vars)?;
+
+        rmp_serde::to_vec(&value).map_err(Error::Msgpack)
+    }
+
+    /// Parse variables into the given document, recording the original name of each variable
+    fn parse_vars<'a, V: ValueOps>(
+        &self,
+        mut json: V,
+        vars: impl Iterator<Item = Var<'a>>,
+        provenance: Option<&mut Provenance>,
+    ) -> Result<V, Error> {
+        self.merge_vars(&mut json, vars, provenance)?;
+        Ok(json)
+    }
+
+    /// Merge variables into a document in place
+    fn merge_vars<'a, V: ValueOps>(
+        &self,
+        json: &mut V,
+        vars: impl Iterator<Item = Var<'a>>,
+        provenance: Option<&mut Provenance>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_vars", prefix = self.prefix.as_deref()).entered();
+
+        self.validate()?;
+        let entries = self.flat_vars(vars)?;
+        self.merge_entries(json, entries, provenance)
+    }
+
+    /// Parse iterator of String tuples and merge them into a caller-owned document in place,
+    /// instead of into a clone of the parser's json object, e.g. to re-apply overrides to a
+    /// long-lived document on reload.
+    /// Variables are validated and coerced before the document is changed, but a conflict while
+    /// merging leaves the variables merged before it applied
+    pub fn merge_into<V: ValueOps>(
+        &self,
+        json: &mut V,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<(), Error> {
+        self.merge_vars(json, owned(vars), None)
+    }
+
+    /// Return the path and coerced value of each variable, in the order they would be merged,
+    /// without merging them into the base json.
+    /// Useful to route values into other sinks or to apply custom merging.
+    /// End-relative indices like `-1` are resolved against the base json, as by [`Parser::parse_iter`]
+    pub fn assignments<'a>(
+        &'a self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> impl Iterator<Item = Result<(JsonPath, Value), Error>> + 'a {
+        let (vars, error) = match self
+            .validate()
+            .and_then(|_| self.preprocess_vars(owned(vars)))
+        {
+            Ok(vars) => (vars, None),
+            Err(err) => (vec![], Some(err)),
+        };
+
+        error
+            .map(Err)
+            .into_iter()
+            .chain(vars.into_iter().map(|(key, value)| {
+                let (key_parts, value) = self.assignment(&key, value)?;
+                Ok((self.json_path_in(&self.json, &key_parts), value))
+            }))
+    }
 
-impl ArrayItem {
-    pub fn new(index: ArrayIndex, value: Value) -> Self {
-        Self { index, value }
+    /// Return the [`Entry`] of each variable, in the order they would be merged, the intermediate
+    /// model to build documents of other data models from with [`tree::build`]
+    pub fn entries<'a>(
+        &'a self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> impl Iterator<Item = Result<Entry, Error>> + 'a {
+        self.assignments(vars).map(|assignment| {
+            assignment.map(|(path, value)| Entry {
+                path,
+                value: value.into(),
+            })
+        })
     }
 
-    pub fn into_array_value(self) -> Value {
-        if self.index == 0 {
-            return Value::Array(vec![self.value]);
+    /// Split the key of a preprocessed variable into key parts, lowercased unless disabled, and
+    /// coerce its value
+    fn assignment<'k>(
+        &self,
+        key: &'k str,
+        env_value: Cow<'_, str>,
+    ) -> Result<(Vec<Cow<'k, str>>, Value), Error> {
+        #[allow(unused_mut)]
+        let mut key_parts = self
+            .key_segmenter()
+            .segments(key, &self.separator)?
+            .into_iter()
+            .map(|part| match (self.lowercase_keys, part) {
+                (true, Cow::Borrowed(part)) => lowercase(part),
+                (true, Cow::Owned(part)) => Cow::Owned(part.to_lowercase()),
+                (false, part) => part,
+            })
+            .collect::<Vec<_>>();
+        self.handle_empty_key_parts(key, &mut key_parts)?;
+
+        if let Some(max_depth) = self.max_depth {
+            let depth = key_parts.len();
+            if depth > max_depth {
+                return Err(Error::TooDeep {
+                    var: format!("{}{key}", self.prefix.as_deref().unwrap_or_default()),
+                    depth,
+                    max_depth,
+                });
+            }
         }
 
-        let mut arr = vec![Value::Null; self.index];
-        arr.push(self.value);
-        Value::Array(arr)
+        #[cfg(feature = "base64")]
+        let decoded = self.decode_base64(key, &mut key_parts, &env_value)?;
+        #[cfg(not(feature = "base64"))]
+        let decoded: Option<String> = None;
+
+        let env_value = match decoded {
+            Some(decoded) => Value::String(decoded),
+            None => {
+                let keep_leading_zeros = if self.keep_leading_zeros_at.is_empty() {
+                    self.keep_leading_zeros
+                } else {
+                    self.keep_leading_zeros_at_path(&key_parts.join(&self.separator))
+                };
+                self.coerce_value(env_value, keep_leading_zeros)?
+            }
+        };
+        debug!(
+            var = %key,
+            value = %self.redacted(&key_parts, &env_value),
+            "coerced variable"
+        );
+
+        Ok((key_parts, env_value))
     }
-}
 
-/// Index/key of a array/object
-#[derive(Debug, Clone)]
-pub enum JsonIndex {
-    String(String),
-    Usize(usize),
-}
+    /// Apply the empty key parts policy to the key parts of a variable
+    fn handle_empty_key_parts(
+        &self,
+        key: &str,
+        key_parts: &mut Vec<Cow<str>>,
+    ) -> Result<(), Error> {
+        let Some(index) = key_parts.iter().position(|part| part.is_empty()) else {
+            return Ok(());
+        };
 
-impl JsonIndex {
-    pub fn from_vec(vec: Vec<&str>) -> Vec<Self> {
-        vec.into_iter().map(Self::from).collect()
+        match self.empty_key_parts {
+            EmptyKeyParts::Keep => Ok(()),
+            EmptyKeyParts::Collapse if key_parts.iter().any(|part| !part.is_empty()) => {
+                key_parts.retain(|part| !part.is_empty());
+                Ok(())
+            }
+            // Nothing to collapse into, the key is empty or only separators
+            EmptyKeyParts::Collapse | EmptyKeyParts::Error => Err(Error::EmptyKeyPart {
+                var: format!("{}{key}", self.prefix.as_deref().unwrap_or_default()),
+                index,
+                cause: match index {
+                    _ if key_parts.len() == 1 => "an empty key",
+                    0 => "a leading separator",
+                    i if i + 1 == key_parts.len() => "a trailing separator",
+                    _ => "consecutive separators",
+                },
+            }),
+        }
     }
-}
 
-impl From<&str> for JsonIndex {
-    fn from(s: &str) -> Self {
-        if let Ok(number) = s.parse::<usize>() {
-            Self::Usize(number)
+    #[cfg(feature = "tracing")]
+    /// Return the value for diagnostics, redacted if it is secret
+    /// Requires the `tracing` feature
+    fn redacted<'v>(&self, key_parts: &[Cow<str>], value: &'v Value) -> Cow<'v, Value> {
+        if self.is_secret(key_parts) {
+            Cow::Owned(Value::String(from_env::REDACTED.to_string()))
         } else {
-            Self::String(s.to_string())
+            Cow::Borrowed(value)
         }
     }
-}
 
-impl From<String> for JsonIndex {
-    fn from(s: String) -> Self {
-        if let Ok(number) = s.parse::<usize>() {
-            Self::Usize(number)
-        } else {
-            Self::String(s)
-        }
+    /// Whether the value at the key parts, or one of its parents, is marked as secret
+    pub(crate) fn is_secret(&self, key_parts: &[Cow<str>]) -> bool {
+        !self.secrets.is_empty()
+            && (1..=key_parts.len()).any(|depth| {
+                self.secrets
+                    .contains(&key_parts[..depth].join(&self.separator))
+            })
     }
-}
 
-impl From<&String> for JsonIndex {
-    fn from(s: &String) -> Self {
-        if let Ok(number) = s.parse::<usize>() {
-            Self::Usize(number)
-        } else {
-            Self::String(s.clone())
+    /// Return the key segmenter, the built-in one for the bracket indices option if none is set
+    fn key_segmenter(&self) -> &dyn KeySegmenter {
+        match (&self.key_segmenter, self.bracket_indices) {
+            (Some(key_segmenter), _) => key_segmenter.as_ref(),
+            (None, true) => &BracketSegmenter,
+            (None, false) => &SeparatorSegmenter,
         }
     }
-}
 
-impl Parser {
-    ///  Return a new parser with the given prefix
-    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
-        self.prefix = Some(prefix.into());
-        self
-    }
+    /// Whether the key part at position `i` is an array index rather than an object key
+    pub(crate) fn is_index(&self, key_parts: &[impl AsRef<str>], i: usize) -> bool {
+        if self.bracket_indices {
+            return segment::bracket_index(key_parts[i].as_ref()).is_some();
+        }
 
-    /// Return a new parser with the given separator
-    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
-        self.separator = separator.into();
-        self
-    }
+        if key_parts[i].as_ref().parse::<usize>().is_err()
+            && self.negative_index(&key_parts[i]).is_none()
+        {
+            return false;
+        }
 
-    #[cfg(feature = "filter")]
-    /// Return a new parser with the given include patterns
-    /// Requires the `filter` feature
-    pub fn with_include(mut self, include: &[&str]) -> Self {
-        self.include = include
+        if self.numeric_object_keys_at.is_empty() {
+            return !self.numeric_object_keys;
+        }
+
+        let path = key_parts[..i]
             .iter()
-            .map(|pattern| Regex::new(pattern).expect("Failed to compile regex"))
-            .collect();
-        self
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(&self.separator);
+
+        !self
+            .numeric_object_keys_at
+            .get(&path)
+            .copied()
+            .unwrap_or(self.numeric_object_keys)
     }
 
-    #[cfg(feature = "filter")]
-    /// Return a new parser with the given exclude patterns
-    pub fn with_exclude(mut self, exclude: &[&str]) -> Self {
-        self.exclude = exclude
-            .iter()
-            .map(|pattern| Regex::new(pattern).expect("Failed to compile regex"))
-            .collect();
-        self
+    /// Return the json path of key parts, resolving end-relative indices against the document the
+    /// value is merged into
+    pub(crate) fn json_path_in(&self, json: &Value, key_parts: &[impl AsRef<str>]) -> JsonPath {
+        self.json_path_with(key_parts, |path| {
+            path::get(json, path)
+                .and_then(Value::as_array)
+                .map_or(0, Vec::len)
+        })
     }
 
-    /// Return a new parser with the given json object
-    pub fn with_json(mut self, json: Value) -> Self {
-        self.json = json;
-        self
+    /// Convert key parts into a json path, resolving end-relative indices like `-1` against the
+    /// lengths of the arrays `base_len` returns for their paths
+    pub(crate) fn json_path_with(
+        &self,
+        key_parts: &[impl AsRef<str>],
+        mut base_len: impl FnMut(&JsonPath) -> usize,
+    ) -> JsonPath {
+        let mut json_path = JsonPath::new();
+
+        for (i, part) in key_parts.iter().enumerate() {
+            let index = match (self.is_index(key_parts, i), self.negative_index(part)) {
+                (true, Some(n)) => JsonIndex::Usize(base_len(&json_path) + n - 1),
+                (true, None) => match segment::bracket_index(part.as_ref()) {
+                    Some(index) => JsonIndex::Usize(index),
+                    None => JsonIndex::from(part.as_ref()),
+                },
+                (false, _) => JsonIndex::String(part.as_ref().to_string()),
+            };
+            json_path.push(index);
+        }
+
+        json_path
     }
 
-    /// Parse environment variables into json
-    pub fn parse_from_env(&self) -> Result<serde_json::Value, Error> {
-        self.parse_iter(env::vars())
+    /// Return `n` of a key part `-n` if negative indices are enabled, e.g. `1` for `-1`
+    fn negative_index(&self, part: impl AsRef<str>) -> Option<usize> {
+        let digits = part.as_ref().strip_prefix('-')?;
+        if !self.negative_indices || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        digits.parse().ok().filter(|n| *n > 0)
     }
 
-    /// Preprocess environment variables by filtering and sorting them
-    fn preprocess_vars(
+    #[cfg(feature = "base64")]
+    /// Decode a value marked as base64, removing the `b64` key part marker if present
+    fn decode_base64(
         &self,
-        vars: impl Iterator<Item = (String, String)>,
-    ) -> Result<Vec<(String, String)>, Error> {
-        let mut vars = if let Some(prefix) = &self.prefix {
-            let vars = vars.filter(|(key, _)| key.starts_with(prefix));
+        key: &str,
+        key_parts: &mut Vec<Cow<str>>,
+        value: &str,
+    ) -> Result<Option<String>, Error> {
+        if !self.base64_values {
+            return Ok(None);
+        }
 
-            #[cfg(feature = "filter")]
-            let vars = vars.filter(|(key, _)| self.is_key_valid(key));
-
-            vars.map(|(key, value)| {
-                Ok((
-                    key.strip_prefix(prefix)
-                        .ok_or_else(|| format!("key {key} does not match prefix {prefix}"))?
-                        .to_string(),
-                    value,
-                ))
-            })
-            .collect::<Result<Vec<_>, Error>>()?
+        let encoded = if key_parts.len() > 1 && key_parts.last().is_some_and(|part| part == "b64") {
+            key_parts.pop();
+            value
+        } else if let Some(encoded) = value.strip_prefix("base64:") {
+            encoded
         } else {
-            vars.collect::<Vec<_>>()
+            return Ok(None);
         };
 
-        // Sort in reverse order to ensure that the longest keys are processed first
-        vars.sort_by(|(key_a, _), (key_b, _)| key_b.cmp(key_a));
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|err| format!("invalid base64 value of {key}: {err}"))?;
 
-        Ok(vars)
+        String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|err| format!("base64 value of {key} is not valid UTF-8: {err}").into())
     }
 
-    #[cfg(feature = "filter")]
-    /// Check if a key is valid based on the include and exclude regex patterns
-    fn is_key_valid(&self, key: &str) -> bool {
-        // If include is empty, key is valid, else key must match at least one of the patterns
-        if !self.include.is_empty() && !self.include.iter().any(|pattern| pattern.is_match(key)) {
-            return false;
+    /// Whether leading zeros are kept for the value at the given path, see [`Parser::with_keep_leading_zeros_at`]
+    fn keep_leading_zeros_at_path(&self, path: &str) -> bool {
+        self.keep_leading_zeros_at
+            .get(path)
+            .copied()
+            .unwrap_or(self.keep_leading_zeros)
+    }
+
+    /// Coerce an environment variable value into a json number, bool or string
+    fn coerce_value(&self, value: Cow<str>, keep_leading_zeros: bool) -> Result<Value, Error> {
+        if value.is_empty() && self.empty_values == EmptyValues::Null {
+            return Ok(Value::Null);
         }
 
-        // If exclude is empty, key is valid, else key must not match any of the patterns
-        if !self.exclude.is_empty() && self.exclude.iter().any(|pattern| pattern.is_match(key)) {
-            return false;
+        if keep_leading_zeros && has_leading_zero(&value) {
+            return Ok(Value::String(value.into_owned()));
         }
 
-        true
+        for value_parser in &self.value_parsers {
+            if let Some(value) = value_parser.parse(&value, self)? {
+                return Ok(value);
+            }
+        }
+
+        Ok(Value::String(value.into_owned()))
     }
 
-    /// Parse iterator of String tuples into json
-    pub fn parse_iter(&self, vars: impl Iterator<Item = (String, String)>) -> Result<Value, Error> {
-        let vars = self.preprocess_vars(vars)?;
-        let mut json = self.json.clone();
-
-        for (key, env_value) in vars {
-            let key_parts = key
-                .split(&self.separator)
-                .map(|s| s.to_lowercase())
-                .collect::<Vec<_>>();
-
-            let env_value = if let Ok(value) = env_value.parse::<i64>() {
-                Value::Number(value.into())
-            } else if let Ok(value) = env_value.parse::<f64>() {
-                Value::Number(Number::from_f64(value).ok_or("Failed to parse float")?)
-            } else if let Ok(value) = env_value.parse::<bool>() {
-                Value::Bool(value)
-            } else {
-                Value::String(env_value)
-            };
+    /// Parse a float, in strict mode only if it survives the round trip through `f64` unchanged
+    pub(crate) fn parse_float(&self, value: &str) -> Result<Option<Number>, Error> {
+        let Ok(float) = value.parse::<f64>() else {
+            return Ok(None);
+        };
 
-            if key_parts.len() == 1 {
-                // Raise error if part is a number
-                if key_parts[0].parse::<usize>().is_ok() {
-                    return Err("First key part cannot be a number".into());
-                }
+        let number = Number::from_f64(float).ok_or("Failed to parse float")?;
+        if self.strict_numbers && number.to_string() != value {
+            return Ok(None);
+        }
 
-                json[key_parts[0].as_str()] = env_value.clone();
-                continue;
-            }
+        Ok(Some(number))
+    }
 
-            // Reverse key parts to iterate from the bottom up
-            // Index starts at len - 1
-            let mut part_value = PartValue::Object(env_value);
+    #[cfg(feature = "arbitrary_precision")]
+    /// Parse a json number literal without going through `f64`, preserving all digits
+    /// Requires the `arbitrary_precision` feature
+    pub(crate) fn parse_exact_number(value: &str) -> Option<Number> {
+        value.parse::<Number>().ok()
+    }
 
-            for (i, part) in key_parts.iter().cloned().enumerate().rev() {
-                // Query json, check if part exists in json
-                let indices = key_parts[..i + 1]
-                    .iter()
-                    .cloned()
-                    .map(JsonIndex::from)
-                    .collect::<Vec<_>>();
-
-                // If part exists, replace part value in json with env var value
-                if let Some(curr_part_value) = Self::json_get_mut(&mut json, &indices) {
-                    match part_value {
-                        PartValue::Object(value) => match curr_part_value {
-                            Value::Object(obj) => {
-                                let (k, v) = value
-                                    .as_object()
-                                    .ok_or(format!("Expected object, got: {:?}", value))?
-                                    .iter()
-                                    .next()
-                                    .unwrap();
-                                obj.insert(k.clone(), v.clone());
-                            }
-                            Value::Null => *curr_part_value = value,
-                            Value::Number(_) => *curr_part_value = value,
-                            Value::String(_) => *curr_part_value = value,
-                            Value::Bool(_) => *curr_part_value = value,
-                            _ => panic!("Unexpected value: {:?}", curr_part_value),
-                        },
-                        PartValue::ArrayItem(array_item) => {
-                            let arr = curr_part_value.as_array_mut().ok_or("Expected array")?;
-
-                            if array_item.index >= arr.len() {
-                                arr.resize_with(array_item.index + 1, || Value::Null);
-                            }
-
-                            arr[array_item.index] = array_item.value;
-                        }
-                    };
-                    break;
-                }
+    #[cfg(not(feature = "arbitrary_precision"))]
+    pub(crate) fn parse_exact_number(_value: &str) -> Option<Number> {
+        None
+    }
 
-                if indices.len() == 1 {
-                    json.as_object_mut().ok_or("Expected object")?.insert(
-                        part.to_string().to_lowercase(),
-                        part_value.into_json_value(),
-                    );
-                    break;
-                }
+    /// Get mutable reference to json value at indices
+    pub fn json_get_mut<'a>(json: &'a mut Value, indices: &[JsonIndex]) -> Option<&'a mut Value> {
+        path::get_mut(json, indices)
+    }
 
-                // If not, we create an Object or Array dependingo on part type (string or usize)
-                // If part is string, create an Object
-                if part.parse::<usize>().is_err() {
-                    part_value = PartValue::Object(json! {{ part: part_value.into_json_value() }});
-                    continue;
-                }
+    /// Set json value at path, building missing objects and arrays on the way and filling
+    /// skipped array items by the policy, the way variables are merged. Returns the previous
+    /// value, see [`path::set`] for the differences to merging variables
+    pub fn json_set(
+        json: &mut Value,
+        path: &JsonPath,
+        value: Value,
+        policy: &CreatePolicy,
+    ) -> Result<Option<Value>, Error> {
+        path::set_with(json, path, value, policy)
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+/// Parse the environment variables with the prefix into json, splitting keys by `__`.
+/// A shorthand for `Parser::default().with_prefix(prefix).parse_from_env()`
+///
+/// ```
+/// use env_vars_to_json::{env_to_json, testing::EnvGuard};
+/// use serde_json::json;
+///
+/// let _env = EnvGuard::new().set("SCRIPT__DB__PORT", "5432");
+///
+/// assert_eq!(env_to_json("SCRIPT__")?, json!({ "db": { "port": 5432 } }));
+/// # Ok::<(), env_vars_to_json::Error>(())
+/// ```
+pub fn env_to_json(prefix: &str) -> Result<Value, Error> {
+    Parser::default().with_prefix(prefix).parse_from_env()
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+/// Parse the environment variables with the prefix into json, splitting keys by the separator,
+/// e.g. `env_to_json_with("_", "APP_")`
+pub fn env_to_json_with(separator: &str, prefix: &str) -> Result<Value, Error> {
+    Parser::default()
+        .with_separator(separator)
+        .with_prefix(prefix)
+        .parse_from_env()
+}
+
+/// Wrap owned variables for the borrowing parse pipeline
+fn owned<'a>(vars: impl Iterator<Item = (String, String)>) -> impl Iterator<Item = Var<'a>> {
+    vars.map(|(key, value)| (Cow::Owned(key), Cow::Owned(value)))
+}
 
-                // If part is usize,  create an Array
-                let index = part.parse::<usize>().expect("This should never fail");
-                part_value =
-                    PartValue::ArrayItem(ArrayItem::new(index, part_value.into_json_value()));
+/// Strip the prefix off a key, reusing its allocation if owned
+fn strip_prefix<'a>(key: Cow<'a, str>, prefix: &str) -> Result<Cow<'a, str>, Error> {
+    match key {
+        Cow::Borrowed(key) => key
+            .strip_prefix(prefix)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| format!("key {key} does not match prefix {prefix}").into()),
+        Cow::Owned(mut key) => {
+            if !key.starts_with(prefix) {
+                return Err(format!("key {key} does not match prefix {prefix}").into());
             }
+            key.drain(..prefix.len());
+            Ok(Cow::Owned(key))
         }
-
-        Ok(json)
     }
+}
 
-    /// Get mutable reference to json value at indices
-    pub fn json_get_mut<'a>(
-        json: &'a mut Value,
-        indices: &'a [JsonIndex],
-    ) -> Option<&'a mut Value> {
-        let mut json = json;
-
-        for index in indices {
-            match index {
-                JsonIndex::String(key) => {
-                    json = json.get_mut(key)?;
-                }
-                JsonIndex::Usize(index) => {
-                    json = json.get_mut(index)?;
-                }
+/// Replace a value with a subslice of itself, only allocating if an owned value actually shrinks
+fn shrink(value: &mut Cow<str>, f: fn(&str) -> &str) {
+    match value {
+        Cow::Borrowed(borrowed) => *borrowed = f(borrowed),
+        Cow::Owned(owned) => {
+            let shrunk = f(owned);
+            if shrunk.len() != owned.len() {
+                *value = Cow::Owned(shrunk.to_string());
             }
         }
+    }
+}
+
+/// Strip a single trailing `\n` or `\r\n`
+fn strip_trailing_newline(value: &str) -> &str {
+    value
+        .strip_suffix('\n')
+        .map_or(value, |value| value.strip_suffix('\r').unwrap_or(value))
+}
+
+/// Lowercase a key part, only allocating if it contains uppercase characters
+fn lowercase(part: &str) -> Cow<'_, str> {
+    if part.chars().any(char::is_uppercase) {
+        Cow::Owned(part.to_lowercase())
+    } else {
+        Cow::Borrowed(part)
+    }
+}
+
+/// Check if a string is a decimal number with a redundant leading zero, e.g. `007` or `-00.5`
+fn has_leading_zero(s: &str) -> bool {
+    let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+    let integer = unsigned
+        .split_once('.')
+        .map_or(unsigned, |(integer, _)| integer);
+
+    integer.len() > 1 && integer.starts_with('0') && integer.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Check if a string is a canonical decimal number: no sign other than `-`, no leading zeros,
+/// no exponent and no trailing zeros in the fraction other than a single `.0`
+fn is_canonical_number(s: &str) -> bool {
+    let unsigned = s.strip_prefix('-').unwrap_or(s);
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+
+    let integer_valid = !integer.is_empty()
+        && integer.bytes().all(|b| b.is_ascii_digit())
+        && (integer == "0" || !integer.starts_with('0'));
+
+    let fraction_valid = match fraction {
+        None => true,
+        Some("0") => true,
+        Some(fraction) => {
+            !fraction.is_empty()
+                && fraction.bytes().all(|b| b.is_ascii_digit())
+                && !fraction.ends_with('0')
+        }
+    };
+
+    integer_valid && fraction_valid
+}
+
+/// Check if a string is a decimal integer literal, optionally signed
+fn is_integer_literal(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parse a hexadecimal (`0x`), octal (`0o`) or binary (`0b`) integer literal, optionally negative
+fn parse_radix_literal(s: &str) -> Option<i64> {
+    let (sign, literal) = match s.strip_prefix('-') {
+        Some(literal) => (-1, literal),
+        None => (1, s),
+    };
+
+    let radix = match literal.get(..2)? {
+        "0x" | "0X" => 16,
+        "0o" | "0O" => 8,
+        "0b" | "0B" => 2,
+        _ => return None,
+    };
+
+    let digits = &literal[2..];
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+
+    i64::from_str_radix(digits, radix)
+        .ok()
+        .map(|value| sign * value)
+}
+
+#[cfg(test)]
+mod test_util {
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    /// Return owned variables from `(key, value)` pairs, in the given order
+    pub fn vars(vars: &[(&str, &str)]) -> std::vec::IntoIter<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
 
-        Some(json)
+    /// Poll a future which never waits to completion
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        let mut context = Context::from_waker(Waker::noop());
+        let mut future = pin!(future);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
     }
 }
 
@@ -385,10 +2134,35 @@ mod tests {
         #[cfg(feature = "filter")]
         #[serde(default)]
         exclude: Vec<&'a str>,
-        env_vars: HashMap<&'a str, &'a str>,
+
+        #[serde(default)]
+        radix_literals: bool,
+
+        #[serde(default)]
+        big_ints_as_strings: bool,
+
+        #[serde(default)]
+        strict_numbers: bool,
+
+        #[serde(default = "default_true")]
+        keep_leading_zeros: bool,
+
+        #[serde(default)]
+        keep_leading_zeros_at: HashMap<&'a str, bool>,
+
+        #[serde(default)]
+        trim_values: bool,
+
+        #[serde(default)]
+        trim_trailing_newline: bool,
+        env_vars: HashMap<&'a str, String>,
         expected: String,
     }
 
+    fn default_true() -> bool {
+        true
+    }
+
     impl TestCase<'_> {
         pub fn from_yaml(yaml: &'static str) -> Self {
             serde_yaml::from_str(yaml).expect("failed to parse yaml")
@@ -400,10 +2174,11 @@ mod tests {
                 .map(|(k, v)| (k.to_string(), v.to_string()))
         }
 
-        pub fn set_vars(&self) {
-            for (k, v) in self.env_vars.iter() {
-                std::env::set_var(k, v);
-            }
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        pub fn set_vars(&self) -> crate::testing::EnvGuard {
+            self.env_vars
+                .iter()
+                .fold(crate::testing::EnvGuard::new(), |env, (k, v)| env.set(k, v))
         }
 
         pub fn assert(&self, actual: &serde_json::Value) {
@@ -415,7 +2190,18 @@ mod tests {
 
     impl From<&TestCase<'_>> for Parser {
         fn from(test_case: &TestCase) -> Self {
-            let mut parser = Parser::default().with_separator(test_case.separator);
+            let mut parser = Parser::default()
+                .with_separator(test_case.separator)
+                .with_radix_literals(test_case.radix_literals)
+                .with_big_ints_as_strings(test_case.big_ints_as_strings)
+                .with_strict_numbers(test_case.strict_numbers)
+                .with_keep_leading_zeros(test_case.keep_leading_zeros)
+                .with_trim_values(test_case.trim_values)
+                .with_trim_trailing_newline(test_case.trim_trailing_newline);
+
+            for (path, keep) in &test_case.keep_leading_zeros_at {
+                parser = parser.with_keep_leading_zeros_at(*path, *keep);
+            }
 
             if let Some(prefix) = test_case.prefix {
                 parser = parser.with_prefix(prefix);
@@ -544,6 +2330,215 @@ mod tests {
           }
     "#
     )]
+    #[case::radix_literals(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        radix_literals: true
+        env_vars:
+            PREFIX__HEX: "0x1F"
+            PREFIX__OCTAL: "0o755"
+            PREFIX__BINARY: "0b1010"
+            PREFIX__NEGATIVE: "-0x10"
+            PREFIX__INVALID: "0xZZ"
+            PREFIX__SIGNED_DIGITS: "0x-1"
+        expected: |
+            {
+                "hex": 31,
+                "octal": 493,
+                "binary": 10,
+                "negative": -16,
+                "invalid": "0xZZ",
+                "signed_digits": "0x-1"
+            }
+        "#
+    )]
+    #[case::radix_literals_disabled(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        env_vars:
+            PREFIX__HEX: "0x1F"
+        expected: |
+            {
+                "hex": "0x1F"
+            }
+        "#
+    )]
+    #[case::u64(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        env_vars:
+            PREFIX__ID: "18446744073709551615"
+            PREFIX__MIN: "-9223372036854775808"
+        expected: |
+            {
+                "id": 18446744073709551615,
+                "min": -9223372036854775808
+            }
+        "#
+    )]
+    #[case::big_ints_as_strings(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        big_ints_as_strings: true
+        env_vars:
+            PREFIX__ID: "18446744073709551615"
+            PREFIX__TOO_BIG: "18446744073709551616"
+            PREFIX__FLOAT: "1.5"
+        expected: |
+            {
+                "id": 18446744073709551615,
+                "too_big": "18446744073709551616",
+                "float": 1.5
+            }
+        "#
+    )]
+    #[case::strict_numbers(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        strict_numbers: true
+        env_vars:
+            PREFIX__VERSION: "1.10"
+            PREFIX__EXPONENT: "1e5"
+            PREFIX__LEADING_ZERO: "08"
+            PREFIX__PLUS: "+5"
+            PREFIX__INT: "-12"
+            PREFIX__FLOAT: "1.5"
+            PREFIX__WHOLE_FLOAT: "2.0"
+            PREFIX__ZERO: "0.25"
+        expected: |
+            {
+                "version": "1.10",
+                "exponent": "1e5",
+                "leading_zero": "08",
+                "plus": "+5",
+                "int": -12,
+                "float": 1.5,
+                "whole_float": 2.0,
+                "zero": 0.25
+            }
+        "#
+    )]
+    #[case::keep_leading_zeros(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        keep_leading_zeros_at:
+            count: false
+        env_vars:
+            PREFIX__AGENT: "007"
+            PREFIX__ZIP: "0012345"
+            PREFIX__COUNT: "010"
+            PREFIX__ZERO: "0"
+            PREFIX__FRACTION: "0.5"
+        expected: |
+            {
+                "agent": "007",
+                "zip": "0012345",
+                "count": 10,
+                "zero": 0,
+                "fraction": 0.5
+            }
+        "#
+    )]
+    #[case::coerce_leading_zeros(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        keep_leading_zeros: false
+        keep_leading_zeros_at:
+            zip: true
+        env_vars:
+            PREFIX__AGENT: "007"
+            PREFIX__ZIP: "0012345"
+        expected: |
+            {
+                "agent": 7,
+                "zip": "0012345"
+            }
+        "#
+    )]
+    #[case::trim_values(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        trim_values: true
+        env_vars:
+            PREFIX__SECRET: "secret\n"
+            PREFIX__INT: " 1 "
+        expected: |
+            {
+                "secret": "secret",
+                "int": 1
+            }
+        "#
+    )]
+    #[case::trim_trailing_newline(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        trim_trailing_newline: true
+        env_vars:
+            PREFIX__SECRET: " secret\r\n"
+            PREFIX__MULTILINE: "a\nb\n\n"
+        expected: |
+            {
+                "secret": " secret",
+                "multiline": "a\nb\n"
+            }
+        "#
+    )]
+    #[case::arrays_of_objects(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        env_vars:
+            PREFIX__SERVERS__0__HOST: a.example.com
+            PREFIX__SERVERS__0__PORT: "80"
+            PREFIX__SERVERS__2__HOST: c.example.com
+            PREFIX__SERVERS__2__TAGS__1: blue
+        expected: |
+            {
+                "servers": [
+                    { "host": "a.example.com", "port": 80 },
+                    null,
+                    { "host": "c.example.com", "tags": [null, "blue"] }
+                ]
+            }
+        "#
+    )]
+    #[case::arrays_of_objects_partial_base(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        json: |
+            {
+                "servers": [
+                    { "host": "a.example.com", "port": 80 },
+                    null,
+                    "placeholder"
+                ]
+            }
+        env_vars:
+            PREFIX__SERVERS__0__PORT: "8080"
+            PREFIX__SERVERS__1__HOST: b.example.com
+            PREFIX__SERVERS__2__HOST: c.example.com
+            PREFIX__SERVERS__3__HOST: d.example.com
+        expected: |
+            {
+                "servers": [
+                    { "host": "a.example.com", "port": 8080 },
+                    { "host": "b.example.com" },
+                    { "host": "c.example.com" },
+                    { "host": "d.example.com" }
+                ]
+            }
+        "#
+    )]
     fn test_parse_iter(#[case] test_yaml: &'static str) -> Result<(), Error> {
         let test_case = TestCase::from_yaml(test_yaml);
         let env_vars_to_json = Parser::from(&test_case);
@@ -836,6 +2831,1052 @@ mod tests {
         Ok(())
     }
 
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Config {
+        server: Server,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Server {
+        port: u16,
+        hosts: Vec<String>,
+    }
+
+    #[rstest]
+    #[case::value(
+        &[("PREFIX__SERVER__PORT", "http"), ("PREFIX__SERVER__HOSTS__0", "localhost")],
+        "failed to deserialize `server.port` (from environment variable `PREFIX__SERVER__PORT`): \
+         invalid type: string \"http\", expected u16"
+    )]
+    #[case::gap(
+        &[("PREFIX__SERVER__PORT", "80"), ("PREFIX__SERVER__HOSTS__1", "1")],
+        "failed to deserialize `server.hosts[0]`: invalid type: null, expected a string"
+    )]
+    #[case::missing(
+        &[("PREFIX__SERVER__HOSTS__0", "localhost")],
+        "failed to deserialize `server` (from environment variable `PREFIX__SERVER__HOSTS__0`): \
+         missing field `port`"
+    )]
+    fn test_parse_into_error(#[case] vars: &[(&str, &str)], #[case] expected: &str) {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let err = parser
+            .parse_into::<Config>(test_util::vars(vars))
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), expected);
+    }
+
+    #[test]
+    fn test_parse_str() -> Result<(), Error> {
+        let text = "# defaults\nPREFIX__STRUCT__INT=1\nexport PREFIX__STRUCT__STRING=\"a b\"\n";
+        let actual = Parser::default().with_prefix("PREFIX__").parse_str(text)?;
+        assert_eq!(actual, json!({ "struct": { "int": 1, "string": "a b" } }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_dotenv_round_trip() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let value = json!({
+            "int_list": [1, 2],
+            "struct": {
+                "float": 1.5,
+                "quoted": "say \"hi\" # not a comment",
+                "multiline": "line1\nline2\ttab",
+                "shell": "$HOME `pwd` \\",
+                "empty": ""
+            }
+        });
+
+        let dotenv = parser.to_dotenv(&value)?;
+        assert!(dotenv.contains("PREFIX__STRUCT__MULTILINE=\"line1\\nline2\\ttab\"\n"));
+        assert_eq!(parser.parse_str(&dotenv)?, value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_snapshot() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let old = source::EnvSnapshot::from_iter([("PREFIX__INT", "1"), ("OTHER", "a")]);
+
+        let unrelated = source::EnvSnapshot::from_iter([("PREFIX__INT", "1"), ("OTHER", "b")]);
+        assert!(!parser.is_affected_by(&old.diff(&unrelated)));
+
+        let new = source::EnvSnapshot::from_iter([("PREFIX__INT", "2"), ("OTHER", "a")]);
+        assert!(parser.is_affected_by(&old.diff(&new)));
+        assert_eq!(parser.parse_snapshot(&new)?, json!({ "int": 2 }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assignments() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let vars = test_util::vars(&[
+            ("PREFIX__LIST__1", "2"),
+            ("PREFIX__STRUCT__INT", "1"),
+            ("OTHER", "x"),
+        ]);
+
+        let assignments = parser
+            .assignments(vars.into_iter())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            assignments,
+            vec![
+                (JsonIndex::from_vec(vec!["struct", "int"]).into(), json!(1)),
+                (JsonIndex::from_vec(vec!["list", "1"]).into(), json!(2)),
+            ]
+        );
+
+        // End-relative indices count from the end of the base json's array, as when parsing
+        let parser = Parser::default()
+            .with_negative_indices(true)
+            .with_json(json!({ "hosts": ["a", "b"] }));
+        let vars = || [("HOSTS__-1".to_string(), "c".to_string())].into_iter();
+        let assignments = parser.assignments(vars()).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            assignments,
+            vec![(
+                vec![JsonIndex::from("hosts"), JsonIndex::Usize(2)].into(),
+                json!("c")
+            )]
+        );
+        assert_eq!(
+            parser.parse_iter(vars())?,
+            json!({ "hosts": ["a", "b", "c"] })
+        );
+
+        let parser = Parser::default().with_separator("");
+        let mut errors = parser.assignments(std::iter::empty());
+        assert!(matches!(errors.next(), Some(Err(Error::Config(_)))));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[rstest]
+    #[case::sorted(false, r#"{"base":0,"struct":{"string":"a","int":1},"list":[true]}"#)]
+    #[case::declaration(true, r#"{"base":0,"list":[true],"struct":{"int":1,"string":"a"}}"#)]
+    fn test_parse_iter_order(
+        #[case] declaration_order: bool,
+        #[case] expected: &str,
+    ) -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "base": 0 }))
+            .with_declaration_order(declaration_order);
+        let vars = [
+            ("PREFIX__LIST__0", "true"),
+            ("PREFIX__STRUCT__INT", "1"),
+            ("PREFIX__STRUCT__STRING", "a"),
+        ];
+
+        assert_eq!(
+            parser.parse_iter_ref(vars.into_iter())?.to_string(),
+            expected
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_sort_keys() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "z": 0, "struct": { "y": 0 } }))
+            .with_declaration_order(true)
+            .with_sort_keys(true);
+        let vars = [("PREFIX__STRUCT__X", "1"), ("PREFIX__A", "2")];
+
+        assert_eq!(
+            parser.parse_iter_ref(vars.into_iter())?.to_string(),
+            r#"{"a":2,"struct":{"x":1,"y":0},"z":0}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_to_compose_env() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("APP__")
+            .with_json(json!({ "log": { "level": "info" } }));
+        let vars = test_util::vars(&[("APP__DB__URL", "postgres://db/app?x=$y")]);
+
+        assert_eq!(
+            parser.parse_to_compose_env(vars.into_iter())?,
+            "# from APP__DB__URL\n\
+             APP__DB__URL='postgres://db/app?x=$y'\n\
+             # from the base json\n\
+             APP__LOG__LEVEL=info\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_overrides() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_overrides(HashMap::from([
+                ("PREFIX__DB__PORT", "6543"),
+                ("PREFIX__FEATURE__BETA", "true"),
+                ("OTHER__FLAG", "true"),
+            ]));
+
+        assert_eq!(
+            parser.parse_iter_ref(
+                [
+                    ("PREFIX__DB__PORT", "5432"),
+                    ("PREFIX__DB__HOST", "localhost")
+                ]
+                .into_iter()
+            )?,
+            json!({
+                "db": { "host": "localhost", "port": 6543 },
+                "feature": { "beta": true }
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_require_match() -> Result<(), Error> {
+        let vars = [("PREFX__PORT", "80"), ("PREFIX__", "")];
+
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_skip_empty_values(true);
+        assert_eq!(parser.parse_iter_ref(vars.into_iter())?, json!({}));
+
+        let parser = parser.with_require_match(true);
+        let err = parser.parse_iter_ref(vars.into_iter()).unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::NoMatch { prefix: Some(prefix) } if prefix == "PREFIX__"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "no environment variables selected with prefix `PREFIX__`"
+        );
+        assert_eq!(
+            parser.parse_iter_ref([("PREFIX__PORT", "80")].into_iter())?,
+            json!({ "port": 80 })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_max_depth() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__").with_max_depth(2);
+
+        assert_eq!(
+            parser.parse_iter_ref([("PREFIX__STRUCT__INT", "1")].into_iter())?,
+            json!({ "struct": { "int": 1 } })
+        );
+
+        let err = parser
+            .parse_iter_ref([("PREFIX__A__B__C", "1")].into_iter())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TooDeep { var, depth: 3, max_depth: 2 } if var == "PREFIX__A__B__C"
+        ));
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("PREFIX__FOO____BAR", 1, "consecutive separators", Some(json!({ "foo": { "bar": 1 } })))]
+    #[case("PREFIX__FOO__", 1, "a trailing separator", Some(json!({ "foo": 1 })))]
+    #[case("PREFIX____FOO", 0, "a leading separator", Some(json!({ "foo": 1 })))]
+    #[case("PREFIX__", 0, "an empty key", None)]
+    fn test_parse_iter_empty_key_parts(
+        #[case] key: &str,
+        #[case] expected_index: usize,
+        #[case] expected_cause: &str,
+        #[case] collapsed: Option<Value>,
+    ) -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_empty_key_parts(EmptyKeyParts::Error);
+        let err = parser.parse_iter_ref([(key, "1")].into_iter()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "environment variable `{key}` has an empty key part at index {expected_index}, from {expected_cause}"
+            )
+        );
+
+        let parser = parser.with_empty_key_parts(EmptyKeyParts::Collapse);
+        let result = parser.parse_iter_ref([(key, "1")].into_iter());
+        match collapsed {
+            Some(expected) => assert_eq!(result?, expected),
+            None => assert!(matches!(result, Err(Error::EmptyKeyPart { .. }))),
+        }
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::global(
+        Parser::default().with_numeric_object_keys(true),
+        json!({ "listeners": { "8080": { "tls": true } }, "ports": { "0": 80 } })
+    )]
+    #[case::per_path(
+        Parser::default().with_numeric_object_keys_at("listeners", true),
+        json!({ "listeners": { "8080": { "tls": true } }, "ports": [80] })
+    )]
+    #[case::per_path_override(
+        Parser::default()
+            .with_numeric_object_keys(true)
+            .with_numeric_object_keys_at("ports", false),
+        json!({ "listeners": { "8080": { "tls": true } }, "ports": [80] })
+    )]
+    fn test_parse_iter_numeric_object_keys(
+        #[case] parser: Parser,
+        #[case] expected: Value,
+    ) -> Result<(), Error> {
+        let vars = [("LISTENERS__8080__TLS", "true"), ("PORTS__0", "80")];
+
+        assert_eq!(parser.parse_iter_ref(vars.into_iter())?, expected);
+
+        let assignments = parser
+            .assignments(test_util::vars(&vars))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            assignments[1].0,
+            vec![
+                JsonIndex::String("listeners".to_string()),
+                JsonIndex::String("8080".to_string()),
+                JsonIndex::String("tls".to_string()),
+            ]
+            .into()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_redacted() {
+        let parser = Parser::default().with_secret("db__password");
+        let value = json!("hunter2");
+        let key_parts = |key: &'static str| key.split("__").map(Cow::Borrowed).collect::<Vec<_>>();
+
+        assert_eq!(
+            parser.redacted(&key_parts("db__password"), &value).as_ref(),
+            &json!("***")
+        );
+        assert_eq!(
+            parser
+                .redacted(&key_parts("db__password__0"), &value)
+                .as_ref(),
+            &json!("***")
+        );
+        assert_eq!(
+            parser.redacted(&key_parts("db__host"), &value).as_ref(),
+            &value
+        );
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn test_parse_iter_globs() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_include(&["^PREFIX__INT$"])
+            .with_include_globs(&["PREFIX__DB__*"])
+            .with_exclude_globs(&["*_SECRET"]);
+        let vars = test_util::vars(&[
+            ("PREFIX__INT", "1"),
+            ("PREFIX__DB__HOST", "localhost"),
+            ("PREFIX__DB__API_SECRET", "x"),
+            ("PREFIX__MY_DB__HOST", "skipped"),
+        ]);
+
+        assert_eq!(
+            parser.parse_iter(vars.into_iter())?,
+            json!({ "int": 1, "db": { "host": "localhost" } })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_filter() -> Result<(), Error> {
+        let allowed = BTreeSet::from(["PREFIX__INT", "PREFIX__EMPTY", "NAME"]);
+        let parser = Parser::default()
+            .with_filter(move |key, _| allowed.contains(key))
+            .with_filter(|_, value| !value.is_empty());
+        let vars = test_util::vars(&[
+            ("PREFIX__INT", "1"),
+            ("PREFIX__EMPTY", ""),
+            ("PREFIX__OTHER", "2"),
+            ("NAME", "app"),
+        ]);
+
+        assert_eq!(
+            parser.parse_iter(vars.clone())?,
+            json!({ "prefix": { "int": 1 }, "name": "app" })
+        );
+        assert_eq!(
+            parser.with_prefix("PREFIX__").skipped(vars.into_iter()),
+            [
+                ("PREFIX__EMPTY".to_string(), SkipReason::Filter),
+                ("PREFIX__OTHER".to_string(), SkipReason::Filter),
+                ("NAME".to_string(), SkipReason::Prefix),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::string(EmptyValues::String, json!({ "name": "", "list": ["", "b"] }))]
+    #[case::null(EmptyValues::Null, json!({ "name": null, "list": [null, "b"] }))]
+    #[case::skip(EmptyValues::Skip, json!({ "name": "base", "list": ["a", "b"] }))]
+    #[case::erase(EmptyValues::Erase, json!({ "list": ["b"] }))]
+    fn test_parse_iter_empty_values(
+        #[case] empty_values: EmptyValues,
+        #[case] expected: Value,
+    ) -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_json(json!({ "name": "base", "list": ["a", "b"] }))
+            .with_empty_values(empty_values);
+        let vars = test_util::vars(&[("NAME", ""), ("LIST__0", "")]);
+
+        assert_eq!(parser.parse_iter(vars.into_iter())?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_tombstone() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "db": { "host": "localhost", "port": 5432 }, "list": ["a", "b"] }))
+            .with_tombstone("__UNSET__");
+        let vars = test_util::vars(&[
+            ("PREFIX__DB__PORT", "__UNSET__"),
+            ("PREFIX__LIST__0", "__UNSET__"),
+            ("PREFIX__MISSING", "__UNSET__"),
+            ("PREFIX__NAME", "app"),
+        ]);
+
+        assert_eq!(
+            parser.parse_iter(vars.into_iter())?,
+            json!({ "db": { "host": "localhost" }, "list": ["b"], "name": "app" })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_value_filters() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_skip_empty_values(true);
+        #[cfg(feature = "filter")]
+        let parser = parser.with_value_exclude(&["^vault:"]);
+
+        let vars = test_util::vars(&[
+            ("PREFIX__INT", "1"),
+            ("PREFIX__EMPTY", ""),
+            ("PREFIX__TOKEN", "vault:secret/token"),
+        ]);
+
+        #[cfg(feature = "filter")]
+        let expected = json!({ "int": 1 });
+        #[cfg(not(feature = "filter"))]
+        let expected = json!({ "int": 1, "token": "vault:secret/token" });
+        assert_eq!(parser.parse_iter(vars.clone())?, expected);
+
+        #[cfg(feature = "filter")]
+        assert_eq!(
+            parser
+                .with_value_include(&["^[0-9]+$"])
+                .skipped(vars.into_iter()),
+            [
+                ("PREFIX__EMPTY".to_string(), SkipReason::Empty),
+                ("PREFIX__TOKEN".to_string(), SkipReason::Value),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn test_parse_iter_case_insensitive_filters() -> Result<(), Error> {
+        let vars = test_util::vars(&[
+            ("Prefix__Db__Host", "localhost"),
+            ("Prefix__db__port", "5432"),
+            ("Prefix__Http__Port", "80"),
+        ]);
+        let parser = Parser::default()
+            .with_prefix("Prefix__")
+            .with_include(&["^DB__"])
+            .with_filters_without_prefix(true);
+
+        assert_eq!(parser.parse_iter(vars.clone())?, json!({}));
+        assert_eq!(
+            parser
+                .with_case_insensitive_filters(true)
+                .parse_iter(vars.into_iter())?,
+            json!({ "db": { "host": "localhost", "port": 5432 } })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skipped() {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        #[cfg(feature = "filter")]
+        let parser = parser
+            .with_include(&["^PREFIX__STRUCT__"])
+            .with_exclude(&["SECRET"]);
+
+        let vars = test_util::vars(&[
+            ("PREFIX__STRUCT__INT", "1"),
+            ("PREFIX__STRUCT__SECRET", "x"),
+            ("PREFIX__LIST__0", "1"),
+            ("PERFIX__STRUCT__INT", "1"),
+        ]);
+
+        #[cfg(feature = "filter")]
+        let expected = vec![
+            ("PREFIX__STRUCT__SECRET".to_string(), SkipReason::Exclude),
+            ("PREFIX__LIST__0".to_string(), SkipReason::Include),
+            ("PERFIX__STRUCT__INT".to_string(), SkipReason::Prefix),
+        ];
+        #[cfg(not(feature = "filter"))]
+        let expected = vec![("PERFIX__STRUCT__INT".to_string(), SkipReason::Prefix)];
+
+        assert_eq!(parser.skipped(vars.clone()), expected);
+        assert!(Parser::default().skipped(vars.into_iter()).is_empty());
+    }
+
+    #[test]
+    fn test_parse_iter_profiles() -> Result<(), Error> {
+        let vars = test_util::vars(&[
+            ("PREFIX__PORT", "80"),
+            ("PREFIX__HOST", "localhost"),
+            ("PREFIX__PROD__PORT", "443"),
+            ("PREFIX__DEV__DEBUG", "true"),
+        ]);
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_profiles(["dev", "prod"]);
+
+        assert_eq!(
+            parser.parse_iter(vars.clone())?,
+            json!({ "port": 80, "host": "localhost" })
+        );
+        assert_eq!(
+            parser
+                .clone()
+                .with_active_profile("PROD")
+                .parse_iter(vars.clone())?,
+            json!({ "port": 443, "host": "localhost" })
+        );
+        assert_eq!(
+            parser
+                .clone()
+                .with_active_profile("dev")
+                .skipped(vars.into_iter()),
+            [("PREFIX__PROD__PORT".to_string(), SkipReason::Profile)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_prefix_map() -> Result<(), Error> {
+        let vars = test_util::vars(&[
+            ("APP__DB__HOST", "localhost"),
+            ("APP__DB__REPLICA__HOST", "replica"),
+            ("APP__HTTP__PORTS__0", "80"),
+            ("APP__OTHER", "skipped"),
+        ]);
+        let parser = Parser::default().with_prefix("APP__").with_prefix_map([
+            ("DB__", "database"),
+            ("DB__REPLICA__", "replica"),
+            ("HTTP__", "services__server"),
+        ]);
+
+        assert_eq!(
+            parser.parse_iter(vars.clone())?,
+            json!({
+                "database": { "host": "localhost" },
+                "replica": { "host": "replica" },
+                "services": { "server": { "ports": [80] } }
+            })
+        );
+        assert_eq!(
+            parser.skipped(vars.into_iter()),
+            [("APP__OTHER".to_string(), SkipReason::Prefix)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_mount() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "services": { "db": { "port": 5432 } } }))
+            .with_mount("services__api");
+
+        assert_eq!(
+            parser.parse_iter(test_util::vars(&[
+                ("PREFIX__PORT", "80"),
+                ("PREFIX__HOSTS__0", "a")
+            ]))?,
+            json!({
+                "services": {
+                    "db": { "port": 5432 },
+                    "api": { "port": 80, "hosts": ["a"] }
+                }
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_bracket_indices() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_bracket_indices(true)
+            .with_secret("servers__[1]__password");
+        let vars = test_util::vars(&[
+            ("PREFIX__SERVERS[0]__HOST", "a"),
+            ("PREFIX__SERVERS[1]__PASSWORD", "x"),
+            ("PREFIX__PORTS__8080", "http"),
+            ("PREFIX__MATRIX[1][0]", "1"),
+        ]);
+
+        assert_eq!(
+            parser.parse_iter(vars.into_iter())?,
+            json!({
+                "servers": [{ "host": "a" }, { "password": "x" }],
+                "ports": { "8080": "http" },
+                "matrix": [null, [1]]
+            })
+        );
+        assert!(parser.is_secret(&[
+            Cow::Borrowed("servers"),
+            Cow::Borrowed("[1]"),
+            Cow::Borrowed("password")
+        ]));
+
+        let err = parser
+            .parse_iter([("PREFIX__SERVERS[X]".to_string(), "a".to_string())].into_iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Encountered error while parsing environment variables: \
+             invalid array index in key part `SERVERS[X]`, expected e.g. `[0]`"
+        );
+
+        Ok(())
+    }
+
+    /// Splits keys by the separator and by `.`
+    struct Dotted;
+
+    impl KeySegmenter for Dotted {
+        fn segments<'k>(&self, key: &'k str, separator: &str) -> Result<Vec<Cow<'k, str>>, Error> {
+            Ok(key
+                .split(separator)
+                .flat_map(|part| part.split('.'))
+                .map(Cow::Borrowed)
+                .collect())
+        }
+    }
+
+    #[rstest]
+    #[case::before(Parser::default().with_key_segmenter(Box::new(Dotted)).with_bracket_indices(false))]
+    #[case::after(Parser::default().with_bracket_indices(false).with_key_segmenter(Box::new(Dotted)))]
+    fn test_key_segmenter_with_bracket_indices(#[case] parser: Parser) -> Result<(), Error> {
+        assert_eq!(
+            parser.parse_iter_ref([("DB.HOSTS__0", "a")].into_iter())?,
+            json!({ "db": { "hosts": ["a"] } })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_negative_indices() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "hosts": ["a", "b"] }))
+            .with_negative_indices(true);
+        let vars = test_util::vars(&[
+            ("PREFIX__HOSTS__-1", "c"),
+            ("PREFIX__HOSTS__-2", "d"),
+            ("PREFIX__PORTS__-1", "80"),
+            ("PREFIX__NAMES__-0", "x"),
+        ]);
+
+        assert_eq!(
+            parser.parse_iter(vars.clone())?,
+            json!({ "hosts": ["a", "b", "c", "d"], "ports": [80], "names": { "-0": "x" } })
+        );
+
+        // End-relative indices count from the document merged into, not from the parser's json
+        let hosts = || [("PREFIX__HOSTS__-1".to_string(), "c".to_string())].into_iter();
+        assert_eq!(
+            parser.clone().parse_iter_into(hosts())?,
+            json!({ "hosts": ["a", "b", "c"] })
+        );
+        assert_eq!(
+            parser.parse_iter_to(json!({ "hosts": ["x", "y", "z"] }), hosts())?,
+            json!({ "hosts": ["x", "y", "z", "c"] })
+        );
+        let mut json = json!({ "hosts": ["x"] });
+        parser.merge_into(&mut json, hosts())?;
+        assert_eq!(json, json!({ "hosts": ["x", "c"] }));
+        assert!(matches!(
+            parser
+                .with_negative_indices(false)
+                .parse_iter(vars.into_iter()),
+            Err(Error::Conflict { .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_lowercase_keys() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_lowercase_keys(false)
+            .with_secret("Db__passWord");
+        let vars = test_util::vars(&[("PREFIX__logLevel", "debug"), ("PREFIX__Db__passWord", "x")]);
+
+        assert_eq!(
+            parser.parse_iter(vars.clone())?,
+            json!({ "logLevel": "debug", "Db": { "passWord": "x" } })
+        );
+        assert!(parser.is_secret(&[Cow::Borrowed("Db"), Cow::Borrowed("passWord")]));
+        assert_eq!(
+            parser
+                .with_lowercase_keys(true)
+                .parse_iter(vars.into_iter())?,
+            json!({ "loglevel": "debug", "db": { "password": "x" } })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_from_async() -> Result<(), Error> {
+        struct RemoteEnv;
+
+        impl AsyncEnvSource for RemoteEnv {
+            async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+                Ok(vec![("PREFIX__INT".to_string(), "1".to_string())])
+            }
+        }
+
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let fake = source::FakeEnv::new().with("PREFIX__INT", "2");
+
+        assert_eq!(
+            test_util::block_on(parser.parse_from_async(&RemoteEnv))?,
+            json!({ "int": 1 })
+        );
+        assert_eq!(
+            test_util::block_on(parser.parse_from_async(&fake))?,
+            json!({ "int": 2 })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_map() -> Result<(), Error> {
+        let vars = std::collections::HashMap::from([
+            ("PREFIX__INT".to_string(), "1".to_string()),
+            ("OTHER".to_string(), "skipped".to_string()),
+        ]);
+
+        assert_eq!(
+            Parser::default().with_prefix("PREFIX__").parse_map(&vars)?,
+            json!({ "int": 1 })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_ref() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_trim_values(true);
+        let vars = [
+            ("PREFIX__STRUCT__STRING", " hello "),
+            ("PREFIX__STRUCT__INT", "1"),
+            ("PREFIX__LIST__1", "true"),
+            ("OTHER", "x"),
+        ];
+
+        let expected = json!({
+            "struct": { "string": "hello", "int": 1 },
+            "list": [null, true]
+        });
+        assert_eq!(parser.parse_iter_ref(vars.into_iter())?, expected);
+        assert_eq!(parser.parse_iter(test_util::vars(&vars))?, expected);
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::object_for_array(json!({ "list": { "a": 1 } }), "PREFIX__LIST__0", "list")]
+    #[case::array_for_object(json!({ "list": [1] }), "PREFIX__LIST__A", "list")]
+    #[case::container_for_value(json!({ "struct": { "a": 1 } }), "PREFIX__STRUCT", "struct")]
+    #[case::root(json!([]), "PREFIX__INT", "")]
+    fn test_parse_iter_conflict(
+        #[case] json: Value,
+        #[case] key: &str,
+        #[case] expected_path: &str,
+    ) {
+        let parser = Parser::default().with_prefix("PREFIX__").with_json(json);
+        let err = parser
+            .parse_iter([(key.to_string(), "1".to_string())].into_iter())
+            .unwrap_err();
+
+        assert!(matches!(err, Error::Conflict { path, .. } if path.to_string() == expected_path));
+    }
+
+    #[test]
+    fn test_parse_iter_into() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "struct": { "int": 0, "string": "default" } }));
+        let vars = [("PREFIX__STRUCT__INT".to_string(), "1".to_string())];
+
+        let expected = json!({ "struct": { "int": 1, "string": "default" } });
+        assert_eq!(
+            parser.clone().parse_iter(vars.clone().into_iter())?,
+            expected
+        );
+        assert_eq!(parser.parse_iter_into(vars.into_iter())?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_to_json() -> Result<(), Error> {
+        let _env = crate::testing::EnvGuard::new()
+            .set("ENV_TO_JSON_TEST__A__B", "1")
+            .set("ENV_TO_JSON_WITH_C_D", "true");
+
+        assert_eq!(
+            env_to_json("ENV_TO_JSON_TEST__")?,
+            json!({ "a": { "b": 1 } })
+        );
+        assert_eq!(
+            env_to_json_with("_", "ENV_TO_JSON_WITH_")?,
+            json!({ "c": { "d": true } })
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("DB__HOST", "db.host")]
+    #[case("HOSTS__2", "hosts[2]")]
+    #[case("HOSTS__0__NAME", "hosts[0].name")]
+    #[case("NAME", "name")]
+    fn test_json_set_matches_parser(#[case] key: &str, #[case] path: &str) -> Result<(), Error> {
+        let base = json!({ "db": "localhost", "hosts": ["a"], "name": null });
+        let parser = Parser::default().with_json(base.clone());
+
+        let mut json = base;
+        Parser::json_set(
+            &mut json,
+            &path.parse()?,
+            json!("x"),
+            &CreatePolicy::FillNull,
+        )?;
+        assert_eq!(parser.parse_iter_ref([(key, "x")].into_iter())?, json);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_into() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_range("port", 1..=65535);
+        let mut json = json!({ "port": 80, "name": "app" });
+
+        parser.merge_into(
+            &mut json,
+            [("PREFIX__PORT".to_string(), "8080".to_string())].into_iter(),
+        )?;
+        assert_eq!(json, json!({ "port": 8080, "name": "app" }));
+
+        // Invalid values leave the document unchanged
+        let vars = [
+            ("PREFIX__NAME".to_string(), "other".to_string()),
+            ("PREFIX__PORT".to_string(), "0".to_string()),
+        ];
+        assert!(parser.merge_into(&mut json, vars.into_iter()).is_err());
+        assert_eq!(json, json!({ "port": 8080, "name": "app" }));
+
+        // End-relative indices count from the target, not from the parser's json
+        let parser = parser
+            .with_json(json!({ "hosts": ["a"] }))
+            .with_negative_indices(true);
+        let mut json = json!({ "hosts": ["x", "y", "z"] });
+        parser.merge_into(
+            &mut json,
+            [("PREFIX__HOSTS__-1".to_string(), "c".to_string())].into_iter(),
+        )?;
+        assert_eq!(json, json!({ "hosts": ["x", "y", "z", "c"] }));
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::empty_separator(Parser::default().with_separator(""))]
+    #[case::empty_prefix(Parser::default().with_prefix(""))]
+    fn test_validate(#[case] parser: Parser) {
+        assert!(matches!(parser.validate(), Err(Error::Config(_))));
+        assert!(parser.parse_iter(std::iter::empty()).is_err());
+    }
+
+    #[test]
+    fn test_warnings() {
+        assert!(Parser::default().warnings().is_empty());
+        assert!(Parser::default()
+            .with_prefix("PREFIX__")
+            .warnings()
+            .is_empty());
+        assert_eq!(Parser::default().with_prefix("PREFIX").warnings().len(), 1);
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_nfc_values() -> Result<(), Error> {
+        let vars = || {
+            vec![
+                ("PREFIX__DECOMPOSED".to_string(), "Cafe\u{301}".to_string()),
+                ("PREFIX__COMPOSED".to_string(), "Caf\u{e9}".to_string()),
+            ]
+            .into_iter()
+        };
+        let parser = Parser::default().with_prefix("PREFIX__");
+
+        let actual = parser.parse_iter(vars())?;
+        assert_ne!(actual["decomposed"], actual["composed"]);
+
+        let parser = parser.with_nfc_values(true);
+        let actual = parser.parse_iter(vars())?;
+        assert_eq!(actual["decomposed"], actual["composed"]);
+        assert_eq!(parser.nfc_changes(vars())?, vec!["DECOMPOSED"]);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "unicode")]
+    #[rstest]
+    #[case::nfc(KeyNormalization::Nfc, json!({ "caf\u{e9}": "b", "\u{ff50}ort": "c" }))]
+    #[case::nfkc(KeyNormalization::Nfkc, json!({ "caf\u{e9}": "b", "port": "c" }))]
+    fn test_key_normalization(
+        #[case] form: KeyNormalization,
+        #[case] expected: Value,
+    ) -> Result<(), Error> {
+        let vars = test_util::vars(&[
+            ("PREFIX__CAFE\u{301}", "a"),
+            ("PREFIX__CAF\u{c9}", "b"),
+            ("PREFIX__\u{ff30}ORT", "c"),
+        ]);
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_key_normalization(form);
+
+        assert_eq!(parser.parse_iter(vars.into_iter())?, expected);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_base64_values() -> Result<(), Error> {
+        let vars = || {
+            vec![
+                (
+                    "PREFIX__CERT__B64".to_string(),
+                    "bGluZTEKbGluZTI=".to_string(),
+                ),
+                ("PREFIX__PORT".to_string(), "base64:ODA4MA==".to_string()),
+                ("PREFIX__B64".to_string(), "plain".to_string()),
+            ]
+            .into_iter()
+        };
+        let parser = Parser::default().with_prefix("PREFIX__");
+        assert_eq!(
+            parser.parse_iter(vars())?["cert"]["b64"],
+            "bGluZTEKbGluZTI="
+        );
+
+        let parser = parser.with_base64_values(true);
+        assert_eq!(
+            parser.parse_iter(vars())?,
+            json!({ "cert": "line1\nline2", "port": "8080", "b64": "plain" })
+        );
+
+        let invalid = [("PREFIX__KEY".to_string(), "base64:!!".to_string())];
+        assert!(parser.parse_iter(invalid.into_iter()).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[rstest]
+    #[case(
+        r#"
+        prefix: PREFIX__
+        separator: "__"
+        env_vars:
+            PREFIX__BIG_INT: "123456789012345678901234567890"
+            PREFIX__PRECISE: "0.1000000000000000000000000001"
+            PREFIX__FLOAT: "1.5"
+        expected: |
+            {
+                "big_int": 123456789012345678901234567890,
+                "precise": 0.1000000000000000000000000001,
+                "float": 1.5
+            }
+        "#
+    )]
+    fn test_parse_iter_arbitrary_precision(#[case] test_yaml: &'static str) -> Result<(), Error> {
+        let test_case = TestCase::from_yaml(test_yaml);
+        let actual = Parser::from(&test_case).parse_iter(test_case.vars())?;
+        assert_eq!(
+            actual["big_int"].to_string(),
+            "123456789012345678901234567890"
+        );
+        test_case.assert(&actual);
+
+        Ok(())
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
     #[rstest]
     #[case(
         r#"
@@ -884,7 +3925,7 @@ mod tests {
     )]
     fn test_parse_from_env(#[case] test_yaml: &'static str) -> Result<(), Error> {
         let test_case = TestCase::from_yaml(test_yaml);
-        test_case.set_vars();
+        let _env = test_case.set_vars();
         let env_vars_to_json = Parser::from(&test_case);
         let actual = env_vars_to_json.parse_from_env()?;
         println!("{}", serde_json::to_string_pretty(&actual).unwrap());
@@ -0,0 +1,199 @@
+//! Merging of parsed json documents
+
+use serde_json::Value;
+
+use crate::{Error, Parser};
+
+/// How arrays are combined by [`deep_merge`]. Objects are always merged recursively, and any
+/// other value of the overlay replaces the base value, even an object or an array, where the parser
+/// reports a variable as [`Error::Conflict`] instead
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Array items are merged by index, skipping `null` placeholders of the overlay, e.g. the
+    /// placeholders before the item of `LIST__1` in a parse result
+    #[default]
+    Index,
+
+    /// Arrays of the overlay replace arrays of the base
+    Replace,
+
+    /// Items of arrays of the overlay are appended to arrays of the base
+    Append,
+}
+
+/// Merge `overlay` into `base` with the given strategy, e.g. to combine several parse results,
+/// or a parse result with a document loaded from a file.
+///
+/// ```
+/// use env_vars_to_json::merge::{deep_merge, MergeStrategy};
+/// use serde_json::json;
+///
+/// let file = json!({ "db": { "host": "localhost", "port": 5432 }, "hosts": ["a", "b"] });
+/// let env = json!({ "db": { "port": 6543 }, "hosts": [null, "c"] });
+/// assert_eq!(
+///     deep_merge(file, env, MergeStrategy::Index),
+///     json!({ "db": { "host": "localhost", "port": 6543 }, "hosts": ["a", "c"] })
+/// );
+/// ```
+pub fn deep_merge(mut base: Value, overlay: Value, strategy: MergeStrategy) -> Value {
+    merge_with(&mut base, overlay, strategy);
+    base
+}
+
+/// Merge `overlay` into `base` with [`MergeStrategy::Index`]
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+    merge_with(base, overlay, MergeStrategy::Index);
+}
+
+/// Merge `overlay` into `base` in place with the given strategy
+fn merge_with(base: &mut Value, overlay: Value, strategy: MergeStrategy) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_with(base_value, value, strategy),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(overlay)) => match strategy {
+            MergeStrategy::Index => {
+                if overlay.len() > base.len() {
+                    base.resize_with(overlay.len(), || Value::Null);
+                }
+
+                for (i, value) in overlay.into_iter().enumerate() {
+                    if !value.is_null() {
+                        merge_with(&mut base[i], value, strategy);
+                    }
+                }
+            }
+            MergeStrategy::Replace => *base = overlay,
+            MergeStrategy::Append => base.extend(overlay),
+        },
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Parse variables with several parsers, e.g. one per prefix, and merge their results in order.
+///
+/// Each parser builds its subtree independently, optionally on its own thread, so a failing parser
+/// is reported with its prefix instead of failing somewhere in one shared document.
+pub fn parse_many(
+    parsers: &[Parser],
+    vars: impl Iterator<Item = (String, String)>,
+    parallel: bool,
+) -> Result<Value, Error> {
+    let vars = vars.collect::<Vec<_>>();
+
+    let parse = |parser: &Parser| {
+        parser
+            .parse_iter(vars.iter().cloned())
+            .map_err(|err| Error::Prefix {
+                prefix: parser.prefix.clone().unwrap_or_default(),
+                source: Box::new(err),
+            })
+    };
+
+    let subtrees = if parallel {
+        std::thread::scope(|scope| {
+            let handles = parsers
+                .iter()
+                .map(|parser| scope.spawn(|| parse(parser)))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("parser thread panicked"))
+                .collect::<Vec<_>>()
+        })
+    } else {
+        parsers.iter().map(parse).collect()
+    };
+
+    let mut json = Value::Object(Default::default());
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    for (parser, subtree) in parsers.iter().zip(subtrees) {
+        merge(&mut json, subtree?);
+        debug!(prefix = parser.prefix.as_deref(), "merged subtree");
+    }
+
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    fn vars() -> impl Iterator<Item = (String, String)> {
+        test_util::vars(&[
+            ("DB__HOST", "localhost"),
+            ("DB__PORTS__1", "5433"),
+            ("HTTP__PORTS__0", "80"),
+            ("HTTP__HOST", "example.com"),
+        ])
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn test_parse_many(#[case] parallel: bool) -> Result<(), Error> {
+        let parsers = [
+            Parser::default().with_prefix("DB__"),
+            Parser::default().with_prefix("HTTP__"),
+        ];
+
+        assert_eq!(
+            parse_many(&parsers, vars(), parallel)?,
+            json!({
+                "host": "example.com",
+                "ports": [80, 5433]
+            })
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(MergeStrategy::Index, json!({ "a": { "b": 2, "c": 1 }, "list": [{ "x": 1, "y": 2 }, 3] }))]
+    #[case(MergeStrategy::Replace, json!({ "a": { "b": 2, "c": 1 }, "list": [{ "y": 2 }, 3] }))]
+    #[case(
+        MergeStrategy::Append,
+        json!({ "a": { "b": 2, "c": 1 }, "list": [{ "x": 1 }, { "y": 2 }, 3] })
+    )]
+    fn test_deep_merge(#[case] strategy: MergeStrategy, #[case] expected: Value) {
+        let base = json!({ "a": { "b": 1, "c": 1 }, "list": [{ "x": 1 }] });
+        let overlay = json!({ "a": { "b": 2 }, "list": [{ "y": 2 }, 3] });
+
+        assert_eq!(deep_merge(base, overlay, strategy), expected);
+    }
+
+    #[test]
+    fn test_deep_merge_replaces_containers() {
+        let base = json!({ "db": { "host": "localhost" }, "hosts": ["a"] });
+        let overlay = json!({ "db": "postgres://db", "hosts": "b" });
+
+        assert_eq!(
+            deep_merge(base, overlay, MergeStrategy::Index),
+            json!({ "db": "postgres://db", "hosts": "b" })
+        );
+    }
+
+    #[test]
+    fn test_parse_many_error() {
+        let parsers = [
+            Parser::default().with_prefix("DB__"),
+            Parser::default().with_prefix("HTTP__"),
+        ];
+        let vars = vars().chain([("HTTP__0".to_string(), "invalid".to_string())]);
+        let err = parse_many(&parsers, vars, true).unwrap_err();
+
+        assert!(matches!(err, Error::Prefix { prefix, .. } if prefix == "HTTP__"));
+    }
+}
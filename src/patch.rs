@@ -0,0 +1,255 @@
+//! Changes of the environment to the base json as RFC 6902 JSON Patch operations
+//! or an RFC 7386 JSON Merge Patch
+
+use serde::{Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+use crate::{path::to_pointer, Error, JsonIndex, JsonPath, Parser};
+
+/// A JSON Patch operation, serialized as e.g. `{ "op": "add", "path": "/list/1", "value": 2 }`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOp {
+    /// Add a value at a new object key or array index
+    Add { path: String, value: Value },
+
+    /// Remove the value at the path
+    Remove { path: String },
+
+    /// Replace the value at the path
+    Replace { path: String, value: Value },
+}
+
+impl PatchOp {
+    /// Return the operation as json
+    pub fn to_json(&self) -> Value {
+        match self {
+            PatchOp::Add { path, value } => json!({ "op": "add", "path": path, "value": value }),
+            PatchOp::Remove { path } => json!({ "op": "remove", "path": path }),
+            PatchOp::Replace { path, value } => {
+                json!({ "op": "replace", "path": path, "value": value })
+            }
+        }
+    }
+}
+
+impl Serialize for PatchOp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+/// Return the operations turning `from` into `to`, applied in order.
+///
+/// Objects and arrays are compared recursively. Items appended to an array are added in order,
+/// and items missing from its end are removed from the last one, so every operation is valid
+/// after the operations before it.
+pub fn diff(from: &Value, to: &Value) -> Vec<PatchOp> {
+    let mut ops = vec![];
+    diff_at(from, to, &mut JsonPath::new(), &mut ops);
+    ops
+}
+
+fn diff_at(from: &Value, to: &Value, path: &mut JsonPath, ops: &mut Vec<PatchOp>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            for (key, from_value) in from {
+                path.push(JsonIndex::String(key.clone()));
+                match to.get(key) {
+                    Some(to_value) => diff_at(from_value, to_value, path, ops),
+                    None => ops.push(PatchOp::Remove {
+                        path: to_pointer(path),
+                    }),
+                }
+                path.pop();
+            }
+
+            for (key, to_value) in to {
+                if !from.contains_key(key) {
+                    path.push(JsonIndex::String(key.clone()));
+                    ops.push(PatchOp::Add {
+                        path: to_pointer(path),
+                        value: to_value.clone(),
+                    });
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for (i, (from_value, to_value)) in from.iter().zip(to).enumerate() {
+                path.push(JsonIndex::Usize(i));
+                diff_at(from_value, to_value, path, ops);
+                path.pop();
+            }
+
+            for (i, to_value) in to.iter().enumerate().skip(from.len()) {
+                path.push(JsonIndex::Usize(i));
+                ops.push(PatchOp::Add {
+                    path: to_pointer(path),
+                    value: to_value.clone(),
+                });
+                path.pop();
+            }
+
+            for i in (to.len()..from.len()).rev() {
+                path.push(JsonIndex::Usize(i));
+                ops.push(PatchOp::Remove {
+                    path: to_pointer(path),
+                });
+                path.pop();
+            }
+        }
+        (from, to) if from != to => ops.push(PatchOp::Replace {
+            path: to_pointer(path),
+            value: to.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Return the minimal merge patch turning `from` into `to`, `{}` if they are equal.
+///
+/// Objects are compared recursively, and keys missing from `to` are set to `null`.
+/// Arrays which differ are replaced as a whole, as merge patches cannot address array items.
+pub fn merge_diff(from: &Value, to: &Value) -> Value {
+    merge_diff_at(from, to).unwrap_or_else(|| Value::Object(Map::new()))
+}
+
+fn merge_diff_at(from: &Value, to: &Value) -> Option<Value> {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut patch = Map::new();
+            for key in from.keys().filter(|key| !to.contains_key(*key)) {
+                patch.insert(key.clone(), Value::Null);
+            }
+            for (key, to_value) in to {
+                let change = match from.get(key) {
+                    Some(from_value) => merge_diff_at(from_value, to_value),
+                    None => Some(to_value.clone()),
+                };
+                if let Some(change) = change {
+                    patch.insert(key.clone(), change);
+                }
+            }
+
+            (!patch.is_empty()).then_some(Value::Object(patch))
+        }
+        (from, to) => (from != to).then(|| to.clone()),
+    }
+}
+
+impl Parser {
+    /// Parse iterator of String tuples into the JSON Patch operations the variables apply to the
+    /// parser's json object, instead of the merged document
+    pub fn parse_as_patch(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Vec<PatchOp>, Error> {
+        let merged = self.parse_iter(vars)?;
+        Ok(diff(&self.json, &merged))
+    }
+
+    /// Parse iterator of String tuples into a JSON Merge Patch of only the values the variables
+    /// change in the parser's json object, e.g. to store per environment.
+    /// Values the variables set to `null` cannot be told apart from removed keys in a merge patch
+    pub fn parse_as_merge_patch(
+        &self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Value, Error> {
+        let merged = self.parse_iter(vars)?;
+        Ok(merge_diff(&self.json, &merged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_parse_as_patch() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "db": { "host": "localhost", "port": 5432 }, "list": ["a", "b"] }))
+            .with_tombstone("__UNSET__");
+        let vars = test_util::vars(&[
+            ("PREFIX__DB__PORT", "6543"),
+            ("PREFIX__DB__HOST", "localhost"),
+            ("PREFIX__LIST__1", "__UNSET__"),
+            ("PREFIX__NAME__FIRST", "app"),
+        ]);
+
+        let patch = parser.parse_as_patch(vars.into_iter())?;
+        assert_eq!(
+            serde_json::to_value(&patch).map_err(Error::SerdeJson)?,
+            json!([
+                { "op": "replace", "path": "/db/port", "value": 6543 },
+                { "op": "remove", "path": "/list/1" },
+                { "op": "add", "path": "/name", "value": { "first": "app" } },
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_as_merge_patch() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "db": { "host": "localhost", "port": 5432 }, "list": ["a", "b"] }))
+            .with_tombstone("__UNSET__");
+        let vars = test_util::vars(&[
+            ("PREFIX__DB__PORT", "6543"),
+            ("PREFIX__DB__HOST", "localhost"),
+            ("PREFIX__LIST__1", "c"),
+            ("PREFIX__NAME", "__UNSET__"),
+        ]);
+
+        assert_eq!(
+            parser.parse_as_merge_patch(vars.into_iter())?,
+            json!({ "db": { "port": 6543 }, "list": ["a", "c"] })
+        );
+        assert_eq!(parser.parse_as_merge_patch(std::iter::empty())?, json!({}));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_diff() {
+        assert_eq!(
+            merge_diff(
+                &json!({ "a": 1, "b": { "c": 2 } }),
+                &json!({ "b": { "c": 2, "d": 3 } })
+            ),
+            json!({ "a": null, "b": { "d": 3 } })
+        );
+        assert_eq!(merge_diff(&json!({ "a": 1 }), &json!([1])), json!([1]));
+    }
+
+    #[test]
+    fn test_diff_arrays() {
+        assert_eq!(
+            diff(&json!([1, 2, 3]), &json!([1])),
+            [
+                PatchOp::Remove {
+                    path: "/2".to_string()
+                },
+                PatchOp::Remove {
+                    path: "/1".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            diff(&json!([0]), &json!([1, { "a": true }])),
+            [
+                PatchOp::Replace {
+                    path: "/0".to_string(),
+                    value: json!(1)
+                },
+                PatchOp::Add {
+                    path: "/1".to_string(),
+                    value: json!({ "a": true })
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,442 @@
+//! Paths into json documents and RFC 6901 JSON Pointers
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
+
+use serde_json::Value;
+
+use crate::{tree, Error};
+
+/// Index/key of a array/object
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum JsonIndex {
+    String(String),
+    Usize(usize),
+}
+
+impl JsonIndex {
+    pub fn from_vec(vec: Vec<&str>) -> Vec<Self> {
+        vec.into_iter().map(Self::from).collect()
+    }
+}
+
+impl From<&str> for JsonIndex {
+    fn from(s: &str) -> Self {
+        if let Ok(number) = s.parse::<usize>() {
+            Self::Usize(number)
+        } else {
+            Self::String(s.to_string())
+        }
+    }
+}
+
+impl From<String> for JsonIndex {
+    fn from(s: String) -> Self {
+        if let Ok(number) = s.parse::<usize>() {
+            Self::Usize(number)
+        } else {
+            Self::String(s)
+        }
+    }
+}
+
+impl From<&String> for JsonIndex {
+    fn from(s: &String) -> Self {
+        if let Ok(number) = s.parse::<usize>() {
+            Self::Usize(number)
+        } else {
+            Self::String(s.clone())
+        }
+    }
+}
+
+impl fmt::Display for JsonIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(key) => f.write_str(key),
+            Self::Usize(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// A path into a json document, displayed and parsed as e.g. `a.b[3].c`.
+///
+/// Keys are joined by `.` and array indices are written in brackets, so `a.0` is the key `0`
+/// of an object while `a[0]` is the first item of an array. Keys which contain `.`, `[`, `]`
+/// or `"`, or are empty, are written as quoted json strings in brackets, e.g. `a["b.c"]`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JsonPath(Vec<JsonIndex>);
+
+impl JsonPath {
+    /// Return an empty path, pointing at the root
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Convert an environment variable key into a path the way the parser does by default,
+    /// e.g. `DB__HOSTS__0` with separator `__` into `db.hosts[0]`: key parts are lowercased
+    /// and numeric key parts become array indices
+    pub fn from_env_key(key: &str, separator: &str) -> Self {
+        key.split(separator)
+            .map(|part| JsonIndex::from(part.to_lowercase()))
+            .collect()
+    }
+
+    /// Return the indices of the path
+    pub fn into_vec(self) -> Vec<JsonIndex> {
+        self.0
+    }
+}
+
+impl Deref for JsonPath {
+    type Target = Vec<JsonIndex>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for JsonPath {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<JsonIndex>> for JsonPath {
+    fn from(indices: Vec<JsonIndex>) -> Self {
+        Self(indices)
+    }
+}
+
+impl From<&[JsonIndex]> for JsonPath {
+    fn from(indices: &[JsonIndex]) -> Self {
+        Self(indices.to_vec())
+    }
+}
+
+impl FromIterator<JsonIndex> for JsonPath {
+    fn from_iter<I: IntoIterator<Item = JsonIndex>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a JsonPath {
+    type Item = &'a JsonIndex;
+    type IntoIter = std::slice::Iter<'a, JsonIndex>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, index) in self.0.iter().enumerate() {
+            match index {
+                JsonIndex::Usize(index) => write!(f, "[{index}]")?,
+                JsonIndex::String(key) if key.is_empty() || key.contains(['.', '[', ']', '"']) => {
+                    write!(f, "[{}]", Value::String(key.clone()))?
+                }
+                JsonIndex::String(key) if i == 0 => f.write_str(key)?,
+                JsonIndex::String(key) => write!(f, ".{key}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = Error;
+
+    /// Parse a path like `a.b[3].c` or `a["b.c"]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| Error::from(format!("invalid json path `{s}`: {message}"));
+
+        let mut path = vec![];
+        let mut rest = s;
+        let mut first = true;
+
+        while !rest.is_empty() {
+            if let Some(bracketed) = rest.strip_prefix('[') {
+                if bracketed.starts_with('"') {
+                    let mut stream =
+                        serde_json::Deserializer::from_str(bracketed).into_iter::<String>();
+                    let key = match stream.next() {
+                        Some(Ok(key)) => key,
+                        _ => return Err(invalid("unterminated quoted key")),
+                    };
+                    rest = bracketed[stream.byte_offset()..]
+                        .strip_prefix(']')
+                        .ok_or_else(|| invalid("expected `]` after quoted key"))?;
+                    path.push(JsonIndex::String(key));
+                } else {
+                    let (index, after) = bracketed
+                        .split_once(']')
+                        .ok_or_else(|| invalid("expected `]`"))?;
+                    let index = index
+                        .parse()
+                        .map_err(|_| invalid("array index must be a number"))?;
+                    path.push(JsonIndex::Usize(index));
+                    rest = after;
+                }
+            } else {
+                let key = match first {
+                    true => rest,
+                    false => rest
+                        .strip_prefix('.')
+                        .ok_or_else(|| invalid("expected `.` or `[`"))?,
+                };
+                let end = key.find(['.', '[']).unwrap_or(key.len());
+                if end == 0 {
+                    return Err(invalid("empty key"));
+                }
+                path.push(JsonIndex::String(key[..end].to_string()));
+                rest = &key[end..];
+            }
+            first = false;
+        }
+
+        Ok(Self(path))
+    }
+}
+
+/// Get a reference to the json value at indices
+pub fn get<'a>(json: &'a Value, indices: &[JsonIndex]) -> Option<&'a Value> {
+    let mut json = json;
+
+    for index in indices {
+        json = match index {
+            JsonIndex::String(key) => json.get(key)?,
+            JsonIndex::Usize(index) => json.get(index)?,
+        };
+    }
+
+    Some(json)
+}
+
+/// Get a mutable reference to the json value at indices
+pub fn get_mut<'a>(json: &'a mut Value, indices: &[JsonIndex]) -> Option<&'a mut Value> {
+    let mut json = json;
+
+    for index in indices {
+        json = match index {
+            JsonIndex::String(key) => json.get_mut(key)?,
+            JsonIndex::Usize(index) => json.get_mut(index)?,
+        };
+    }
+
+    Some(json)
+}
+
+/// How [`set_with`] fills the array items skipped by an index past the end of an array
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum CreatePolicy {
+    /// Pad arrays with `null`
+    #[default]
+    FillNull,
+
+    /// Pad arrays with copies of the value, e.g. `{}`
+    Fill(Value),
+
+    /// Only allow indices up to the end of an array, appending a single item
+    NoGaps,
+}
+
+/// Set the json value at indices, returning the previous value.
+///
+/// Missing intermediate containers are created: an object for a string key and an array for a
+/// numeric index, padding arrays with `null`. Scalars on the way are replaced by containers, and a
+/// value already at the indices is replaced, even a container. A string key into an array or a
+/// numeric index into an object is an [`Error::Conflict`].
+///
+/// This is how the parser merges variables, see [`crate::tree::insert`], except that the parser
+/// reports a container where a variable's value goes as a conflict instead of replacing it.
+pub fn set(json: &mut Value, indices: &[JsonIndex], value: Value) -> Result<Option<Value>, Error> {
+    set_with(json, indices, value, &CreatePolicy::default())
+}
+
+/// Set the json value at indices like [`set`], filling skipped array items by the policy
+pub fn set_with(
+    json: &mut Value,
+    indices: &[JsonIndex],
+    value: Value,
+    policy: &CreatePolicy,
+) -> Result<Option<Value>, Error> {
+    let gap = |path: &[JsonIndex], len| match policy {
+        CreatePolicy::FillNull => Ok(Value::Null),
+        CreatePolicy::Fill(fill) => Ok(fill.clone()),
+        CreatePolicy::NoGaps => Err(format!(
+            "cannot set {} at `{}`, the array has {len} items",
+            to_pointer(indices),
+            path.last().expect("gaps are filled before an index"),
+        )
+        .into()),
+    };
+
+    tree::set(json, indices, value, gap, true)
+}
+
+/// Convert indices into an RFC 6901 JSON Pointer, e.g. `/struct/bool_list/0`
+pub fn to_pointer(indices: &[JsonIndex]) -> String {
+    indices
+        .iter()
+        .map(|index| {
+            format!(
+                "/{}",
+                index.to_string().replace('~', "~0").replace('/', "~1")
+            )
+        })
+        .collect()
+}
+
+/// Parse an RFC 6901 JSON Pointer into indices, numeric tokens become array indices
+pub fn from_pointer(pointer: &str) -> Result<Vec<JsonIndex>, Error> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let tokens = pointer
+        .strip_prefix('/')
+        .ok_or_else(|| format!("JSON Pointer must start with `/`: {pointer}"))?;
+
+    Ok(tokens
+        .split('/')
+        .map(|token| JsonIndex::from(token.replace("~1", "/").replace("~0", "~")))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_get() {
+        let json = json!({ "a": { "b": [1, { "c": true }] } });
+
+        assert_eq!(
+            get(&json, &JsonIndex::from_vec(vec!["a", "b", "1", "c"])),
+            Some(&json!(true))
+        );
+        assert_eq!(get(&json, &JsonIndex::from_vec(vec!["a", "x"])), None);
+    }
+
+    #[test]
+    fn test_set() -> Result<(), Error> {
+        let mut json = json!({ "a": { "b": [1] } });
+
+        assert_eq!(
+            set(
+                &mut json,
+                &JsonIndex::from_vec(vec!["a", "b", "0"]),
+                json!(2)
+            )?,
+            Some(json!(1))
+        );
+        assert_eq!(
+            set(
+                &mut json,
+                &JsonIndex::from_vec(vec!["a", "b", "2", "c"]),
+                json!("x")
+            )?,
+            None
+        );
+        assert_eq!(
+            set(&mut json, &JsonIndex::from_vec(vec!["d", "0"]), json!(true))?,
+            None
+        );
+        assert_eq!(
+            json,
+            json!({ "a": { "b": [2, null, { "c": "x" }] }, "d": [true] })
+        );
+
+        assert!(set(
+            &mut json,
+            &JsonIndex::from_vec(vec!["a", "b", "key"]),
+            json!(1)
+        )
+        .is_err());
+        assert!(set(&mut json, &JsonIndex::from_vec(vec!["a", "0"]), json!(1)).is_err());
+
+        // Scalars on the way are replaced, like the parser does
+        assert_eq!(
+            set(
+                &mut json,
+                &JsonIndex::from_vec(vec!["d", "0", "key"]),
+                json!(1)
+            )?,
+            None
+        );
+        assert_eq!(json["d"], json!([{ "key": 1 }]));
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case::fill_null(CreatePolicy::FillNull, Some(json!({ "a": [null, null, 1] })))]
+    #[case::fill(CreatePolicy::Fill(json!({})), Some(json!({ "a": [{}, {}, 1] })))]
+    #[case::no_gaps(CreatePolicy::NoGaps, None)]
+    fn test_set_with(#[case] policy: CreatePolicy, #[case] expected: Option<Value>) {
+        let mut json = json!({});
+        let path = "a[2]".parse::<JsonPath>().unwrap();
+
+        let result = set_with(&mut json, &path, json!(1), &policy);
+        assert_eq!(result.ok().map(|_| json), expected);
+    }
+
+    #[rstest]
+    #[case("", vec![])]
+    #[case("a.b[3].c", vec![s("a"), s("b"), JsonIndex::Usize(3), s("c")])]
+    #[case("[0]", vec![JsonIndex::Usize(0)])]
+    #[case("a.0", vec![s("a"), s("0")])]
+    #[case(r#"a["b.c"][""]"#, vec![s("a"), s("b.c"), s("")])]
+    fn test_json_path(#[case] text: &str, #[case] indices: Vec<JsonIndex>) -> Result<(), Error> {
+        let path = text.parse::<JsonPath>()?;
+
+        assert_eq!(path, JsonPath::from(indices));
+        assert_eq!(path.to_string(), text);
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a..b")]
+    #[case("a[x]")]
+    #[case("a[1")]
+    #[case(r#"a["b"c]"#)]
+    #[case(".a")]
+    fn test_json_path_invalid(#[case] text: &str) {
+        assert!(text.parse::<JsonPath>().is_err());
+    }
+
+    #[test]
+    fn test_json_path_from_env_key() {
+        assert_eq!(
+            JsonPath::from_env_key("DB__HOSTS__0", "__").to_string(),
+            "db.hosts[0]"
+        );
+    }
+
+    fn s(key: &str) -> JsonIndex {
+        JsonIndex::String(key.to_string())
+    }
+
+    #[rstest]
+    #[case("", vec![])]
+    #[case("/a/0", vec!["a", "0"])]
+    #[case("/a~1b/m~0n", vec!["a/b", "m~n"])]
+    fn test_pointer(#[case] pointer: &str, #[case] indices: Vec<&str>) -> Result<(), Error> {
+        let indices = JsonIndex::from_vec(indices);
+
+        assert_eq!(from_pointer(pointer)?, indices);
+        assert_eq!(to_pointer(&indices), pointer);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Best-effort parsing which reports what happened to every variable, see [`ParseReport`]
+
+use std::{borrow::Cow, collections::BTreeSet};
+
+use serde_json::Value;
+
+use crate::{from_env::REDACTED, Error, FlatEntry, JsonPath, Parser, SkipReason};
+
+/// The result of [`Parser::parse_iter_report`], e.g. to render a table of the resolved
+/// configuration and where each value came from
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    /// The parsed json, built from the variables without errors
+    pub value: Value,
+
+    /// Variables merged into the json, with the path they were merged at
+    pub used: Vec<(String, JsonPath)>,
+
+    /// Variables dropped by the prefix and the include/exclude filters
+    pub skipped: Vec<(String, SkipReason)>,
+
+    /// How the value of each used variable was coerced
+    pub coercions: Vec<Coercion>,
+
+    /// Errors of variables which couldn't be parsed or merged
+    pub errors: Vec<Error>,
+
+    /// Problems which didn't prevent parsing but are likely a mistake, e.g. that no variable was
+    /// selected by a parser requiring a match
+    pub warnings: Vec<String>,
+}
+
+/// How the value of a variable was coerced. Secret values are redacted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coercion {
+    /// The name of the variable
+    pub var: String,
+
+    /// The value of the variable after preprocessing, e.g. trimming
+    pub raw: String,
+
+    /// The coerced value
+    pub value: Value,
+}
+
+impl ParseReport {
+    /// Whether all variables were parsed and merged without errors
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Parser {
+    /// Parse iterator of String tuples into json without stopping at the first error, reporting which
+    /// variables were used, skipped and how they were coerced
+    pub fn parse_iter_report(&self, vars: impl Iterator<Item = (String, String)>) -> ParseReport {
+        let vars = vars.collect::<Vec<_>>();
+        let mut report = ParseReport {
+            value: self.json.clone(),
+            skipped: self.skipped(vars.iter().cloned()),
+            ..Default::default()
+        };
+
+        let vars =
+            match self.validate().and_then(|_| {
+                self.preprocess_vars(vars.iter().map(|(key, value)| {
+                    (Cow::Borrowed(key.as_str()), Cow::Borrowed(value.as_str()))
+                }))
+            }) {
+                Ok(vars) => vars,
+                Err(err) => {
+                    report.errors.push(err);
+                    return report;
+                }
+            };
+
+        if self.require_match && vars.is_empty() {
+            report.warnings.push(self.no_match().to_string());
+        }
+
+        let prefix = self.prefix.as_deref().unwrap_or_default();
+        let mut entries = vec![];
+        let mut coercions = vec![];
+
+        for (key, raw) in vars {
+            let var = format!("{prefix}{key}");
+
+            let erased = self.erases(&raw);
+            #[cfg_attr(not(feature = "uuid"), allow(unused_mut))]
+            let (key_parts, mut value) = match self.assignment(&key, raw.clone()) {
+                Ok(assignment) => assignment,
+                Err(err) => {
+                    report.errors.push(err);
+                    continue;
+                }
+            };
+
+            let coercion = if erased {
+                None
+            } else {
+                let invalid = self.rule_errors(&key, &key_parts, &value);
+                if !invalid.is_empty() {
+                    report.errors.push(Error::Validation(invalid));
+                    continue;
+                }
+
+                #[cfg(feature = "uuid")]
+                self.canonicalize(&key_parts, &mut value);
+
+                Some(if self.is_secret(&key_parts) {
+                    Coercion {
+                        var: var.clone(),
+                        raw: REDACTED.to_string(),
+                        value: Value::String(REDACTED.to_string()),
+                    }
+                } else {
+                    Coercion {
+                        var: var.clone(),
+                        raw: raw.into_owned(),
+                        value: value.clone(),
+                    }
+                })
+            };
+
+            entries.push(FlatEntry {
+                var,
+                segments: key_parts.into_iter().map(Cow::into_owned).collect(),
+                value: (!erased).then_some(value),
+            });
+            coercions.push(coercion);
+        }
+
+        // Merge like the parser does, so the value matches the result of parsing
+        let paths = self.entry_paths(&mut report.value, &entries);
+        let mut compacted = BTreeSet::new();
+        for ((entry, path), coercion) in entries.into_iter().zip(paths).zip(coercions) {
+            match self.merge_entry(&mut report.value, &entry, &path, &mut compacted) {
+                Ok(()) => {
+                    report.used.push((entry.var, path));
+                    report.coercions.extend(coercion);
+                }
+                Err(err) => report.errors.push(err),
+            }
+        }
+        self.finish_merge(&mut report.value, compacted);
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{test_util, JsonIndex};
+
+    #[test]
+    fn test_parse_iter_report() {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_secret("password");
+        let vars = test_util::vars(&[
+            ("PREFIX__INT", "1"),
+            ("PREFIX__PASSWORD", "hunter2"),
+            ("PREFIX__0", "invalid"),
+            ("OTHER", "x"),
+        ]);
+
+        let report = parser.parse_iter_report(vars.into_iter());
+
+        assert_eq!(report.value, json!({ "int": 1, "password": "hunter2" }));
+        assert_eq!(
+            report.used,
+            vec![
+                (
+                    "PREFIX__PASSWORD".to_string(),
+                    JsonIndex::from_vec(vec!["password"]).into()
+                ),
+                (
+                    "PREFIX__INT".to_string(),
+                    JsonIndex::from_vec(vec!["int"]).into()
+                ),
+            ]
+        );
+        assert_eq!(
+            report.skipped,
+            vec![("OTHER".to_string(), SkipReason::Prefix)]
+        );
+        assert_eq!(
+            report.coercions,
+            vec![
+                Coercion {
+                    var: "PREFIX__PASSWORD".to_string(),
+                    raw: "***".to_string(),
+                    value: json!("***"),
+                },
+                Coercion {
+                    var: "PREFIX__INT".to_string(),
+                    raw: "1".to_string(),
+                    value: json!(1),
+                },
+            ]
+        );
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.warnings.is_empty());
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_parse_iter_report_compact_arrays() -> Result<(), Error> {
+        let parser = Parser::default().with_compact_arrays(true);
+        let vars = || test_util::vars(&[("HOSTS__0", "a"), ("HOSTS__3", "b")]);
+
+        let report = parser.parse_iter_report(vars());
+        assert!(report.is_ok());
+        assert_eq!(report.value, json!({ "hosts": ["a", "b"] }));
+        assert_eq!(report.value, parser.parse_iter(vars())?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_parse_iter_report_uuid() -> Result<(), Error> {
+        let parser = Parser::default().with_uuid("id");
+        let vars = || test_util::vars(&[("ID", "{67E55044-10B1-426F-9247-BB680E5FE0C8}")]);
+
+        let report = parser.parse_iter_report(vars());
+        assert_eq!(report.value, parser.parse_iter(vars())?);
+        assert_eq!(
+            report.coercions[0].value,
+            json!("67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_iter_report_require_match() {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_require_match(true);
+        let vars = [("OTHER__INT".to_string(), "1".to_string())];
+
+        let report = parser.parse_iter_report(vars.into_iter());
+        assert!(report.is_ok());
+        assert_eq!(
+            report.warnings,
+            vec!["no environment variables selected with prefix `PREFIX__`".to_string()]
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Splitting of keys into key parts
+
+use std::{borrow::Cow, fmt};
+
+use crate::Error;
+
+/// Splits the key of a variable, after the prefix, into key parts, see [`Parser::with_key_segmenter`].
+///
+/// Key parts are lowercased afterwards unless disabled, and numeric key parts become array
+/// indices as usual.
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// use env_vars_to_json::{segment::KeySegmenter, Error, Parser};
+/// use serde_json::json;
+///
+/// /// Splits keys by the separator and by `.`, e.g. `DB.HOST__PORT`
+/// struct Dotted;
+///
+/// impl KeySegmenter for Dotted {
+///     fn segments<'k>(&self, key: &'k str, separator: &str) -> Result<Vec<Cow<'k, str>>, Error> {
+///         Ok(key
+///             .split(separator)
+///             .flat_map(|part| part.split('.'))
+///             .map(Cow::Borrowed)
+///             .collect())
+///     }
+/// }
+///
+/// let parser = Parser::default().with_key_segmenter(Box::new(Dotted));
+/// let vars = [("DB.HOST__PORT".to_string(), "5432".to_string())];
+/// assert_eq!(
+///     parser.parse_iter(vars.into_iter())?,
+///     json!({ "db": { "host": { "port": 5432 } } })
+/// );
+/// # Ok::<(), Error>(())
+/// ```
+///
+/// [`Parser::with_key_segmenter`]: crate::Parser::with_key_segmenter
+pub trait KeySegmenter: Send + Sync {
+    /// Split a key into key parts, e.g. `DB__HOST` into `DB` and `HOST` with separator `__`
+    fn segments<'k>(&self, key: &'k str, separator: &str) -> Result<Vec<Cow<'k, str>>, Error>;
+}
+
+impl fmt::Debug for dyn KeySegmenter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeySegmenter")
+    }
+}
+
+/// Splits keys at every occurrence of the separator, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeparatorSegmenter;
+
+impl KeySegmenter for SeparatorSegmenter {
+    fn segments<'k>(&self, key: &'k str, separator: &str) -> Result<Vec<Cow<'k, str>>, Error> {
+        Ok(key.split(separator).map(Cow::Borrowed).collect())
+    }
+}
+
+/// Splits keys at every occurrence of the separator, and bracketed array indices off key parts,
+/// e.g. `SERVERS[0]__HOST` into `SERVERS`, `[0]` and `HOST`, see [`Parser::with_bracket_indices`]
+///
+/// [`Parser::with_bracket_indices`]: crate::Parser::with_bracket_indices
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BracketSegmenter;
+
+impl KeySegmenter for BracketSegmenter {
+    fn segments<'k>(&self, key: &'k str, separator: &str) -> Result<Vec<Cow<'k, str>>, Error> {
+        let mut segments = vec![];
+
+        for part in key.split(separator) {
+            let invalid = || {
+                Error::from(format!(
+                    "invalid array index in key part `{part}`, expected e.g. `[0]`"
+                ))
+            };
+
+            let (name, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if !name.is_empty() || indices.is_empty() {
+                segments.push(Cow::Borrowed(name));
+            }
+
+            while !indices.is_empty() {
+                let end = indices.find(']').ok_or_else(invalid)? + 1;
+                let index = &indices[..end];
+                bracket_index(index).ok_or_else(invalid)?;
+                segments.push(Cow::Borrowed(index));
+                indices = &indices[end..];
+            }
+        }
+
+        Ok(segments)
+    }
+}
+
+/// Return the index of a bracketed key part, e.g. `0` for `[0]`
+pub(crate) fn bracket_index(part: &str) -> Option<usize> {
+    let digits = part.strip_prefix('[')?.strip_suffix(']')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    digits.parse().ok()
+}
@@ -0,0 +1,172 @@
+//! Azure App Configuration
+//! Requires the `azure-app-config` feature
+
+use std::{collections::BTreeMap, future::Future};
+
+use crate::Error;
+
+use super::{hierarchical_key, AsyncEnvSource};
+
+/// A key-value of the configuration store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppConfigSetting {
+    /// The `:`-separated key, e.g. `App:Db:Port`
+    pub key: String,
+
+    /// The label, `None` for unlabeled settings
+    pub label: Option<String>,
+
+    /// The value
+    pub value: String,
+}
+
+/// The calls [`AppConfigSource`] needs from an App Configuration client.
+///
+/// Implement it for your client, e.g. by listing settings with the key filter `<prefix>*`.
+pub trait AppConfigClient {
+    /// Return the settings of all labels whose key starts with the prefix
+    fn list(
+        &self,
+        prefix: &str,
+    ) -> impl Future<Output = Result<Vec<AppConfigSetting>, Error>> + Send;
+}
+
+/// Settings below a key prefix of the configuration store, selected by label.
+///
+/// Keys are translated into variable keys by stripping the prefix and joining the remaining `:`-separated
+/// parts with the separator, so `App:Db:Port` below `App:` becomes `Db__Port`, prepended by the key
+/// prefix.
+///
+/// Only unlabeled settings are selected by default. If a key has settings with several selected
+/// labels, the setting with the label selected last wins, e.g. `prod` overrides unlabeled defaults.
+#[derive(Debug, Clone)]
+pub struct AppConfigSource<C> {
+    client: C,
+    prefix: String,
+    labels: Vec<Option<String>>,
+    key_prefix: String,
+    separator: String,
+}
+
+impl<C: AppConfigClient> AppConfigSource<C> {
+    /// Return a source reading the unlabeled settings below the key prefix
+    pub fn new(client: C, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            prefix: prefix.into(),
+            labels: vec![None],
+            key_prefix: String::new(),
+            separator: "__".to_string(),
+        }
+    }
+
+    /// Return the source selecting the settings with the given labels, in increasing precedence.
+    /// `None` selects unlabeled settings, e.g. `[None, Some("prod")]`
+    pub fn with_labels<L: Into<String>>(
+        mut self,
+        labels: impl IntoIterator<Item = Option<L>>,
+    ) -> Self {
+        self.labels = labels
+            .into_iter()
+            .map(|label| label.map(Into::into))
+            .collect();
+        self
+    }
+
+    /// Return the source with the given prefix prepended to every key, e.g. the prefix of the parser
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Return the source with the given separator joining the parts of keys
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+}
+
+impl<C: AppConfigClient + Sync> AsyncEnvSource for AppConfigSource<C> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        // The selected setting of every key, with the precedence of its label
+        let mut selected = BTreeMap::new();
+
+        for setting in self.client.list(&self.prefix).await? {
+            let Some(precedence) = self.labels.iter().position(|label| *label == setting.label)
+            else {
+                continue;
+            };
+            let Some(key) = hierarchical_key(&setting.key, &self.prefix, ':', &self.separator)
+            else {
+                continue;
+            };
+
+            match selected.get(&key) {
+                Some((current, _)) if *current > precedence => {}
+                _ => {
+                    selected.insert(key, (precedence, setting.value));
+                }
+            }
+        }
+
+        Ok(selected
+            .into_iter()
+            .map(|(key, (_, value))| (format!("{}{key}", self.key_prefix), value))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{test_util::block_on, Parser};
+
+    struct FakeAppConfig;
+
+    impl AppConfigClient for FakeAppConfig {
+        async fn list(&self, prefix: &str) -> Result<Vec<AppConfigSetting>, Error> {
+            Ok([
+                ("App:Db:Port", None, "5432"),
+                ("App:Db:Port", Some("prod"), "6543"),
+                ("App:Db:Host", None, "localhost"),
+                ("App:Debug", Some("dev"), "true"),
+                ("Other:Key", None, "ignored"),
+            ]
+            .into_iter()
+            .filter(|(key, _, _)| key.starts_with(prefix))
+            .map(|(key, label, value)| AppConfigSetting {
+                key: key.to_string(),
+                label: label.map(str::to_string),
+                value: value.to_string(),
+            })
+            .collect())
+        }
+    }
+
+    #[test]
+    fn test_app_config_source() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("APP__");
+        let source = AppConfigSource::new(FakeAppConfig, "App:").with_key_prefix("APP__");
+
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "db": { "port": 5432, "host": "localhost" } })
+        );
+
+        let source = source.with_labels([None, Some("prod")]);
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "db": { "port": 6543, "host": "localhost" } })
+        );
+
+        let source = source.with_labels([Some("dev")]);
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "debug": true })
+        );
+
+        Ok(())
+    }
+}
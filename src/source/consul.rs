@@ -0,0 +1,121 @@
+//! Consul KV store
+//! Requires the `consul` feature
+
+use std::future::Future;
+
+use crate::Error;
+
+use super::{hierarchical_key, AsyncEnvSource};
+
+/// An entry of the KV store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsulEntry {
+    /// The `/`-separated key, e.g. `app/prod/db/port`
+    pub key: String,
+
+    /// The decoded value, `None` for folders
+    pub value: Option<String>,
+}
+
+/// The calls [`ConsulSource`] needs from a Consul client.
+///
+/// Implement it for your client, e.g. by requesting `/v1/kv/<prefix>?recurse` and decoding the
+/// base64 values.
+pub trait ConsulClient {
+    /// Return all entries whose key starts with the prefix
+    fn list(&self, prefix: &str) -> impl Future<Output = Result<Vec<ConsulEntry>, Error>> + Send;
+}
+
+/// Entries below a prefix of the KV store.
+///
+/// Keys are translated into variable keys by stripping the prefix and joining the remaining parts
+/// with the separator, so `app/prod/db/port` below `app/prod` becomes `db__port`, prepended by the
+/// key prefix. Folders are skipped.
+#[derive(Debug, Clone)]
+pub struct ConsulSource<C> {
+    client: C,
+    prefix: String,
+    key_prefix: String,
+    separator: String,
+}
+
+impl<C: ConsulClient> ConsulSource<C> {
+    /// Return a source reading the entries below the prefix
+    pub fn new(client: C, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            prefix: prefix.into(),
+            key_prefix: String::new(),
+            separator: "__".to_string(),
+        }
+    }
+
+    /// Return the source with the given prefix prepended to every key, e.g. the prefix of the parser
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Return the source with the given separator joining the parts of keys
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+}
+
+impl<C: ConsulClient + Sync> AsyncEnvSource for ConsulSource<C> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let entries = self.client.list(&self.prefix).await?;
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| {
+                let key = hierarchical_key(&entry.key, &self.prefix, '/', &self.separator)?;
+                Some((format!("{}{key}", self.key_prefix), entry.value?))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{test_util::block_on, Parser};
+
+    struct FakeConsul;
+
+    impl ConsulClient for FakeConsul {
+        async fn list(&self, prefix: &str) -> Result<Vec<ConsulEntry>, Error> {
+            Ok([
+                ("app/prod/", None),
+                ("app/prod/db/", None),
+                ("app/prod/db/port", Some("5432")),
+                ("app/prod/hosts/0", Some("a")),
+                ("app/prod/hosts/1", Some("b")),
+                ("app/staging/db/port", Some("6543")),
+            ]
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| ConsulEntry {
+                key: key.to_string(),
+                value: value.map(str::to_string),
+            })
+            .collect())
+        }
+    }
+
+    #[test]
+    fn test_consul_source() -> Result<(), Error> {
+        let source = ConsulSource::new(FakeConsul, "app/prod/").with_key_prefix("APP__");
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "db": { "port": 5432 }, "hosts": ["a", "b"] })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! `KEY=VALUE` text in dotenv syntax
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Error;
+
+use super::EnvSource;
+
+/// A block of `KEY=VALUE` lines, e.g. the output of `env` or the content of a `.env` file.
+///
+/// Supported syntax:
+/// - blank lines and lines starting with `#` are ignored
+/// - an optional `export ` before the key
+/// - unquoted values are trimmed and end at an inline ` #` comment
+/// - single-quoted values are taken literally
+/// - double-quoted values support the escapes `\n`, `\r`, `\t`, `\"`, `\\`, `\$` and may span multiple lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DotenvText {
+    text: String,
+}
+
+impl DotenvText {
+    /// Return a source reading the given text
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+
+    /// Return a source reading the file at the given path
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(fs::read_to_string(path).map_err(Error::Io)?))
+    }
+}
+
+impl EnvSource for DotenvText {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        parse_dotenv(&self.text)
+    }
+}
+
+/// A `.env` file which is read again every time its variables are requested, e.g. when watching it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DotenvFile {
+    path: PathBuf,
+}
+
+impl DotenvFile {
+    /// Return a source reading the file at the given path
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl EnvSource for DotenvFile {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        parse_dotenv(&fs::read_to_string(&self.path).map_err(Error::Io)?)
+    }
+}
+
+/// Parse `KEY=VALUE` lines in dotenv syntax, see [`DotenvText`]
+pub fn parse_dotenv(text: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = vec![];
+    let mut lines = text.lines().enumerate();
+
+    while let Some((i, line)) = lines.next() {
+        let line_number = i + 1;
+        let line = line.trim_start();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, rest) = line.split_once('=').ok_or_else(|| Error::Syntax {
+            line: line_number,
+            message: "expected KEY=VALUE".to_string(),
+        })?;
+
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(Error::Syntax {
+                line: line_number,
+                message: format!("invalid key `{key}`"),
+            });
+        }
+
+        let rest = rest.trim_start();
+        let value = match rest.chars().next() {
+            Some('\'') => {
+                let end = rest[1..].find('\'').ok_or_else(|| Error::Syntax {
+                    line: line_number,
+                    message: "unterminated single quote".to_string(),
+                })?;
+                check_trailing(&rest[end + 2..], line_number)?;
+                rest[1..end + 1].to_string()
+            }
+            Some('"') => {
+                let mut value = String::new();
+                let mut quoted = rest[1..].to_string();
+
+                loop {
+                    if let Some(end) = unescape_until_quote(&quoted, &mut value) {
+                        check_trailing(&quoted[end + 1..], line_number)?;
+                        break;
+                    }
+
+                    let (_, next) = lines.next().ok_or_else(|| Error::Syntax {
+                        line: line_number,
+                        message: "unterminated double quote".to_string(),
+                    })?;
+                    value.push('\n');
+                    quoted = next.to_string();
+                }
+
+                value
+            }
+            _ => match rest.find(" #") {
+                Some(comment) => rest[..comment].trim_end().to_string(),
+                None => rest.trim_end().to_string(),
+            },
+        };
+
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Unescape a double-quoted value into `value` up to the closing quote, returning its position.
+/// Returns `None` if the closing quote is not on this line.
+fn unescape_until_quote(quoted: &str, value: &mut String) -> Option<usize> {
+    let mut chars = quoted.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some(i),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c @ ('"' | '\\' | '$' | '`'))) => value.push(c),
+                Some((_, c)) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// Only whitespace and comments may follow a closing quote
+fn check_trailing(trailing: &str, line: usize) -> Result<(), Error> {
+    let trailing = trailing.trim_start();
+
+    if trailing.is_empty() || trailing.starts_with('#') {
+        Ok(())
+    } else {
+        Err(Error::Syntax {
+            line,
+            message: format!("unexpected `{trailing}` after closing quote"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_parse_dotenv() -> Result<(), Error> {
+        let text = r#"
+# comment
+export PREFIX__INT=1
+PREFIX__STRING = hello world # inline comment
+PREFIX__HASH=a#b
+PREFIX__SINGLE='$literal \n'
+PREFIX__DOUBLE="line1\nline2 \"quoted\"" # comment
+PREFIX__MULTILINE="first
+second"
+PREFIX__EMPTY=
+"#;
+
+        assert_eq!(
+            parse_dotenv(text)?,
+            test_util::vars(&[
+                ("PREFIX__INT", "1"),
+                ("PREFIX__STRING", "hello world"),
+                ("PREFIX__HASH", "a#b"),
+                ("PREFIX__SINGLE", "$literal \\n"),
+                ("PREFIX__DOUBLE", "line1\nline2 \"quoted\""),
+                ("PREFIX__MULTILINE", "first\nsecond"),
+                ("PREFIX__EMPTY", ""),
+            ])
+            .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("NO_EQUALS", 1)]
+    #[case("\nA B=1", 2)]
+    #[case("A='unterminated", 1)]
+    #[case("A=\"unterminated\nB=1", 1)]
+    #[case("A=\"value\" trailing", 1)]
+    fn test_parse_dotenv_error(#[case] text: &str, #[case] expected_line: usize) {
+        assert!(matches!(
+            parse_dotenv(text),
+            Err(Error::Syntax { line, .. }) if line == expected_line
+        ));
+    }
+}
@@ -0,0 +1,191 @@
+//! etcd key-value store
+//! Requires the `etcd` feature
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use crate::Error;
+
+use super::{hierarchical_key, AsyncEnvSource, EnvSource};
+
+/// The calls [`EtcdSource`] needs from an etcd client.
+///
+/// Implement it for your client, e.g. around `etcd_client::Client::get` with
+/// `GetOptions::new().with_prefix()`.
+pub trait EtcdClient {
+    /// Return the `(key, value)` pairs of all keys starting with the prefix
+    fn range(
+        &self,
+        prefix: &str,
+    ) -> impl Future<Output = Result<Vec<(String, String)>, Error>> + Send;
+}
+
+/// A change of a key, e.g. from an etcd watch on the prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EtcdEvent {
+    /// The key was created or updated
+    Put { key: String, value: String },
+
+    /// The key was deleted
+    Delete { key: String },
+}
+
+/// The keys starting with a prefix of the store.
+///
+/// Keys are translated into variable keys by stripping the prefix and joining the remaining `/`-separated
+/// parts with the separator, so `/app/prod/db/port` below `/app/prod` becomes `db__port`, prepended
+/// by the key prefix.
+#[derive(Debug, Clone)]
+pub struct EtcdSource<C> {
+    client: C,
+    keys: EtcdKeys,
+}
+
+/// How keys below the prefix are translated into variable keys
+#[derive(Debug, Clone)]
+struct EtcdKeys {
+    prefix: String,
+    key_prefix: String,
+    separator: String,
+}
+
+impl EtcdKeys {
+    fn vars(&self, entries: impl IntoIterator<Item = (String, String)>) -> Vec<(String, String)> {
+        entries
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let key = hierarchical_key(&key, &self.prefix, '/', &self.separator)?;
+                Some((format!("{}{key}", self.key_prefix), value))
+            })
+            .collect()
+    }
+}
+
+impl<C: EtcdClient> EtcdSource<C> {
+    /// Return a source reading the keys starting with the prefix
+    pub fn new(client: C, prefix: impl Into<String>) -> Self {
+        Self {
+            client,
+            keys: EtcdKeys {
+                prefix: prefix.into(),
+                key_prefix: String::new(),
+                separator: "__".to_string(),
+            },
+        }
+    }
+
+    /// Return the source with the given prefix prepended to every key, e.g. the prefix of the parser
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.keys.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Return the source with the given separator joining the parts of keys
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.keys.separator = separator.into();
+        self
+    }
+
+    /// Read the keys into a snapshot, which can be kept up to date with the events of an etcd watch
+    pub async fn snapshot(&self) -> Result<EtcdSnapshot, Error> {
+        let entries = self.client.range(&self.keys.prefix).await?;
+
+        Ok(EtcdSnapshot {
+            entries: Arc::new(Mutex::new(entries.into_iter().collect())),
+            keys: self.keys.clone(),
+        })
+    }
+}
+
+impl<C: EtcdClient + Sync> AsyncEnvSource for EtcdSource<C> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self.keys.vars(self.client.range(&self.keys.prefix).await?))
+    }
+}
+
+/// The keys of an [`EtcdSource`] in memory, updated by applying [`EtcdEvent`]s.
+///
+/// Clones share the same keys, so a clone can be passed to the `Watch` of the `watch` feature
+/// while events of an etcd watch are applied to the original, reloading the configuration on changes.
+#[derive(Debug, Clone)]
+pub struct EtcdSnapshot {
+    entries: Arc<Mutex<BTreeMap<String, String>>>,
+    keys: EtcdKeys,
+}
+
+impl EtcdSnapshot {
+    /// Apply a change of a key
+    pub fn apply(&self, event: EtcdEvent) {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+
+        match event {
+            EtcdEvent::Put { key, value } => {
+                entries.insert(key, value);
+            }
+            EtcdEvent::Delete { key } => {
+                entries.remove(&key);
+            }
+        }
+    }
+}
+
+impl EnvSource for EtcdSnapshot {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+
+        Ok(self.keys.vars(entries.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{test_util::block_on, Parser};
+
+    struct FakeEtcd;
+
+    impl EtcdClient for FakeEtcd {
+        async fn range(&self, prefix: &str) -> Result<Vec<(String, String)>, Error> {
+            Ok([
+                ("/app/prod/db/port", "5432"),
+                ("/app/prod/db/host", "localhost"),
+                ("/app/staging/db/port", "6543"),
+            ]
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect())
+        }
+    }
+
+    #[test]
+    fn test_etcd_snapshot() -> Result<(), Error> {
+        let source = EtcdSource::new(FakeEtcd, "/app/prod/").with_key_prefix("APP__");
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "db": { "port": 5432, "host": "localhost" } })
+        );
+
+        let snapshot = block_on(source.snapshot())?;
+        snapshot.clone().apply(EtcdEvent::Put {
+            key: "/app/prod/db/port".to_string(),
+            value: "5433".to_string(),
+        });
+        snapshot.apply(EtcdEvent::Delete {
+            key: "/app/prod/db/host".to_string(),
+        });
+        assert_eq!(
+            parser.parse_iter(EnvSource::vars(&snapshot)?.into_iter())?,
+            json!({ "db": { "port": 5433 } })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+//! Google Cloud Secret Manager
+//! Requires the `gcp-secret-manager` feature
+
+use std::{collections::BTreeMap, future::Future};
+
+use crate::Error;
+
+use super::AsyncEnvSource;
+
+/// A secret of the project, without its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcpSecret {
+    /// The secret id, e.g. `APP__DB__PASSWORD`
+    pub id: String,
+
+    /// The labels of the secret
+    pub labels: BTreeMap<String, String>,
+}
+
+/// The calls [`GcpSecretSource`] needs from a Secret Manager client.
+///
+/// Implement it for your client, e.g. around `ListSecrets` and `AccessSecretVersion` of the project.
+pub trait GcpSecretClient {
+    /// Return all secrets of the project
+    fn list_secrets(&self) -> impl Future<Output = Result<Vec<GcpSecret>, Error>> + Send;
+
+    /// Return the value of the latest version of the secret
+    fn access_latest(&self, id: &str) -> impl Future<Output = Result<String, Error>> + Send;
+}
+
+/// Secrets selected by id prefix and labels, read as variables.
+///
+/// Secret ids are used as variable keys, so name secrets like variables, e.g. `APP__DB__PASSWORD`.
+/// Parse the source with [`crate::Parser::parse_secrets_from_async`] to redact the values
+/// from diagnostics.
+#[derive(Debug, Clone)]
+pub struct GcpSecretSource<C> {
+    client: C,
+    prefix: String,
+    labels: BTreeMap<String, String>,
+}
+
+impl<C: GcpSecretClient> GcpSecretSource<C> {
+    /// Return a source reading all secrets of the project
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            prefix: String::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Return the source reading only secrets whose id starts with the prefix, e.g. the prefix of the parser
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Return the source reading only secrets with the given label
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    fn is_selected(&self, secret: &GcpSecret) -> bool {
+        secret.id.starts_with(&self.prefix)
+            && self
+                .labels
+                .iter()
+                .all(|(key, value)| secret.labels.get(key) == Some(value))
+    }
+}
+
+impl<C: GcpSecretClient + Sync> AsyncEnvSource for GcpSecretSource<C> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut vars = vec![];
+
+        for secret in self.client.list_secrets().await? {
+            if self.is_selected(&secret) {
+                let value = self.client.access_latest(&secret.id).await?;
+                vars.push((secret.id, value));
+            }
+        }
+
+        Ok(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{test_util::block_on, Parser};
+
+    struct FakeSecretManager;
+
+    impl GcpSecretClient for FakeSecretManager {
+        async fn list_secrets(&self) -> Result<Vec<GcpSecret>, Error> {
+            Ok([
+                ("APP__DB__PASSWORD", "prod"),
+                ("APP__API_KEY", "prod"),
+                ("APP__DB__PASSWORD_STAGING", "staging"),
+                ("OTHER__TOKEN", "prod"),
+            ]
+            .map(|(id, env)| GcpSecret {
+                id: id.to_string(),
+                labels: [("env".to_string(), env.to_string())].into(),
+            })
+            .to_vec())
+        }
+
+        async fn access_latest(&self, id: &str) -> Result<String, Error> {
+            Ok(format!("value of {id}"))
+        }
+    }
+
+    #[test]
+    fn test_gcp_secret_source() -> Result<(), Error> {
+        let source = GcpSecretSource::new(FakeSecretManager)
+            .with_prefix("APP__")
+            .with_label("env", "prod");
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            block_on(parser.parse_secrets_from_async(&source))?,
+            json!({
+                "db": { "password": "value of APP__DB__PASSWORD" },
+                "api_key": "value of APP__API_KEY"
+            })
+        );
+
+        Ok(())
+    }
+}
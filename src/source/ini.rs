@@ -0,0 +1,159 @@
+//! INI files, with `[section]` headers prefixing their keys
+
+use std::{fs, path::Path};
+
+use crate::Error;
+
+use super::EnvSource;
+
+/// The content of an INI file.
+///
+/// The key of every `key = value` line is prefixed with its section and the separator,
+/// so `port` below `[db]` becomes `db__port`:
+/// - blank lines and lines starting with `;` or `#` are ignored
+/// - keys before the first section are not prefixed
+/// - dots in section names nest sections, e.g. `[db.replica]` prefixes keys with `db__replica__`
+/// - keys and values are separated by `=` or `:`, whitespace around both is trimmed
+/// - values in matching single or double quotes are unquoted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IniText {
+    text: String,
+    separator: String,
+}
+
+impl IniText {
+    /// Return a source reading the given text
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            separator: "__".to_string(),
+        }
+    }
+
+    /// Return a source reading the file at the given path
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(fs::read_to_string(path).map_err(Error::Io)?))
+    }
+
+    /// Return the source joining sections and keys with the given separator instead of `__`,
+    /// which should be the separator of the parser
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+}
+
+impl EnvSource for IniText {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        parse_ini(&self.text, &self.separator)
+    }
+}
+
+/// Parse the lines of an INI file into variables, see [`IniText`]
+pub fn parse_ini(text: &str, separator: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = vec![];
+    let mut section = String::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| Error::Syntax {
+                    line: line_number,
+                    message: format!("invalid section `{line}`"),
+                })?;
+
+            section = name
+                .split('.')
+                .map(str::trim)
+                .map(|part| format!("{part}{separator}"))
+                .collect();
+            continue;
+        }
+
+        let (key, value) = line.split_once(['=', ':']).ok_or_else(|| Error::Syntax {
+            line: line_number,
+            message: "expected `key = value`".to_string(),
+        })?;
+
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(Error::Syntax {
+                line: line_number,
+                message: "empty key".to_string(),
+            });
+        }
+
+        vars.push((format!("{section}{key}"), unquote(value.trim()).to_string()));
+    }
+
+    Ok(vars)
+}
+
+/// Strip matching single or double quotes around a value
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_parse_ini() -> Result<(), Error> {
+        let text = r#"
+; comment
+name = app
+
+[db]
+host = localhost
+port: 5432
+# comment
+password = "secret value"
+
+[db.replica]
+host = 'replica'
+"#;
+
+        assert_eq!(
+            parse_ini(text, "__")?,
+            test_util::vars(&[
+                ("name", "app"),
+                ("db__host", "localhost"),
+                ("db__port", "5432"),
+                ("db__password", "secret value"),
+                ("db__replica__host", "replica"),
+            ])
+            .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("[db", 1)]
+    #[case("[]", 1)]
+    #[case("\nno value", 2)]
+    #[case("= value", 1)]
+    fn test_parse_ini_error(#[case] text: &str, #[case] expected_line: usize) {
+        assert!(matches!(
+            parse_ini(text, "__"),
+            Err(Error::Syntax { line, .. }) if line == expected_line
+        ));
+    }
+}
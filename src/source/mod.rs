@@ -0,0 +1,190 @@
+//! Sources of environment variables
+//!
+//! Remote stores, e.g. the AWS Systems Manager Parameter Store or Consul, are read through a client
+//! trait per store, like `SsmClient`, which you implement for the client you already use, so this
+//! crate does not depend on a specific SDK, HTTP client or async runtime. Sources translating the
+//! hierarchical keys of a store into variable keys should be given the prefix and separator of the
+//! parser reading them.
+
+use std::{collections::BTreeMap, future::Future};
+
+use crate::Error;
+
+mod dotenv;
+mod ini;
+mod properties;
+mod secret_dir;
+mod snapshot;
+mod systemd;
+
+#[cfg(feature = "azure-app-config")]
+pub mod azure_app_config;
+#[cfg(feature = "consul")]
+pub mod consul;
+#[cfg(feature = "etcd")]
+pub mod etcd;
+#[cfg(feature = "gcp-secret-manager")]
+pub mod gcp_secret_manager;
+#[cfg(feature = "aws-ssm")]
+pub mod ssm;
+#[cfg(feature = "vault")]
+pub mod vault;
+
+pub use dotenv::{parse_dotenv, DotenvFile, DotenvText};
+pub use ini::{parse_ini, IniText};
+pub use properties::{parse_properties, PropertiesText};
+pub use secret_dir::SecretDir;
+pub use snapshot::{EnvDiff, EnvSnapshot};
+pub use systemd::{parse_systemd_env, SystemdEnvText};
+
+/// A source of `(key, value)` variables to parse
+pub trait EnvSource {
+    /// Return all variables of the source
+    fn vars(&self) -> Result<Vec<(String, String)>, Error>;
+}
+
+/// A source of `(key, value)` variables which is read without blocking, e.g. a remote secret store.
+///
+/// Implementations can use `async fn vars(&self)`. Every [`EnvSource`] is also an `AsyncEnvSource`.
+pub trait AsyncEnvSource {
+    /// Return all variables of the source
+    fn vars(&self) -> impl Future<Output = Result<Vec<(String, String)>, Error>> + Send;
+}
+
+impl<S: EnvSource + Sync + ?Sized> AsyncEnvSource for S {
+    fn vars(&self) -> impl Future<Output = Result<Vec<(String, String)>, Error>> + Send {
+        std::future::ready(EnvSource::vars(self))
+    }
+}
+
+#[cfg(any(
+    feature = "aws-ssm",
+    feature = "azure-app-config",
+    feature = "consul",
+    feature = "etcd"
+))]
+/// Translate the hierarchical name of a remote key, e.g. `/app/prod/db/port` below the root `/app/prod`,
+/// into a variable key by joining its parts with the separator, e.g. `db__port`.
+/// Return `None` if the name is not below the root
+pub(crate) fn hierarchical_key(
+    name: &str,
+    root: &str,
+    delimiter: char,
+    separator: &str,
+) -> Option<String> {
+    let rest = name.strip_prefix(root.trim_end_matches(delimiter))?;
+    if !rest.is_empty() && !rest.starts_with(delimiter) && !root.is_empty() {
+        return None;
+    }
+
+    let key = rest
+        .split(delimiter)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator);
+
+    (!key.is_empty()).then_some(key)
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+/// The environment of the current process
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProcessEnv;
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl EnvSource for ProcessEnv {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(std::env::vars().collect())
+    }
+}
+
+/// An in-memory environment, useful for examples and tests which must not mutate the process environment
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FakeEnv {
+    vars: BTreeMap<String, String>,
+}
+
+impl FakeEnv {
+    /// Return an empty environment
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the environment with the given variable set
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.set(key, value);
+        self
+    }
+
+    /// Set a variable, returning its previous value
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.vars.insert(key.into(), value.into())
+    }
+
+    /// Remove a variable, returning its previous value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.vars.remove(key)
+    }
+
+    /// Get the value of a variable
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars.get(key).map(String::as_str)
+    }
+}
+
+impl EnvSource for FakeEnv {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .vars
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for FakeEnv {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            vars: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+impl<S: EnvSource + ?Sized> EnvSource for &S {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        (**self).vars()
+    }
+}
+
+#[cfg(all(
+    test,
+    any(
+        feature = "aws-ssm",
+        feature = "azure-app-config",
+        feature = "consul",
+        feature = "etcd"
+    )
+))]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("/app/prod/db/port", "/app/prod", Some("db__port"))]
+    #[case("/app/prod/db/port", "/app/prod/", Some("db__port"))]
+    #[case("/app/production/db", "/app/prod", None)]
+    #[case("/other/db", "/app/prod", None)]
+    #[case("/app/prod", "/app/prod", None)]
+    #[case("db/port", "", Some("db__port"))]
+    fn test_hierarchical_key(
+        #[case] name: &str,
+        #[case] root: &str,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(hierarchical_key(name, root, '/', "__").as_deref(), expected);
+    }
+}
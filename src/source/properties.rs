@@ -0,0 +1,208 @@
+//! Java `.properties` files, with dot-separated keys
+
+use std::{fs, path::Path};
+
+use crate::Error;
+
+use super::EnvSource;
+
+/// The content of a Java `.properties` file.
+///
+/// The dots of every key are replaced with the separator, so `db.port=5432` becomes `db__port`
+/// and is parsed into `{ "db": { "port": 5432 } }`. Follows the rules of `java.util.Properties`:
+/// - blank lines and lines starting with `#` or `!` are ignored
+/// - keys and values are separated by `=`, `:` or whitespace
+/// - a backslash at the end of a line continues the value on the next line, without its leading whitespace
+/// - the escapes `\t`, `\n`, `\r`, `\f` and `\uXXXX` are supported, and a backslash before any other
+///   character is dropped, e.g. `\=` in a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertiesText {
+    text: String,
+    separator: String,
+}
+
+impl PropertiesText {
+    /// Return a source reading the given text
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            separator: "__".to_string(),
+        }
+    }
+
+    /// Return a source reading the file at the given path
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(fs::read_to_string(path).map_err(Error::Io)?))
+    }
+
+    /// Return the source replacing the dots of keys with the given separator instead of `__`,
+    /// which should be the separator of the parser
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+}
+
+impl EnvSource for PropertiesText {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        parse_properties(&self.text, &self.separator)
+    }
+}
+
+/// Parse the lines of a `.properties` file into variables, see [`PropertiesText`]
+pub fn parse_properties(text: &str, separator: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = vec![];
+    let mut lines = text.lines().enumerate();
+
+    while let Some((i, line)) = lines.next() {
+        let line_number = i + 1;
+        let line = line.trim_start();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+
+        // Join continued lines, dropping the backslash and the leading whitespace of the next line
+        let mut logical = line.to_string();
+        while ends_with_continuation(&logical) {
+            logical.pop();
+            match lines.next() {
+                Some((_, next)) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let (key, value) = split_key_value(&logical);
+        let key = unescape(key, line_number)?;
+        if key.is_empty() {
+            return Err(Error::Syntax {
+                line: line_number,
+                message: "empty key".to_string(),
+            });
+        }
+
+        vars.push((key.replace('.', separator), unescape(value, line_number)?));
+    }
+
+    Ok(vars)
+}
+
+/// Whether the line ends with an odd number of backslashes
+fn ends_with_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+/// Split a logical line at the first unescaped `=`, `:` or whitespace, returning the raw key and value
+fn split_key_value(line: &str) -> (&str, &str) {
+    let mut chars = line.char_indices();
+    let mut end = line.len();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '=' | ':' | ' ' | '\t' | '\x0c' => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let key = &line[..end];
+    let rest = line[end..].trim_start_matches([' ', '\t', '\x0c']);
+    let rest = rest
+        .strip_prefix(['=', ':'])
+        .unwrap_or(rest)
+        .trim_start_matches([' ', '\t', '\x0c']);
+
+    (key, rest)
+}
+
+/// Resolve the escapes of a key or value
+fn unescape(escaped: &str, line: usize) -> Result<String, Error> {
+    let mut unescaped = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('f') => unescaped.push('\x0c'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                let c = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 4)
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| Error::Syntax {
+                        line,
+                        message: format!("invalid unicode escape `\\u{hex}`"),
+                    })?;
+                unescaped.push(c);
+            }
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_parse_properties() -> Result<(), Error> {
+        let text = r#"
+# comment
+! comment
+app.name = app
+db.host:localhost
+db.port 5432
+db.url = jdbc:postgresql://localhost\
+         /app
+greeting = café\tbar
+key\=with\:separators = value
+empty =
+"#;
+
+        assert_eq!(
+            parse_properties(text, "__")?,
+            test_util::vars(&[
+                ("app__name", "app"),
+                ("db__host", "localhost"),
+                ("db__port", "5432"),
+                ("db__url", "jdbc:postgresql://localhost/app"),
+                ("greeting", "café\tbar"),
+                ("key=with:separators", "value"),
+                ("empty", ""),
+            ])
+            .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("= value", 1)]
+    #[case("\nkey = \\u00zz", 2)]
+    #[case("key = \\u00", 1)]
+    fn test_parse_properties_error(#[case] text: &str, #[case] expected_line: usize) {
+        assert!(matches!(
+            parse_properties(text, "__"),
+            Err(Error::Syntax { line, .. }) if line == expected_line
+        ));
+    }
+}
@@ -0,0 +1,73 @@
+//! A directory with one file per variable, e.g. mounted Docker or Kubernetes secrets
+
+use std::{fs, path::PathBuf};
+
+use crate::Error;
+
+use super::EnvSource;
+
+/// A directory with one file per variable: the file name is the key and the content the value.
+///
+/// Hidden files and subdirectories are ignored, such as the `..data` links Kubernetes creates.
+/// The directory is read again every time its variables are requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretDir {
+    path: PathBuf,
+}
+
+impl SecretDir {
+    /// Return a source reading the directory at the given path
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl EnvSource for SecretDir {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut vars = vec![];
+
+        for entry in fs::read_dir(&self.path).map_err(Error::Io)? {
+            let entry = entry.map_err(Error::Io)?;
+            let Some(key) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+
+            if key.starts_with('.') || !entry.path().is_file() {
+                continue;
+            }
+
+            vars.push((key, fs::read_to_string(entry.path()).map_err(Error::Io)?));
+        }
+
+        vars.sort();
+
+        Ok(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_secret_dir() -> Result<(), Error> {
+        let dir =
+            std::env::temp_dir().join(format!("env-vars-to-json-secrets-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).map_err(Error::Io)?;
+        fs::write(dir.join("PREFIX__PASSWORD"), "hunter2\n").map_err(Error::Io)?;
+        fs::write(dir.join("PREFIX__USER"), "admin").map_err(Error::Io)?;
+        fs::write(dir.join(".hidden"), "x").map_err(Error::Io)?;
+
+        let vars = SecretDir::new(&dir).vars();
+        fs::remove_dir_all(&dir).map_err(Error::Io)?;
+
+        assert_eq!(
+            vars?,
+            test_util::vars(&[("PREFIX__PASSWORD", "hunter2\n"), ("PREFIX__USER", "admin")])
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+}
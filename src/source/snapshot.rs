@@ -0,0 +1,144 @@
+//! Environments captured at a point in time, and the differences between them
+
+use std::collections::BTreeMap;
+
+use crate::Error;
+
+use super::EnvSource;
+
+/// The variables of an environment at one point in time, e.g. to find out what changed before
+/// re-parsing on reload
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EnvSnapshot {
+    vars: BTreeMap<String, String>,
+}
+
+impl EnvSnapshot {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    /// Capture the variables of the process environment
+    pub fn capture() -> Self {
+        std::env::vars().collect()
+    }
+
+    /// Capture the variables of a source
+    pub fn capture_from(source: &(impl EnvSource + ?Sized)) -> Result<Self, Error> {
+        Ok(source.vars()?.into_iter().collect())
+    }
+
+    /// Get the value of a variable
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars.get(key).map(String::as_str)
+    }
+
+    /// Return the variables added, removed and changed from this snapshot to the other
+    pub fn diff(&self, other: &EnvSnapshot) -> EnvDiff {
+        let mut diff = EnvDiff::default();
+
+        for (key, value) in &self.vars {
+            match other.vars.get(key) {
+                None => {
+                    diff.removed.insert(key.clone(), value.clone());
+                }
+                Some(new) if new != value => {
+                    diff.changed
+                        .insert(key.clone(), (value.clone(), new.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (key, value) in &other.vars {
+            if !self.vars.contains_key(key) {
+                diff.added.insert(key.clone(), value.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+impl EnvSource for EnvSnapshot {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .vars
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for EnvSnapshot {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            vars: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+/// The differences between two [`EnvSnapshot`]s
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EnvDiff {
+    /// Variables which are only in the newer snapshot, with their value
+    pub added: BTreeMap<String, String>,
+
+    /// Variables which are only in the older snapshot, with their value
+    pub removed: BTreeMap<String, String>,
+
+    /// Variables whose value changed, with their old and new value
+    pub changed: BTreeMap<String, (String, String)>,
+}
+
+impl EnvDiff {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Return every variable which differs with its value before and after the change,
+    /// `None` if it did not exist
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>, Option<&str>)> {
+        let added = self
+            .added
+            .iter()
+            .map(|(key, value)| (key.as_str(), None, Some(value.as_str())));
+        let removed = self
+            .removed
+            .iter()
+            .map(|(key, value)| (key.as_str(), Some(value.as_str()), None));
+        let changed = self
+            .changed
+            .iter()
+            .map(|(key, (old, new))| (key.as_str(), Some(old.as_str()), Some(new.as_str())));
+
+        added.chain(removed).chain(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old = EnvSnapshot::from_iter([("KEPT", "1"), ("CHANGED", "a"), ("REMOVED", "x")]);
+        let new = EnvSnapshot::from_iter([("KEPT", "1"), ("CHANGED", "b"), ("ADDED", "y")]);
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            EnvDiff {
+                added: BTreeMap::from([("ADDED".to_string(), "y".to_string())]),
+                removed: BTreeMap::from([("REMOVED".to_string(), "x".to_string())]),
+                changed: BTreeMap::from([(
+                    "CHANGED".to_string(),
+                    ("a".to_string(), "b".to_string())
+                )]),
+            }
+        );
+        assert!(!diff.is_empty());
+        assert!(old.diff(&old.clone()).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! AWS Systems Manager Parameter Store
+//! Requires the `aws-ssm` feature
+
+use std::future::Future;
+
+use crate::Error;
+
+use super::{hierarchical_key, AsyncEnvSource};
+
+/// A parameter of the parameter store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SsmParameter {
+    /// The full hierarchical name, e.g. `/app/prod/db/password`
+    pub name: String,
+
+    /// The value, decrypted if requested
+    pub value: String,
+}
+
+/// The calls [`SsmSource`] needs from a parameter store client.
+///
+/// Implement it for your client, e.g. by wrapping `aws_sdk_ssm::Client::get_parameters_by_path`
+/// and following its pagination.
+pub trait SsmClient {
+    /// Return all parameters below the path, recursively
+    fn get_parameters_by_path(
+        &self,
+        path: &str,
+        with_decryption: bool,
+    ) -> impl Future<Output = Result<Vec<SsmParameter>, Error>> + Send;
+}
+
+/// Parameters below a path of the parameter store.
+///
+/// Parameter names are translated into variable keys by stripping the path and joining the
+/// remaining parts with the separator, so `/app/prod/db/port` below `/app/prod` becomes `db__port`,
+/// prepended by the key prefix.
+#[derive(Debug, Clone)]
+pub struct SsmSource<C> {
+    client: C,
+    path: String,
+    with_decryption: bool,
+    key_prefix: String,
+    separator: String,
+}
+
+impl<C: SsmClient> SsmSource<C> {
+    /// Return a source reading the parameters below the path, decrypting secure strings
+    pub fn new(client: C, path: impl Into<String>) -> Self {
+        Self {
+            client,
+            path: path.into(),
+            with_decryption: true,
+            key_prefix: String::new(),
+            separator: "__".to_string(),
+        }
+    }
+
+    /// Return the source with secure strings decrypted or not
+    pub fn with_decryption(mut self, with_decryption: bool) -> Self {
+        self.with_decryption = with_decryption;
+        self
+    }
+
+    /// Return the source with the given prefix prepended to every key, e.g. the prefix of the parser
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Return the source with the given separator joining the parts of parameter names
+    pub fn with_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+}
+
+impl<C: SsmClient + Sync> AsyncEnvSource for SsmSource<C> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let parameters = self
+            .client
+            .get_parameters_by_path(&self.path, self.with_decryption)
+            .await?;
+
+        Ok(parameters
+            .into_iter()
+            .filter_map(|parameter| {
+                let key = hierarchical_key(&parameter.name, &self.path, '/', &self.separator)?;
+                Some((format!("{}{key}", self.key_prefix), parameter.value))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{test_util::block_on, Parser};
+
+    struct FakeSsm;
+
+    impl SsmClient for FakeSsm {
+        async fn get_parameters_by_path(
+            &self,
+            path: &str,
+            with_decryption: bool,
+        ) -> Result<Vec<SsmParameter>, Error> {
+            assert_eq!(path, "/app/prod");
+            let password = if with_decryption {
+                "hunter2"
+            } else {
+                "encrypted"
+            };
+
+            Ok([
+                ("/app/prod/db/port", "5432"),
+                ("/app/prod/db/password", password),
+            ]
+            .map(|(name, value)| SsmParameter {
+                name: name.to_string(),
+                value: value.to_string(),
+            })
+            .to_vec())
+        }
+    }
+
+    #[test]
+    fn test_ssm_source() -> Result<(), Error> {
+        let source = SsmSource::new(FakeSsm, "/app/prod").with_key_prefix("APP__");
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "db": { "port": 5432, "password": "hunter2" } })
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! `KEY=VALUE` text following the rules of systemd `EnvironmentFile=`
+
+use std::{fs, path::Path};
+
+use crate::Error;
+
+use super::EnvSource;
+
+/// The content of a systemd `EnvironmentFile=`.
+///
+/// Follows systemd semantics rather than dotenv ones:
+/// - blank lines and lines starting with `#` or `;` are ignored, there are no inline comments
+/// - a backslash at the end of a line continues the value on the next line
+/// - outside of quotes a backslash escapes the next character
+/// - single quotes are taken literally, double quotes support the escapes `\"`, `\\`, `\$` and `` \` ``
+/// - quoted and unquoted parts can be mixed, e.g. `A=foo"bar baz"` is `foobar baz`
+/// - whitespace around unquoted values is trimmed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemdEnvText {
+    text: String,
+}
+
+impl SystemdEnvText {
+    /// Return a source reading the given text
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into() }
+    }
+
+    /// Return a source reading the file at the given path
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(fs::read_to_string(path).map_err(Error::Io)?))
+    }
+}
+
+impl EnvSource for SystemdEnvText {
+    fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        parse_systemd_env(&self.text)
+    }
+}
+
+enum State {
+    Unquoted,
+    SingleQuoted,
+    DoubleQuoted,
+}
+
+/// Parse `KEY=VALUE` lines following systemd `EnvironmentFile=` rules, see [`SystemdEnvText`]
+pub fn parse_systemd_env(text: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut vars = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        // Skip leading whitespace and blank lines
+        while let Some(c) = chars.next_if(|c| c.is_whitespace()) {
+            if c == '\n' {
+                line += 1;
+            }
+        }
+
+        match chars.peek() {
+            None => break,
+            Some('#' | ';') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let start_line = line;
+        let mut key = String::new();
+        let mut assigned = false;
+
+        for c in chars.by_ref() {
+            match c {
+                '=' => {
+                    assigned = true;
+                    break;
+                }
+                '\n' => {
+                    line += 1;
+                    break;
+                }
+                c => key.push(c),
+            }
+        }
+
+        let key = key.trim();
+        if !assigned || key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(Error::Syntax {
+                line: start_line,
+                message: format!("invalid assignment `{key}`, expected KEY=VALUE"),
+            });
+        }
+
+        let mut value = String::new();
+        // Length of the value up to the last quoted or escaped character, which must not be trimmed
+        let mut kept = 0;
+        let mut state = State::Unquoted;
+
+        while let Some(c) = chars.next() {
+            match state {
+                State::Unquoted => match c {
+                    '\n' => {
+                        line += 1;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some('\n') => line += 1,
+                        Some(c) => {
+                            value.push(c);
+                            kept = value.len();
+                        }
+                        None => {}
+                    },
+                    '\'' => state = State::SingleQuoted,
+                    '"' => state = State::DoubleQuoted,
+                    c if c.is_whitespace() && value.is_empty() => {}
+                    c => value.push(c),
+                },
+                State::SingleQuoted => match c {
+                    '\'' => {
+                        state = State::Unquoted;
+                        kept = value.len();
+                    }
+                    c => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        value.push(c);
+                    }
+                },
+                State::DoubleQuoted => match c {
+                    '"' => {
+                        state = State::Unquoted;
+                        kept = value.len();
+                    }
+                    '\\' => match chars.next() {
+                        Some('\n') => line += 1,
+                        Some(c @ ('"' | '\\' | '$' | '`')) => value.push(c),
+                        Some(c) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                        None => {}
+                    },
+                    c => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        value.push(c);
+                    }
+                },
+            }
+        }
+
+        if !matches!(state, State::Unquoted) {
+            return Err(Error::Syntax {
+                line: start_line,
+                message: format!("unterminated quote in value of `{key}`"),
+            });
+        }
+
+        let trimmed = value.trim_end().len().max(kept);
+        value.truncate(trimmed);
+        vars.push((key.to_string(), value));
+    }
+
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_parse_systemd_env() -> Result<(), Error> {
+        let text = r#"
+# comment
+; also a comment
+PREFIX__INT=1
+PREFIX__HASH=a #not a comment
+PREFIX__CONTINUED=first \
+second
+PREFIX__MIXED=foo"bar baz"'$qux'
+PREFIX__ESCAPED=a\ b\\
+PREFIX__DOUBLE="say \"hi\" \n"
+PREFIX__KEPT="trailing  "
+PREFIX__EMPTY=
+"#;
+
+        assert_eq!(
+            parse_systemd_env(text)?,
+            test_util::vars(&[
+                ("PREFIX__INT", "1"),
+                ("PREFIX__HASH", "a #not a comment"),
+                ("PREFIX__CONTINUED", "first second"),
+                ("PREFIX__MIXED", "foobar baz$qux"),
+                ("PREFIX__ESCAPED", "a b\\"),
+                ("PREFIX__DOUBLE", "say \"hi\" \\n"),
+                ("PREFIX__KEPT", "trailing  "),
+                ("PREFIX__EMPTY", ""),
+            ])
+            .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_systemd_env_error() {
+        assert!(matches!(
+            parse_systemd_env("A=1\nB=\"unterminated\n"),
+            Err(Error::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_systemd_env("A=1\nNO_EQUALS\n"),
+            Err(Error::Syntax { .. })
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! HashiCorp Vault KV version 2 secrets engine
+//! Requires the `vault` feature
+
+use std::future::Future;
+
+use serde_json::{Map, Value};
+
+use crate::Error;
+
+use super::{AsyncEnvSource, EnvSource};
+
+/// The calls [`VaultSource`] and [`VaultRefs`] need from a Vault client.
+///
+/// Implement it for your client, e.g. around `vaultrs::kv2::read`.
+pub trait VaultClient {
+    /// Return the data of the latest version of the secret at the path of a KV v2 mount
+    fn read_secret(
+        &self,
+        mount: &str,
+        path: &str,
+    ) -> impl Future<Output = Result<Map<String, Value>, Error>> + Send;
+}
+
+/// The fields of a secret of a KV v2 mount, read as variables.
+///
+/// Field names are used as variable keys, prepended by the key prefix. Fields which are not strings
+/// are read as their json text. Parse it with [`crate::Parser::parse_secrets_from_async`] to redact
+/// the values from diagnostics.
+#[derive(Debug, Clone)]
+pub struct VaultSource<C> {
+    client: C,
+    mount: String,
+    path: String,
+    key_prefix: String,
+}
+
+impl<C: VaultClient> VaultSource<C> {
+    /// Return a source reading the secret at the path of the mount, e.g. `secret` and `app/prod`
+    pub fn new(client: C, mount: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            client,
+            mount: mount.into(),
+            path: path.into(),
+            key_prefix: String::new(),
+        }
+    }
+
+    /// Return the source with the given prefix prepended to every key, e.g. the prefix of the parser
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+}
+
+impl<C: VaultClient + Sync> AsyncEnvSource for VaultSource<C> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let data = self.client.read_secret(&self.mount, &self.path).await?;
+
+        Ok(data
+            .into_iter()
+            .map(|(field, value)| (format!("{}{field}", self.key_prefix), text(value)))
+            .collect())
+    }
+}
+
+/// Variables of another source, where variables ending with the suffix refer to a secret field,
+/// like the `_FILE` convention refers to a file.
+///
+/// `APP__DB__PASSWORD_VAULT=app/prod#password` is read as `APP__DB__PASSWORD` with the value of
+/// the field `password` of the secret `app/prod`. The default suffix is `_VAULT`.
+#[derive(Debug, Clone)]
+pub struct VaultRefs<C, S> {
+    client: C,
+    mount: String,
+    source: S,
+    suffix: String,
+}
+
+impl<C: VaultClient, S: EnvSource> VaultRefs<C, S> {
+    /// Return a source resolving the references of the source to secrets of the mount
+    pub fn new(client: C, mount: impl Into<String>, source: S) -> Self {
+        Self {
+            client,
+            mount: mount.into(),
+            source,
+            suffix: "_VAULT".to_string(),
+        }
+    }
+
+    /// Return the source with the given suffix marking references
+    pub fn with_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.suffix = suffix.into();
+        self
+    }
+}
+
+impl<C: VaultClient + Sync, S: EnvSource + Sync> AsyncEnvSource for VaultRefs<C, S> {
+    async fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut vars = vec![];
+
+        for (key, value) in self.source.vars()? {
+            let Some(key) = key.strip_suffix(&self.suffix) else {
+                vars.push((key, value));
+                continue;
+            };
+
+            let (path, field) = value.rsplit_once('#').ok_or_else(|| {
+                Error::Config(format!(
+                    "`{key}{}` must refer to a secret field as `path#field`",
+                    self.suffix
+                ))
+            })?;
+            let mut data = self.client.read_secret(&self.mount, path).await?;
+            let value = data
+                .remove(field)
+                .ok_or_else(|| Error::Config(format!("secret `{path}` has no field `{field}`")))?;
+
+            vars.push((key.to_string(), text(value)));
+        }
+
+        Ok(vars)
+    }
+}
+
+/// Return the text of a secret field
+fn text(value: Value) -> String {
+    match value {
+        Value::String(value) => value,
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{source::FakeEnv, test_util::block_on, Parser};
+
+    struct FakeVault;
+
+    impl VaultClient for FakeVault {
+        async fn read_secret(&self, mount: &str, path: &str) -> Result<Map<String, Value>, Error> {
+            assert_eq!(mount, "secret");
+
+            match json!({
+                "app/prod": { "DB__PASSWORD": "hunter2", "DB__PORT": 5432 },
+                "shared": { "token": "abc" },
+            })
+            .get(path)
+            {
+                Some(Value::Object(data)) => Ok(data.clone()),
+                _ => Err(format!("no secret at `{path}`").into()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_vault_source() -> Result<(), Error> {
+        let source = VaultSource::new(FakeVault, "secret", "app/prod").with_key_prefix("APP__");
+        let vars = block_on(source.vars())?;
+        let parser = Parser::default()
+            .with_prefix("APP__")
+            .with_secret_vars(vars.iter().map(|(key, _)| key));
+
+        assert!(parser.is_secret(&["db".into(), "password".into()]));
+        assert_eq!(
+            parser.parse_iter(vars.into_iter())?,
+            json!({ "db": { "password": "hunter2", "port": 5432 } })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vault_refs() -> Result<(), Error> {
+        let env = FakeEnv::new()
+            .with("APP__TOKEN_VAULT", "shared#token")
+            .with("APP__PORT", "8080");
+        let parser = Parser::default().with_prefix("APP__");
+
+        let source = VaultRefs::new(FakeVault, "secret", &env);
+        assert_eq!(
+            block_on(parser.parse_from_async(&source))?,
+            json!({ "token": "abc", "port": 8080 })
+        );
+
+        let env = env.with("APP__MISSING_VAULT", "shared#missing");
+        let source = VaultRefs::new(FakeVault, "secret", &env);
+        assert!(matches!(block_on(source.vars()), Err(Error::Config(_))));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,608 @@
+//! Specification of the environment variables a parser is expected to read
+
+use std::collections::BTreeSet;
+
+use serde_json::{json, Map, Value};
+
+use crate::Parser;
+
+/// An expected environment variable
+#[derive(Debug, Clone, Default)]
+pub struct VarSpec {
+    /// Path of the variable without the prefix, with key parts joined by the parser separator, e.g. `struct__int`
+    pub path: String,
+
+    /// Human readable description of the variable
+    pub description: Option<String>,
+
+    /// Example or default value of the variable
+    pub default: Option<String>,
+
+    /// Whether the variable must be set
+    pub required: bool,
+}
+
+impl VarSpec {
+    /// Return a new variable spec with the given path
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Return a new variable spec with the given description
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Return a new variable spec with the given default value
+    pub fn with_default(mut self, default: impl Into<String>) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    /// Return a new variable spec with the given required flag
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    /// Name of the environment variable for the given parser, e.g. `PREFIX__STRUCT__INT`
+    pub fn env_name(&self, parser: &Parser) -> String {
+        format!(
+            "{}{}",
+            parser.prefix.as_deref().unwrap_or_default(),
+            self.path.to_uppercase()
+        )
+    }
+}
+
+/// The set of environment variables a parser is expected to read
+#[derive(Debug, Clone, Default)]
+pub struct EnvSpec {
+    /// The expected variables, in declaration order
+    pub vars: Vec<VarSpec>,
+}
+
+impl EnvSpec {
+    /// Return a new spec with the given variable added
+    pub fn with_var(mut self, var: VarSpec) -> Self {
+        self.vars.push(var);
+        self
+    }
+
+    /// Return a new spec with the description attached to the variable at the given path,
+    /// adding the variable if it is not declared yet
+    pub fn with_description(mut self, path: &str, description: impl Into<String>) -> Self {
+        match self.vars.iter_mut().find(|var| var.path == path) {
+            Some(var) => var.description = Some(description.into()),
+            None => self
+                .vars
+                .push(VarSpec::new(path).with_description(description)),
+        }
+        self
+    }
+
+    /// Get the variable at the given path
+    pub fn get(&self, path: &str) -> Option<&VarSpec> {
+        self.vars.iter().find(|var| var.path == path)
+    }
+
+    /// Render a `.env.example` file, with descriptions as comments
+    pub fn to_env_example(&self, parser: &Parser) -> String {
+        let mut output = String::new();
+
+        for var in &self.vars {
+            if let Some(description) = &var.description {
+                for line in description.lines() {
+                    output.push_str(&format!("# {line}\n"));
+                }
+            }
+
+            if var.required {
+                output.push_str("# (required)\n");
+            }
+
+            output.push_str(&format!(
+                "{}={}\n",
+                var.env_name(parser),
+                var.default.as_deref().unwrap_or_default()
+            ));
+        }
+
+        output
+    }
+
+    /// Render a Markdown table documenting the variables
+    pub fn to_markdown(&self, parser: &Parser) -> String {
+        let mut output =
+            String::from("| Variable | Required | Default | Description |\n|---|---|---|---|\n");
+
+        for var in &self.vars {
+            output.push_str(&format!(
+                "| `{}` | {} | {} | {} |\n",
+                var.env_name(parser),
+                if var.required { "yes" } else { "no" },
+                var.default
+                    .as_ref()
+                    .map(|default| format!("`{default}`"))
+                    .unwrap_or_default(),
+                escape_markdown_cell(var.description.as_deref().unwrap_or_default()),
+            ));
+        }
+
+        output
+    }
+
+    /// Render an HTML table documenting the variables
+    pub fn to_html(&self, parser: &Parser) -> String {
+        let mut output = String::from(
+            "<table>\n<thead>\n<tr><th>Variable</th><th>Required</th><th>Default</th><th>Description</th></tr>\n</thead>\n<tbody>\n",
+        );
+
+        for var in &self.vars {
+            output.push_str(&format!(
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&var.env_name(parser)),
+                if var.required { "yes" } else { "no" },
+                var.default
+                    .as_ref()
+                    .map(|default| format!("<code>{}</code>", escape_html(default)))
+                    .unwrap_or_default(),
+                escape_html(var.description.as_deref().unwrap_or_default()),
+            ));
+        }
+
+        output.push_str("</tbody>\n</table>\n");
+        output
+    }
+
+    /// Render an OpenAPI-like parameter listing, one parameter object per variable
+    pub fn to_openapi_parameters(&self, parser: &Parser) -> Value {
+        self.vars
+            .iter()
+            .map(|var| {
+                let mut schema = Map::new();
+                if let Some(default) = &var.default {
+                    let default = coerce_default(parser, var, default);
+                    schema.insert("type".to_string(), json!(json_type(&default)));
+                    schema.insert("default".to_string(), default);
+                } else {
+                    schema.insert("type".to_string(), json!("string"));
+                }
+
+                let mut parameter = Map::new();
+                parameter.insert("name".to_string(), json!(var.env_name(parser)));
+                parameter.insert("in".to_string(), json!("environment"));
+                if let Some(description) = &var.description {
+                    parameter.insert("description".to_string(), json!(description));
+                }
+                parameter.insert("required".to_string(), json!(var.required));
+                parameter.insert("schema".to_string(), Value::Object(schema));
+
+                Value::Object(parameter)
+            })
+            .collect()
+    }
+
+    /// Render a JSON Schema describing the json produced by the parser
+    pub fn to_json_schema(&self, parser: &Parser) -> Value {
+        let mut schema = object_schema();
+
+        for var in &self.vars {
+            let parts = var.path.split(&parser.separator).collect::<Vec<_>>();
+            insert_schema(&mut schema, &parts, var, parser);
+        }
+
+        schema
+    }
+}
+
+/// Infer a JSON Schema from a parsed document, e.g. to bootstrap a schema from a known-good
+/// environment: the type of every value, with the keys of objects required.
+///
+/// The items of an array are merged into one schema: keys are required if every object item
+/// has them, and mixed items list all their types. `null` items are skipped, as they are the
+/// placeholders parsing creates for missing indices.
+///
+/// ```
+/// use env_vars_to_json::spec::infer_schema;
+/// use serde_json::json;
+///
+/// let schema = infer_schema(&json!({ "ports": [80, 443] }));
+/// assert_eq!(
+///     schema,
+///     json!({
+///         "type": "object",
+///         "required": ["ports"],
+///         "properties": {
+///             "ports": { "type": "array", "items": { "type": "integer" } }
+///         }
+///     })
+/// );
+/// ```
+pub fn infer_schema(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut schema = object_schema();
+            if !map.is_empty() {
+                let mut required = map.keys().collect::<Vec<_>>();
+                required.sort();
+                schema["required"] = required.into_iter().map(|key| json!(key)).collect();
+            }
+            schema["properties"] = map
+                .iter()
+                .map(|(key, value)| (key.clone(), infer_schema(value)))
+                .collect();
+            schema
+        }
+        Value::Array(items) => {
+            let mut schema = array_schema();
+            if let Some(items) = items
+                .iter()
+                .filter(|item| !item.is_null())
+                .map(infer_schema)
+                .reduce(merge_schemas)
+            {
+                schema["items"] = items;
+            }
+            schema
+        }
+        value => json!({ "type": json_type(value) }),
+    }
+}
+
+/// Merge the inferred schemas of two items of an array into one schema accepting both
+fn merge_schemas(a: Value, b: Value) -> Value {
+    if a == b || b.get("type").is_none() {
+        return a;
+    }
+    if a.get("type").is_none() {
+        return b;
+    }
+
+    match (a["type"].as_str(), b["type"].as_str()) {
+        (Some("object"), Some("object")) => {
+            let required_b = b["required"].as_array().cloned().unwrap_or_default();
+            let required = a["required"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|key| required_b.contains(key))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let Value::Object(mut properties) = a["properties"].clone() else {
+                unreachable!("object schemas have properties")
+            };
+            for (key, schema) in b["properties"].as_object().into_iter().flatten() {
+                let schema = match properties.remove(key) {
+                    Some(existing) => merge_schemas(existing, schema.clone()),
+                    None => schema.clone(),
+                };
+                properties.insert(key.clone(), schema);
+            }
+
+            let mut schema = object_schema();
+            if !required.is_empty() {
+                schema["required"] = Value::Array(required);
+            }
+            schema["properties"] = Value::Object(properties);
+            schema
+        }
+        (Some("array"), Some("array")) => {
+            let mut schema = array_schema();
+            schema["items"] = merge_schemas(a["items"].clone(), b["items"].clone());
+            schema
+        }
+        (Some("integer"), Some("number")) | (Some("number"), Some("integer")) => {
+            json!({ "type": "number" })
+        }
+        _ => {
+            // Mixed items, only their types are kept
+            let types = [&a["type"], &b["type"]]
+                .into_iter()
+                .flat_map(|types| match types {
+                    Value::Array(types) => types.clone(),
+                    types => vec![types.clone()],
+                })
+                .filter_map(|types| types.as_str().map(str::to_string))
+                .collect::<BTreeSet<_>>();
+            json!({ "type": types })
+        }
+    }
+}
+
+fn object_schema() -> Value {
+    json!({ "type": "object", "properties": {} })
+}
+
+fn array_schema() -> Value {
+    json!({ "type": "array", "items": {} })
+}
+
+/// Insert the schema of a variable, creating intermediate object and array schemas on the way down
+fn insert_schema(schema: &mut Value, parts: &[&str], var: &VarSpec, parser: &Parser) {
+    let Some((part, rest)) = parts.split_first() else {
+        return;
+    };
+
+    if var.required && schema["type"] == "object" {
+        let required = schema
+            .as_object_mut()
+            .expect("schema is an object")
+            .entry("required")
+            .or_insert_with(|| json!([]))
+            .as_array_mut()
+            .expect("required is an array");
+
+        if !required.iter().any(|key| key == part) {
+            required.push(json!(part));
+        }
+    }
+
+    let next = rest.first().map(|next| {
+        if next.parse::<usize>().is_ok() {
+            array_schema()
+        } else {
+            object_schema()
+        }
+    });
+
+    let child = if schema["type"] == "array" {
+        &mut schema["items"]
+    } else {
+        let properties = schema["properties"]
+            .as_object_mut()
+            .expect("properties is an object");
+        properties.entry(*part).or_insert_with(|| json!({}))
+    };
+
+    match next {
+        Some(next) => {
+            if child.get("type").is_none() {
+                *child = next;
+            }
+            insert_schema(child, rest, var, parser);
+        }
+        None => {
+            let leaf = child.as_object_mut().expect("schema is an object");
+            leaf_schema(leaf, var, parser);
+        }
+    }
+}
+
+fn leaf_schema(leaf: &mut Map<String, Value>, var: &VarSpec, parser: &Parser) {
+    if let Some(description) = &var.description {
+        leaf.insert("description".to_string(), json!(description));
+    }
+
+    if let Some(default) = &var.default {
+        leaf.insert("default".to_string(), coerce_default(parser, var, default));
+    }
+}
+
+/// Coerce a default value the same way the parser coerces variables
+fn coerce_default(parser: &Parser, var: &VarSpec, default: &str) -> Value {
+    parser
+        .coerce_value(default.into(), parser.keep_leading_zeros_at_path(&var.path))
+        .unwrap_or_else(|_| json!(default))
+}
+
+/// Name of the JSON Schema type of a scalar value
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+impl Parser {
+    /// Return a new parser which rejects variables with the prefix that are not declared in the spec,
+    /// or below a declared path, see [`Parser::with_known_paths`]
+    pub fn with_strict_spec(self, spec: &EnvSpec) -> Self {
+        self.with_known_paths(spec.vars.iter().map(|var| &var.path))
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_markdown_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn spec() -> EnvSpec {
+        EnvSpec::default()
+            .with_var(
+                VarSpec::new("server__port")
+                    .with_default("8080")
+                    .with_required(true),
+            )
+            .with_var(VarSpec::new("server__hosts__0").with_default("localhost"))
+            .with_description("server__port", "Port to listen on")
+    }
+
+    #[test]
+    fn test_env_example() {
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            spec().to_env_example(&parser),
+            "# Port to listen on\n# (required)\nAPP__SERVER__PORT=8080\nAPP__SERVER__HOSTS__0=localhost\n"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            spec().to_markdown(&parser),
+            "| Variable | Required | Default | Description |\n\
+             |---|---|---|---|\n\
+             | `APP__SERVER__PORT` | yes | `8080` | Port to listen on |\n\
+             | `APP__SERVER__HOSTS__0` | no | `localhost` |  |\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let parser = Parser::default().with_prefix("APP__");
+        let spec = EnvSpec::default().with_var(
+            VarSpec::new("name")
+                .with_default("<none>")
+                .with_description("Name & title"),
+        );
+
+        assert_eq!(
+            spec.to_html(&parser),
+            "<table>\n<thead>\n<tr><th>Variable</th><th>Required</th><th>Default</th><th>Description</th></tr>\n</thead>\n<tbody>\n\
+             <tr><td><code>APP__NAME</code></td><td>no</td><td><code>&lt;none&gt;</code></td><td>Name &amp; title</td></tr>\n\
+             </tbody>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn test_openapi_parameters() {
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            spec().to_openapi_parameters(&parser),
+            json!([
+                {
+                    "name": "APP__SERVER__PORT",
+                    "in": "environment",
+                    "description": "Port to listen on",
+                    "required": true,
+                    "schema": { "type": "integer", "default": 8080 }
+                },
+                {
+                    "name": "APP__SERVER__HOSTS__0",
+                    "in": "environment",
+                    "required": false,
+                    "schema": { "type": "string", "default": "localhost" }
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let parser = Parser::default().with_prefix("APP__");
+
+        assert_eq!(
+            spec().to_json_schema(&parser),
+            json!({
+                "type": "object",
+                "required": ["server"],
+                "properties": {
+                    "server": {
+                        "type": "object",
+                        "required": ["port"],
+                        "properties": {
+                            "port": {
+                                "description": "Port to listen on",
+                                "default": 8080
+                            },
+                            "hosts": {
+                                "type": "array",
+                                "items": {
+                                    "default": "localhost"
+                                }
+                            }
+                        }
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let value = json!({
+            "server": { "port": 80, "ratio": 0.5, "tls": false },
+            "hosts": ["a", null, "b"],
+            "upstreams": [{ "url": "x", "weight": 1 }, { "url": "y", "weight": 1.5 }, { "url": "z" }],
+            "mixed": [1, "a", true],
+            "empty": []
+        });
+
+        assert_eq!(
+            infer_schema(&value),
+            json!({
+                "type": "object",
+                "required": ["empty", "hosts", "mixed", "server", "upstreams"],
+                "properties": {
+                    "server": {
+                        "type": "object",
+                        "required": ["port", "ratio", "tls"],
+                        "properties": {
+                            "port": { "type": "integer" },
+                            "ratio": { "type": "number" },
+                            "tls": { "type": "boolean" }
+                        }
+                    },
+                    "hosts": { "type": "array", "items": { "type": "string" } },
+                    "upstreams": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["url"],
+                            "properties": {
+                                "url": { "type": "string" },
+                                "weight": { "type": "number" }
+                            }
+                        }
+                    },
+                    "mixed": { "type": "array", "items": { "type": ["boolean", "integer", "string"] } },
+                    "empty": { "type": "array", "items": {} }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_strict_spec() -> Result<(), crate::Error> {
+        let parser = Parser::default()
+            .with_prefix("APP__")
+            .with_strict_spec(&spec());
+        let actual = parser.parse_iter(test_util::vars(&[
+            ("APP__SERVER__PORT", "80"),
+            ("APP__SERVER__HOSTS__0", "a"),
+            ("OTHER__TIMOUT", "1"),
+        ]))?;
+        assert_eq!(actual, json!({ "server": { "port": 80, "hosts": ["a"] } }));
+
+        let err = parser
+            .parse_iter(test_util::vars(&[
+                ("APP__SERVER__PORT", "80"),
+                ("APP__SERVER__TIMOUT", "1"),
+                ("APP__SERVER__PORTS", "1"),
+            ]))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            crate::Error::UnknownVars(vars)
+                if vars == ["APP__SERVER__TIMOUT", "APP__SERVER__PORTS"]
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! Helpers for testing code which reads the process environment
+
+use std::{
+    cell::Cell,
+    ffi::{OsStr, OsString},
+    sync::{Mutex, MutexGuard},
+};
+
+/// Serializes the [`EnvGuard`]s of different threads
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// Whether the current thread holds the lock, so its guards can be nested
+    static LOCKED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Sets and removes variables of the process environment, restoring their previous values on drop.
+///
+/// The environment is global to the process while tests run in parallel threads, so guards hold
+/// a global lock until they are dropped: tests using a guard run one after another and never
+/// see each other's variables. Guards can be nested within a thread.
+///
+/// ```
+/// use env_vars_to_json::{testing::EnvGuard, Parser};
+///
+/// let _env = EnvGuard::new()
+///     .set("APP__PORT", "8080")
+///     .remove("APP__DEBUG");
+///
+/// let json = Parser::default().with_prefix("APP__").parse_from_env()?;
+/// assert_eq!(json, serde_json::json!({ "port": 8080 }));
+/// # Ok::<(), env_vars_to_json::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct EnvGuard {
+    /// The value of each changed variable before the guard changed it
+    previous: Vec<(OsString, Option<OsString>)>,
+
+    /// The lock, held by the outermost guard of the thread
+    lock: Option<MutexGuard<'static, ()>>,
+}
+
+impl EnvGuard {
+    /// Return a guard without changes, waiting for the guards of other threads to be dropped
+    pub fn new() -> Self {
+        let lock = (!LOCKED.get()).then(|| {
+            // A test panicking while holding the lock has still restored the environment
+            let lock = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+            LOCKED.set(true);
+            lock
+        });
+
+        Self {
+            previous: vec![],
+            lock,
+        }
+    }
+
+    /// Set a variable until the guard is dropped
+    pub fn set(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.remember(key.as_ref());
+        std::env::set_var(key, value);
+        self
+    }
+
+    /// Remove a variable until the guard is dropped
+    pub fn remove(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.remember(key.as_ref());
+        std::env::remove_var(key);
+        self
+    }
+
+    /// Record the value of a variable unless it was already changed by the guard
+    fn remember(&mut self, key: &OsStr) {
+        if !self.previous.iter().any(|(changed, _)| changed == key) {
+            self.previous.push((key.to_owned(), std::env::var_os(key)));
+        }
+    }
+}
+
+impl Default for EnvGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (key, value) in self.previous.drain(..).rev() {
+            match value {
+                Some(value) => std::env::set_var(key, value),
+                None => std::env::remove_var(key),
+            }
+        }
+
+        if self.lock.take().is_some() {
+            LOCKED.set(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_guard() {
+        let key = "ENV_VARS_TO_JSON_TEST_ENV_GUARD";
+
+        let outer = EnvGuard::new().set(key, "outer");
+        {
+            let _inner = EnvGuard::new().set(key, "first").set(key, "second");
+            assert_eq!(std::env::var(key).as_deref(), Ok("second"));
+
+            let _removed = EnvGuard::new().remove(key);
+            assert!(std::env::var_os(key).is_none());
+        }
+        assert_eq!(std::env::var(key).as_deref(), Ok("outer"));
+
+        // Other threads wait for the guards of this thread
+        let other = std::thread::spawn(move || {
+            let _env = EnvGuard::new();
+            std::env::var_os(key)
+        });
+        drop(outer);
+        assert!(other.join().unwrap().is_none());
+        assert!(std::env::var_os(key).is_none());
+    }
+}
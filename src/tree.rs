@@ -0,0 +1,356 @@
+//! Building documents from paths and scalars, independent of the document type.
+//!
+//! The parser turns variables into [`Entry`]s, a path and a coerced [`Scalar`] each, and merges them
+//! into a document of any type implementing [`ValueOps`]. Implement [`ValueOps`] for another data
+//! model, e.g. a protobuf `Struct` or BSON, to reuse the segmentation and merge rules.
+
+use serde_json::{Number, Value};
+
+use crate::{Error, JsonIndex, JsonPath, ValueOps};
+
+/// A coerced value of a variable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scalar {
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(String),
+}
+
+impl From<Scalar> for Value {
+    fn from(scalar: Scalar) -> Self {
+        match scalar {
+            Scalar::Null => Value::Null,
+            Scalar::Bool(value) => Value::Bool(value),
+            Scalar::Number(number) => Value::Number(number),
+            Scalar::String(value) => Value::String(value),
+        }
+    }
+}
+
+impl From<Value> for Scalar {
+    /// Convert a json value, keeping containers as their json string
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Scalar::Null,
+            Value::Bool(value) => Scalar::Bool(value),
+            Value::Number(number) => Scalar::Number(number),
+            Value::String(value) => Scalar::String(value),
+            value => Scalar::String(value.to_string()),
+        }
+    }
+}
+
+/// A scalar at a path of a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Where the value goes, e.g. `["list", 1]`
+    pub path: JsonPath,
+
+    /// The value
+    pub value: Scalar,
+}
+
+/// Merge entries into the base document in order, see [`insert`]
+pub fn build<V: ValueOps>(
+    mut base: V,
+    entries: impl IntoIterator<Item = Entry>,
+) -> Result<V, Error> {
+    for entry in entries {
+        insert(&mut base, &entry.path, V::from_scalar(entry.value))?;
+    }
+
+    Ok(base)
+}
+
+/// Insert a value into a document at the given path in a single descent.
+///
+/// Missing containers are created on the way down: an object for a key and an array for an
+/// index, padding arrays with `null`. `null` and scalar values on the way are replaced, while an
+/// object where an array is needed, an array where an object is needed, or a container where the
+/// value should go are reported as [`Error::Conflict`].
+///
+/// This is the descent of [`crate::path::set_with`] too, which differs only in filling arrays by
+/// its [`crate::CreatePolicy`] and in replacing a container where the value goes.
+pub fn insert<V: ValueOps>(root: &mut V, path: &[JsonIndex], value: V) -> Result<(), Error> {
+    if matches!(path.first(), Some(JsonIndex::Usize(_))) {
+        return Err("First key part cannot be a number".into());
+    }
+
+    let gap = |path: &[JsonIndex], _len| {
+        V::null().ok_or_else(|| Error::MissingItems {
+            path: path.into(),
+            var: None,
+        })
+    };
+    set(root, path, value, gap, false)?;
+
+    Ok(())
+}
+
+/// Set a value at the given path in a single descent, see [`insert`], returning the previous
+/// value unless it was `null`.
+///
+/// Arrays are padded up to an index with the items `gap` returns for the path of the index and the
+/// length of the array. A container where the value goes is replaced if `replace_containers`
+/// is set, and reported as [`Error::Conflict`] otherwise
+pub(crate) fn set<V: ValueOps>(
+    root: &mut V,
+    path: &[JsonIndex],
+    value: V,
+    gap: impl Fn(&[JsonIndex], usize) -> Result<V, Error>,
+    replace_containers: bool,
+) -> Result<Option<V>, Error> {
+    let replace = |slot: &mut V, value: V| {
+        if !replace_containers && (slot.is_object() || slot.is_array()) {
+            return Err(conflict(path, slot));
+        }
+        Ok(Some(std::mem::replace(slot, value)).filter(|previous| !previous.is_null()))
+    };
+
+    let Some(first) = path.first() else {
+        return replace(root, value);
+    };
+    if root.is_null() {
+        *root = container(first);
+    }
+
+    let mut value = Some(value);
+    let mut node = root;
+
+    for (i, index) in path.iter().enumerate() {
+        let is_last = i + 1 == path.len();
+
+        // A missing value is inserted as the container of the next index, or as the value itself
+        let mut missing = || {
+            if is_last {
+                value.take()
+            } else {
+                Some(container(&path[i + 1]))
+            }
+        };
+
+        let slot = match index {
+            JsonIndex::String(key) if node.is_object() => node
+                .entry(key, || missing().expect("value is inserted once"))
+                .expect("node is an object"),
+            JsonIndex::Usize(index) if node.is_array() => {
+                let index = *index;
+                let items = node.items_mut().expect("node is an array");
+                while items.len() < index {
+                    items.push(gap(&path[..=i], items.len())?);
+                }
+                if items.len() == index {
+                    items.push(missing().expect("value is inserted once"));
+                }
+                &mut items[index]
+            }
+            _ => return Err(conflict(&path[..i], node)),
+        };
+
+        if is_last {
+            // The value is still there unless it was inserted as a missing value
+            return match value.take() {
+                Some(value) => replace(slot, value),
+                None => Ok(None),
+            };
+        }
+
+        if !slot.is_object() && !slot.is_array() {
+            *slot = container(&path[i + 1]);
+        }
+        node = slot;
+    }
+
+    Ok(None)
+}
+
+/// Remove the value at the given path from a document, returning it.
+///
+/// Object keys are removed, and array items are removed shifting the items after them.
+/// Return `None` if there is no value at the path.
+pub fn remove<V: ValueOps>(root: &mut V, path: &[JsonIndex]) -> Option<V> {
+    let (last, parents) = path.split_last()?;
+    let node = get_mut(root, parents)?;
+
+    match last {
+        JsonIndex::String(key) => node.remove(key),
+        JsonIndex::Usize(index) => {
+            let items = node.items_mut()?;
+            (*index < items.len()).then(|| items.remove(*index))
+        }
+    }
+}
+
+/// Remove the `null` items of the array at the path, shifting the items after them down.
+/// Returns the number of removed items
+pub fn compact<V: ValueOps>(root: &mut V, path: &[JsonIndex]) -> usize {
+    let Some(items) = get_mut(root, path).and_then(ValueOps::items_mut) else {
+        return 0;
+    };
+
+    let len = items.len();
+    items.retain(|item| !item.is_null());
+    len - items.len()
+}
+
+/// Return the number of items of the array at the path, `0` if there is none
+pub(crate) fn array_len<V: ValueOps>(root: &mut V, path: &[JsonIndex]) -> usize {
+    get_mut(root, path)
+        .and_then(ValueOps::items_mut)
+        .map_or(0, |items| items.len())
+}
+
+/// Return the value at the path
+fn get_mut<'a, V: ValueOps>(root: &'a mut V, path: &[JsonIndex]) -> Option<&'a mut V> {
+    let mut node = root;
+    for index in path {
+        node = match index {
+            JsonIndex::String(key) => node.get_mut(key)?,
+            JsonIndex::Usize(index) => node.items_mut()?.get_mut(*index)?,
+        };
+    }
+
+    Some(node)
+}
+
+/// Return the empty container the index goes into
+fn container<V: ValueOps>(index: &JsonIndex) -> V {
+    match index {
+        JsonIndex::String(_) => V::object(),
+        JsonIndex::Usize(_) => V::array(),
+    }
+}
+
+/// Describe a value found at a path that a variable can't be inserted into
+fn conflict(path: &[JsonIndex], found: &impl ValueOps) -> Error {
+    let found = if found.is_object() {
+        "an object"
+    } else if found.is_array() {
+        "an array"
+    } else {
+        "a scalar"
+    };
+
+    Error::Conflict {
+        path: path.into(),
+        found,
+    }
+}
+
+/// A part of a json path which can be either an object or an array item
+#[deprecated(note = "variables are inserted in a single descent, see `tree::insert`")]
+#[derive(Debug)]
+pub enum PartValue {
+    Object(Value),
+    #[allow(deprecated)]
+    ArrayItem(ArrayItem),
+}
+
+#[allow(deprecated)]
+impl PartValue {
+    pub fn into_json_value(self) -> Value {
+        match self {
+            Self::Object(value) => value,
+            Self::ArrayItem(item) => item.into_array_value(),
+        }
+    }
+}
+
+/// A value at an index of an otherwise empty array
+#[deprecated(note = "variables are inserted in a single descent, see `tree::insert`")]
+#[derive(Debug)]
+pub struct ArrayItem {
+    pub index: usize,
+    pub value: Value,
+}
+
+#[allow(deprecated)]
+impl ArrayItem {
+    pub fn new(index: usize, value: Value) -> Self {
+        Self { index, value }
+    }
+
+    pub fn into_array_value(self) -> Value {
+        let mut items = vec![Value::Null; self.index];
+        items.push(self.value);
+        Value::Array(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util;
+
+    fn entry(path: Vec<&str>, value: Scalar) -> Entry {
+        Entry {
+            path: JsonIndex::from_vec(path).into(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_build() -> Result<(), Error> {
+        let entries = [
+            entry(vec!["list", "1"], Scalar::String("b".to_string())),
+            entry(vec!["struct", "int"], Scalar::Number(1.into())),
+            entry(vec!["struct", "bool"], Scalar::Bool(true)),
+        ];
+
+        assert_eq!(
+            build(json!({ "base": 0 }), entries.clone())?,
+            json!({ "base": 0, "list": [null, "b"], "struct": { "int": 1, "bool": true } })
+        );
+        assert!(matches!(
+            build(json!({ "list": {} }), entries),
+            Err(Error::Conflict { path, found: "an object" }) if path.to_string() == "list"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut json = json!({ "list": ["a", "b", "c"], "struct": { "int": 1, "bool": true } });
+
+        assert_eq!(
+            remove(&mut json, &JsonIndex::from_vec(vec!["list", "1"])),
+            Some(json!("b"))
+        );
+        assert_eq!(
+            remove(&mut json, &JsonIndex::from_vec(vec!["struct", "int"])),
+            Some(json!(1))
+        );
+        assert_eq!(
+            remove(&mut json, &JsonIndex::from_vec(vec!["list", "5"])),
+            None
+        );
+        assert_eq!(
+            remove(&mut json, &JsonIndex::from_vec(vec!["other", "int"])),
+            None
+        );
+        assert_eq!(
+            json,
+            json!({ "list": ["a", "c"], "struct": { "bool": true } })
+        );
+    }
+
+    #[test]
+    fn test_build_parser_entries() -> Result<(), Error> {
+        let parser = crate::Parser::default();
+        let vars = test_util::vars(&[("LIST__0", "a"), ("STRUCT__INT", "1")]);
+
+        let entries = parser
+            .entries(vars.clone())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            build(Value::Null, entries)?,
+            parser.parse_iter(vars.into_iter())?
+        );
+
+        Ok(())
+    }
+}
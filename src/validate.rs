@@ -0,0 +1,548 @@
+//! Validation of coerced values, reported together for all variables
+
+use std::{
+    borrow::Cow,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+#[cfg(feature = "filter")]
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{from_env::REDACTED, Error, Parser};
+
+/// A custom check of coerced values, e.g. of a cron expression, see [`Parser::with_validator`].
+///
+/// Implemented for closures taking the value and returning why it is invalid:
+///
+/// ```
+/// use env_vars_to_json::Parser;
+///
+/// let parser = Parser::default().with_validator(
+///     "*__url",
+///     Box::new(|value: &serde_json::Value| match value.as_str() {
+///         Some(url) if url.contains("://") => Ok(()),
+///         _ => Err("must be a url".to_string()),
+///     }),
+/// );
+/// ```
+pub trait Validator: Send + Sync {
+    /// Check a coerced value, returning why it is invalid, e.g. `must be a url`
+    fn validate(&self, value: &Value) -> Result<(), String>;
+}
+
+impl<F: Fn(&Value) -> Result<(), String> + Send + Sync> Validator for F {
+    fn validate(&self, value: &Value) -> Result<(), String> {
+        self(value)
+    }
+}
+
+impl fmt::Debug for dyn Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
+/// A check of the coerced value at a path, see [`Parser::with_allowed`]
+#[derive(Debug, Clone)]
+pub enum Rule {
+    /// The value must be one of the given values, compared with its text, e.g. `info`
+    Allowed(Vec<String>),
+
+    /// The value must be a number within the lower and upper bound
+    Range(Bound<f64>, Bound<f64>),
+
+    #[cfg(feature = "filter")]
+    /// The text of the value must match the pattern, e.g. `^postgres://`
+    /// Requires the `filter` feature
+    Pattern(Regex),
+
+    /// The value must pass a custom check
+    Custom(Arc<dyn Validator>),
+
+    /// The text of the value must be an IPv4 or IPv6 address, e.g. `127.0.0.1` or `::1`
+    IpAddr,
+
+    /// The text of the value must be an IP address with a port, e.g. `0.0.0.0:8080` or `[::1]:80`
+    SocketAddr,
+
+    /// The text of the value must be an IP network in CIDR notation, e.g. `10.0.0.0/8`
+    Cidr,
+
+    #[cfg(feature = "uuid")]
+    /// The text of the value must be a UUID in any of its formats, e.g. braced or without hyphens.
+    /// Valid values are canonicalized into the lowercase hyphenated format
+    /// Requires the `uuid` feature
+    Uuid,
+}
+
+impl Rule {
+    /// Check a value, returning why it is invalid
+    fn check(&self, value: &Value) -> Result<(), String> {
+        match self {
+            Rule::Allowed(allowed) => {
+                let text = text(value);
+                match allowed.iter().any(|allowed| *allowed == text) {
+                    true => Ok(()),
+                    false => Err(format!("must be one of {}", allowed.join(", "))),
+                }
+            }
+            Rule::Range(min, max) => match value.as_f64() {
+                Some(number) if (*min, *max).contains(&number) => Ok(()),
+                _ => Err(format!("must be a number in {}", display_range(min, max))),
+            },
+            #[cfg(feature = "filter")]
+            Rule::Pattern(pattern) => match pattern.is_match(&text(value)) {
+                true => Ok(()),
+                false => Err(format!("must match `{pattern}`")),
+            },
+            Rule::Custom(validator) => validator.validate(value),
+            Rule::IpAddr => match text(value).parse::<IpAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("must be an IP address".to_string()),
+            },
+            Rule::SocketAddr => match text(value).parse::<SocketAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("must be a socket address, e.g. `0.0.0.0:8080`".to_string()),
+            },
+            Rule::Cidr => match is_cidr(&text(value)) {
+                true => Ok(()),
+                false => Err("must be a CIDR block, e.g. `10.0.0.0/8`".to_string()),
+            },
+            #[cfg(feature = "uuid")]
+            Rule::Uuid => uuid::Uuid::parse_str(&text(value))
+                .map(|_| ())
+                .map_err(|_| "must be a uuid".to_string()),
+        }
+    }
+}
+
+/// A value which failed a [`Rule`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The variable the value came from, e.g. `PREFIX__LOG__LEVEL`
+    pub var: String,
+
+    /// The value, redacted if it is secret
+    pub value: String,
+
+    /// Why the value is invalid, e.g. `must be one of debug, info`
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` = `{}` {}", self.var, self.value, self.message)
+    }
+}
+
+impl Parser {
+    /// Return a new parser which only accepts the given values at the path, e.g.
+    /// `with_allowed("log__level", &["debug", "info"])`. Paths are key parts joined by the separator.
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    pub fn with_allowed<S: AsRef<str>>(mut self, path: impl Into<String>, allowed: &[S]) -> Self {
+        let allowed = allowed.iter().map(|value| value.as_ref().to_string());
+        self.rules
+            .push((path.into(), Rule::Allowed(allowed.collect())));
+        self
+    }
+
+    /// Return a new parser which only accepts numbers in the range at the path, e.g.
+    /// `with_range("server__port", 1..=65535)`, checked after coercion.
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    pub fn with_range<N: Into<f64> + Copy>(
+        mut self,
+        path: impl Into<String>,
+        range: impl RangeBounds<N>,
+    ) -> Self {
+        let bound = |bound: Bound<&N>| bound.map(|number| (*number).into());
+        let rule = Rule::Range(bound(range.start_bound()), bound(range.end_bound()));
+        self.rules.push((path.into(), rule));
+        self
+    }
+
+    #[cfg(feature = "filter")]
+    /// Return a new parser which only accepts values at the path matching the pattern, e.g.
+    /// `with_value_pattern("database__url", "^postgres://")`, checked after coercion.
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    /// Requires the `filter` feature
+    pub fn with_value_pattern(mut self, path: impl Into<String>, pattern: &str) -> Self {
+        let pattern = Regex::new(pattern).expect("Failed to compile regex");
+        self.rules.push((path.into(), Rule::Pattern(pattern)));
+        self
+    }
+
+    /// Return a new parser which checks values at the paths matching the glob with a custom validator,
+    /// e.g. `with_validator("*__url", validator)`, where `*` matches any characters.
+    /// Invalid values of all variables are reported together as [`Error::Validation`]
+    pub fn with_validator(
+        mut self,
+        path_glob: impl Into<String>,
+        validator: Box<dyn Validator>,
+    ) -> Self {
+        self.rules
+            .push((path_glob.into(), Rule::Custom(Arc::from(validator))));
+        self
+    }
+
+    #[cfg(feature = "uuid")]
+    /// Return a new parser which only accepts UUIDs at the paths matching the glob, e.g.
+    /// `with_uuid("tenant__id")`, and canonicalizes them into the lowercase hyphenated format.
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    /// Requires the `uuid` feature
+    pub fn with_uuid(mut self, path_glob: impl Into<String>) -> Self {
+        self.rules.push((path_glob.into(), Rule::Uuid));
+        self
+    }
+
+    #[cfg(feature = "uuid")]
+    /// Canonicalize a valid value at the key parts if a rule requires it, e.g. a UUID
+    pub(crate) fn canonicalize(&self, key_parts: &[Cow<str>], value: &mut Value) {
+        for rule in self.rules_at(key_parts) {
+            if let Rule::Uuid = rule {
+                if let Ok(uuid) = uuid::Uuid::parse_str(&text(value)) {
+                    *value = Value::String(uuid.hyphenated().to_string());
+                }
+            }
+        }
+    }
+
+    /// Return a new parser which only accepts IP addresses at the paths matching the glob, e.g.
+    /// `with_ip_addr("*__host")`, as parsed by [`IpAddr`].
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    pub fn with_ip_addr(mut self, path_glob: impl Into<String>) -> Self {
+        self.rules.push((path_glob.into(), Rule::IpAddr));
+        self
+    }
+
+    /// Return a new parser which only accepts socket addresses at the paths matching the glob, e.g.
+    /// `with_socket_addr("server__bind")`, as parsed by [`SocketAddr`], so a bad bind address is
+    /// reported while parsing rather than when binding.
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    pub fn with_socket_addr(mut self, path_glob: impl Into<String>) -> Self {
+        self.rules.push((path_glob.into(), Rule::SocketAddr));
+        self
+    }
+
+    /// Return a new parser which only accepts IP networks in CIDR notation at the paths matching
+    /// the glob, e.g. `with_cidr("allowed_networks__*")` for `10.0.0.0/8` or `fd00::/8`.
+    /// Other values fail the parse with [`Error::Validation`], naming the variable
+    pub fn with_cidr(mut self, path_glob: impl Into<String>) -> Self {
+        self.rules.push((path_glob.into(), Rule::Cidr));
+        self
+    }
+
+    /// Check the coerced values of preprocessed variables against the rules, reporting all invalid values
+    pub(crate) fn check_rules<'k>(
+        &self,
+        assignments: impl Iterator<Item = (&'k str, &'k [Cow<'k, str>], &'k Value)>,
+    ) -> Result<(), Error> {
+        if self.rules.is_empty() {
+            return Ok(());
+        }
+
+        let errors = assignments
+            .flat_map(|(key, key_parts, value)| self.rule_errors(key, key_parts, value))
+            .collect::<Vec<_>>();
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Validation(errors)),
+        }
+    }
+
+    /// Return the errors of the rules at the path of a variable
+    pub(crate) fn rule_errors(
+        &self,
+        key: &str,
+        key_parts: &[Cow<str>],
+        value: &Value,
+    ) -> Vec<ValidationError> {
+        self.rules_at(key_parts)
+            .filter_map(|rule| rule.check(value).err())
+            .map(|message| ValidationError {
+                var: format!("{}{key}", self.prefix.as_deref().unwrap_or_default()),
+                value: match self.is_secret(key_parts) {
+                    true => REDACTED.to_string(),
+                    false => text(value).into_owned(),
+                },
+                message,
+            })
+            .collect()
+    }
+}
+
+impl Parser {
+    /// Return the rules whose glob matches the path of the key parts
+    fn rules_at<'r>(&'r self, key_parts: &[Cow<str>]) -> impl Iterator<Item = &'r Rule> {
+        let path = key_parts.join(&self.separator).to_lowercase();
+
+        self.rules
+            .iter()
+            .filter(move |(glob, _)| glob_match(&glob.to_lowercase(), &path))
+            .map(|(_, rule)| rule)
+    }
+}
+
+/// Return the text of a coerced value, e.g. `info` for a string rather than `"info"`
+fn text(value: &Value) -> Cow<'_, str> {
+    match value {
+        Value::String(value) => Cow::Borrowed(value),
+        value => Cow::Owned(value.to_string()),
+    }
+}
+
+/// Whether the text is an IP address and a prefix length no longer than the address, e.g. `10.0.0.0/8`
+fn is_cidr(text: &str) -> bool {
+    let Some((addr, len)) = text.split_once('/') else {
+        return false;
+    };
+    let max_len = match addr.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => 32,
+        Ok(IpAddr::V6(_)) => 128,
+        Err(_) => return false,
+    };
+
+    len.bytes().all(|b| b.is_ascii_digit()) && len.parse::<u8>().is_ok_and(|len| len <= max_len)
+}
+
+/// Whether the text matches the glob, where `*` matches any characters
+fn glob_match(glob: &str, text: &str) -> bool {
+    let Some((first, rest)) = glob.split_once('*') else {
+        return glob == text;
+    };
+    let Some(mut text) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts = rest.split('*').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            return text.len() >= part.len() && text.ends_with(part);
+        }
+        match text.find(part) {
+            Some(i) => text = &text[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+/// Return a range in Rust syntax, e.g. `1..=65535`
+fn display_range(min: &Bound<f64>, max: &Bound<f64>) -> String {
+    let min = match min {
+        Bound::Included(min) | Bound::Excluded(min) => min.to_string(),
+        Bound::Unbounded => String::new(),
+    };
+    let max = match max {
+        Bound::Included(max) => format!("={max}"),
+        Bound::Excluded(max) => max.to_string(),
+        Bound::Unbounded => String::new(),
+    };
+
+    format!("{min}..{max}")
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_allowed() {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_allowed("log__level", &["debug", "info"])
+            .with_allowed("workers", &["1", "2"])
+            .with_secret("workers");
+
+        assert!(parser
+            .parse_iter(test_util::vars(&[
+                ("PREFIX__LOG__LEVEL", "info"),
+                ("PREFIX__WORKERS", "2")
+            ]))
+            .is_ok());
+
+        let err = parser
+            .parse_iter(test_util::vars(&[
+                ("PREFIX__LOG__LEVEL", "verbose"),
+                ("PREFIX__WORKERS", "3"),
+            ]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid values: `PREFIX__WORKERS` = `***` must be one of 1, 2; \
+             `PREFIX__LOG__LEVEL` = `verbose` must be one of debug, info"
+        );
+    }
+
+    #[test]
+    fn test_validator() {
+        let is_url = |value: &Value| match value.as_str() {
+            Some(url) if url.contains("://") => Ok(()),
+            _ => Err("must be a url".to_string()),
+        };
+        let parser = Parser::default()
+            .with_validator("*__url", Box::new(is_url))
+            .with_allowed("db__url", &["postgres://db"]);
+
+        assert!(parser
+            .parse_iter(test_util::vars(&[
+                ("DB__URL", "postgres://db"),
+                ("URL", "x")
+            ]))
+            .is_ok());
+
+        let err = parser
+            .parse_iter(test_util::vars(&[
+                ("DB__URL", "db"),
+                ("CACHE__URL", "redis://cache"),
+            ]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid values: `DB__URL` = `db` must be a url; \
+             `DB__URL` = `db` must be one of postgres://db"
+        );
+    }
+
+    #[rstest]
+    #[case("db__url", "db__url", true)]
+    #[case("db__url", "db__urls", false)]
+    #[case("*__url", "cache__url", true)]
+    #[case("*__url", "url", false)]
+    #[case("db__*", "db__replica__host", true)]
+    #[case("*__*__host", "db__replica__host", true)]
+    #[case("*a*a", "a", false)]
+    fn test_glob_match(#[case] glob: &str, #[case] text: &str, #[case] expected: bool) {
+        assert_eq!(glob_match(glob, text), expected);
+    }
+
+    #[test]
+    fn test_range() {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_range("server__port", 1..=65535)
+            .with_range("ratio", ..1.0);
+
+        assert!(parser
+            .parse_iter(test_util::vars(&[
+                ("PREFIX__SERVER__PORT", "8080"),
+                ("PREFIX__RATIO", "0.5")
+            ]))
+            .is_ok());
+
+        let err = parser
+            .parse_iter(test_util::vars(&[
+                ("PREFIX__SERVER__PORT", "70000"),
+                ("PREFIX__RATIO", "1"),
+            ]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid values: `PREFIX__SERVER__PORT` = `70000` must be a number in 1..=65535; \
+             `PREFIX__RATIO` = `1` must be a number in ..1"
+        );
+    }
+
+    #[rstest]
+    #[case::ip_v4(Rule::IpAddr, "127.0.0.1", true)]
+    #[case::ip_v6(Rule::IpAddr, "::1", true)]
+    #[case::ip_with_port(Rule::IpAddr, "127.0.0.1:80", false)]
+    #[case::socket_v4(Rule::SocketAddr, "0.0.0.0:8080", true)]
+    #[case::socket_v6(Rule::SocketAddr, "[::1]:80", true)]
+    #[case::socket_without_port(Rule::SocketAddr, "0.0.0.0", false)]
+    #[case::socket_hostname(Rule::SocketAddr, "localhost:80", false)]
+    #[case::cidr_v4(Rule::Cidr, "10.0.0.0/8", true)]
+    #[case::cidr_v6(Rule::Cidr, "fd00::/128", true)]
+    #[case::cidr_too_long(Rule::Cidr, "10.0.0.0/33", false)]
+    #[case::cidr_signed(Rule::Cidr, "10.0.0.0/+8", false)]
+    #[case::cidr_without_len(Rule::Cidr, "10.0.0.0", false)]
+    fn test_address_rules(#[case] rule: Rule, #[case] value: &str, #[case] valid: bool) {
+        assert_eq!(rule.check(&Value::String(value.to_string())).is_ok(), valid);
+    }
+
+    #[test]
+    fn test_socket_addr() {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_socket_addr("server__bind")
+            .with_ip_addr("*__host")
+            .with_cidr("allowed__*");
+
+        assert!(parser
+            .parse_iter(test_util::vars(&[
+                ("PREFIX__SERVER__BIND", "0.0.0.0:8080"),
+                ("PREFIX__DB__HOST", "10.0.0.5"),
+                ("PREFIX__ALLOWED__0", "10.0.0.0/8"),
+            ]))
+            .is_ok());
+
+        let err = parser
+            .parse_iter(test_util::vars(&[("PREFIX__SERVER__BIND", "0.0.0.0:http")]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid values: `PREFIX__SERVER__BIND` = `0.0.0.0:http` must be a socket address, e.g. `0.0.0.0:8080`"
+        );
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__").with_uuid("*__id");
+
+        assert_eq!(
+            parser.parse_iter(test_util::vars(&[
+                (
+                    "PREFIX__TENANT__ID",
+                    "{67E55044-10B1-426F-9247-BB680E5FE0C8}"
+                ),
+                ("PREFIX__ID", "not-checked"),
+            ]))?,
+            serde_json::json!({
+                "tenant": { "id": "67e55044-10b1-426f-9247-bb680e5fe0c8" },
+                "id": "not-checked"
+            })
+        );
+
+        let err = parser
+            .parse_iter(test_util::vars(&[("PREFIX__REQUEST__ID", "abc-123")]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid values: `PREFIX__REQUEST__ID` = `abc-123` must be a uuid"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "filter")]
+    #[test]
+    fn test_value_pattern() {
+        let parser = Parser::default()
+            .with_value_pattern("database__url", "^postgres://")
+            .with_value_pattern("port", r"^\d{4}$");
+
+        assert!(parser
+            .parse_iter(test_util::vars(&[
+                ("DATABASE__URL", "postgres://db"),
+                ("PORT", "5432")
+            ]))
+            .is_ok());
+
+        let err = parser
+            .parse_iter(test_util::vars(&[
+                ("DATABASE__URL", "mysql://db"),
+                ("PORT", "80"),
+            ]))
+            .unwrap_err();
+        assert!(matches!(err, Error::Validation(errors) if errors.len() == 2));
+    }
+}
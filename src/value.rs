@@ -0,0 +1,596 @@
+//! Document types the parser can build, see [`ValueOps`]
+
+use serde_json::Value;
+
+use crate::tree::Scalar;
+
+/// The operations the parser needs to build a document of a value type.
+///
+/// Implemented for `serde_json::Value`, and for `serde_yaml::Value` and `toml::Value` with the
+/// `yaml` and `toml` features, so documents are built without converting a whole `serde_json::Value`.
+/// `serde_json` is still used on the way: values are coerced into a [`Scalar`], which holds a
+/// `serde_json::Number`, and the CBOR and MessagePack outputs convert the parser's json into
+/// their base document.
+pub trait ValueOps: Sized {
+    /// Return an empty object
+    fn object() -> Self;
+
+    /// Return an empty array
+    fn array() -> Self;
+
+    /// Return the placeholder for array items without a value, `None` if the type has no null
+    fn null() -> Option<Self>;
+
+    /// Convert a coerced value
+    fn from_scalar(value: Scalar) -> Self;
+
+    /// Whether the value is null
+    fn is_null(&self) -> bool;
+
+    /// Whether the value is an object
+    fn is_object(&self) -> bool;
+
+    /// Whether the value is an array
+    fn is_array(&self) -> bool;
+
+    /// Return the value at the key of an object, inserting `default()` if missing.
+    /// Return `None` if the value is not an object
+    fn entry(&mut self, key: &str, default: impl FnOnce() -> Self) -> Option<&mut Self>;
+
+    /// Return the value at the key of an object, `None` if missing or the value is not an object
+    fn get_mut(&mut self, key: &str) -> Option<&mut Self>;
+
+    /// Remove the key of an object, returning its value.
+    /// Return `None` if missing or the value is not an object
+    fn remove(&mut self, key: &str) -> Option<Self>;
+
+    /// Return the items of an array, `None` if the value is not an array
+    fn items_mut(&mut self) -> Option<&mut Vec<Self>>;
+
+    /// Sort object keys recursively
+    fn sort_keys(&mut self);
+}
+
+impl ValueOps for Value {
+    fn object() -> Self {
+        Value::Object(Default::default())
+    }
+
+    fn array() -> Self {
+        Value::Array(vec![])
+    }
+
+    fn null() -> Option<Self> {
+        Some(Value::Null)
+    }
+
+    fn from_scalar(value: Scalar) -> Self {
+        value.into()
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_null()
+    }
+
+    fn is_object(&self) -> bool {
+        self.is_object()
+    }
+
+    fn is_array(&self) -> bool {
+        self.is_array()
+    }
+
+    fn entry(&mut self, key: &str, default: impl FnOnce() -> Self) -> Option<&mut Self> {
+        Some(self.as_object_mut()?.entry(key).or_insert_with(default))
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Self> {
+        self.as_object_mut()?.get_mut(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Self> {
+        let object = self.as_object_mut()?;
+        #[cfg(feature = "preserve_order")]
+        return object.shift_remove(key);
+        #[cfg(not(feature = "preserve_order"))]
+        object.remove(key)
+    }
+
+    fn items_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.as_array_mut()
+    }
+
+    fn sort_keys(&mut self) {
+        self.sort_all_objects();
+    }
+}
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+/// Convert a json value into a document of another value type, e.g. the parser's json object
+/// into the base of a CBOR or MessagePack document
+pub(crate) fn from_json<V: ValueOps>(value: Value) -> Result<V, crate::Error> {
+    Ok(match value {
+        Value::Null => V::null().ok_or("cannot convert null, the value type has no null")?,
+        Value::Object(object) => {
+            let mut converted = V::object();
+            for (key, value) in object {
+                let value = from_json(value)?;
+                *converted
+                    .entry(&key, V::object)
+                    .expect("value is an object") = value;
+            }
+            converted
+        }
+        Value::Array(items) => {
+            let mut converted = V::array();
+            *converted.items_mut().expect("value is an array") =
+                items.into_iter().map(from_json).collect::<Result<_, _>>()?;
+            converted
+        }
+        scalar => V::from_scalar(scalar.into()),
+    })
+}
+
+#[cfg(feature = "yaml")]
+/// Requires the `yaml` feature
+impl ValueOps for serde_yaml::Value {
+    fn object() -> Self {
+        serde_yaml::Value::Mapping(Default::default())
+    }
+
+    fn array() -> Self {
+        serde_yaml::Value::Sequence(vec![])
+    }
+
+    fn null() -> Option<Self> {
+        Some(serde_yaml::Value::Null)
+    }
+
+    fn from_scalar(value: Scalar) -> Self {
+        match value {
+            Scalar::Null => serde_yaml::Value::Null,
+            Scalar::Bool(value) => serde_yaml::Value::Bool(value),
+            Scalar::Number(number) => {
+                if let Some(number) = number.as_i64() {
+                    serde_yaml::Value::Number(number.into())
+                } else if let Some(number) = number.as_u64() {
+                    serde_yaml::Value::Number(number.into())
+                } else {
+                    serde_yaml::Value::Number(number.as_f64().unwrap_or(f64::NAN).into())
+                }
+            }
+            Scalar::String(value) => serde_yaml::Value::String(value),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_null()
+    }
+
+    fn is_object(&self) -> bool {
+        self.is_mapping()
+    }
+
+    fn is_array(&self) -> bool {
+        self.is_sequence()
+    }
+
+    fn entry(&mut self, key: &str, default: impl FnOnce() -> Self) -> Option<&mut Self> {
+        Some(
+            self.as_mapping_mut()?
+                .entry(serde_yaml::Value::String(key.to_string()))
+                .or_insert_with(default),
+        )
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Self> {
+        self.as_mapping_mut()?.get_mut(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Self> {
+        self.as_mapping_mut()?.shift_remove(key)
+    }
+
+    fn items_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.as_sequence_mut()
+    }
+
+    fn sort_keys(&mut self) {
+        match self {
+            serde_yaml::Value::Mapping(mapping) => {
+                let mut entries = std::mem::take(mapping).into_iter().collect::<Vec<_>>();
+                entries.sort_by(|(a, _), (b, _)| a.as_str().cmp(&b.as_str()));
+
+                for (key, mut value) in entries {
+                    value.sort_keys();
+                    mapping.insert(key, value);
+                }
+            }
+            serde_yaml::Value::Sequence(items) => items.iter_mut().for_each(ValueOps::sort_keys),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "toml")]
+/// Requires the `toml` feature. Toml has no null, so arrays can't have missing items, see
+/// [`crate::Error::MissingItems`], and null values become empty strings.
+impl ValueOps for toml::Value {
+    fn object() -> Self {
+        toml::Value::Table(Default::default())
+    }
+
+    fn array() -> Self {
+        toml::Value::Array(vec![])
+    }
+
+    fn null() -> Option<Self> {
+        None
+    }
+
+    fn from_scalar(value: Scalar) -> Self {
+        match value {
+            Scalar::Null => toml::Value::String(String::new()),
+            Scalar::Bool(value) => toml::Value::Boolean(value),
+            Scalar::Number(number) => match number.as_i64() {
+                Some(number) => toml::Value::Integer(number),
+                None => toml::Value::Float(number.as_f64().unwrap_or(f64::NAN)),
+            },
+            Scalar::String(value) => toml::Value::String(value),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        false
+    }
+
+    fn is_object(&self) -> bool {
+        self.is_table()
+    }
+
+    fn is_array(&self) -> bool {
+        self.is_array()
+    }
+
+    fn entry(&mut self, key: &str, default: impl FnOnce() -> Self) -> Option<&mut Self> {
+        Some(self.as_table_mut()?.entry(key).or_insert_with(default))
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Self> {
+        self.as_table_mut()?.get_mut(key)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Self> {
+        self.as_table_mut()?.remove(key)
+    }
+
+    fn items_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.as_array_mut()
+    }
+
+    fn sort_keys(&mut self) {
+        match self {
+            // Tables are kept sorted unless toml's `preserve_order` feature is enabled
+            toml::Value::Table(table) => {
+                let mut entries = std::mem::take(table).into_iter().collect::<Vec<_>>();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                for (key, mut value) in entries {
+                    value.sort_keys();
+                    table.insert(key, value);
+                }
+            }
+            toml::Value::Array(items) => items.iter_mut().for_each(ValueOps::sort_keys),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+/// Requires the `cbor` feature. Integers are kept as CBOR integers.
+impl ValueOps for ciborium::Value {
+    fn object() -> Self {
+        ciborium::Value::Map(vec![])
+    }
+
+    fn array() -> Self {
+        ciborium::Value::Array(vec![])
+    }
+
+    fn null() -> Option<Self> {
+        Some(ciborium::Value::Null)
+    }
+
+    fn from_scalar(value: Scalar) -> Self {
+        match value {
+            Scalar::Null => ciborium::Value::Null,
+            Scalar::Bool(value) => ciborium::Value::Bool(value),
+            Scalar::Number(number) => {
+                if let Some(number) = number.as_i64() {
+                    ciborium::Value::Integer(number.into())
+                } else if let Some(number) = number.as_u64() {
+                    ciborium::Value::Integer(number.into())
+                } else {
+                    ciborium::Value::Float(number.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            Scalar::String(value) => ciborium::Value::Text(value),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_null()
+    }
+
+    fn is_object(&self) -> bool {
+        self.is_map()
+    }
+
+    fn is_array(&self) -> bool {
+        self.is_array()
+    }
+
+    fn entry(&mut self, key: &str, default: impl FnOnce() -> Self) -> Option<&mut Self> {
+        let map = self.as_map_mut()?;
+        let i = match map.iter().position(|(k, _)| k.as_text() == Some(key)) {
+            Some(i) => i,
+            None => {
+                map.push((ciborium::Value::Text(key.to_string()), default()));
+                map.len() - 1
+            }
+        };
+        Some(&mut map[i].1)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Self> {
+        let map = self.as_map_mut()?;
+        let i = map.iter().position(|(k, _)| k.as_text() == Some(key))?;
+        Some(&mut map[i].1)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Self> {
+        let map = self.as_map_mut()?;
+        let i = map.iter().position(|(k, _)| k.as_text() == Some(key))?;
+        Some(map.remove(i).1)
+    }
+
+    fn items_mut(&mut self) -> Option<&mut Vec<Self>> {
+        self.as_array_mut()
+    }
+
+    fn sort_keys(&mut self) {
+        match self {
+            ciborium::Value::Map(map) => {
+                map.sort_by(|(a, _), (b, _)| a.as_text().cmp(&b.as_text()));
+                map.iter_mut().for_each(|(_, value)| value.sort_keys());
+            }
+            ciborium::Value::Array(items) => items.iter_mut().for_each(ValueOps::sort_keys),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "msgpack")]
+/// Requires the `msgpack` feature. Integers are kept as MessagePack integers.
+impl ValueOps for rmpv::Value {
+    fn object() -> Self {
+        rmpv::Value::Map(vec![])
+    }
+
+    fn array() -> Self {
+        rmpv::Value::Array(vec![])
+    }
+
+    fn null() -> Option<Self> {
+        Some(rmpv::Value::Nil)
+    }
+
+    fn from_scalar(value: Scalar) -> Self {
+        match value {
+            Scalar::Null => rmpv::Value::Nil,
+            Scalar::Bool(value) => rmpv::Value::Boolean(value),
+            Scalar::Number(number) => {
+                if let Some(number) = number.as_i64() {
+                    rmpv::Value::from(number)
+                } else if let Some(number) = number.as_u64() {
+                    rmpv::Value::from(number)
+                } else {
+                    rmpv::Value::F64(number.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            Scalar::String(value) => rmpv::Value::from(value),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_nil()
+    }
+
+    fn is_object(&self) -> bool {
+        self.is_map()
+    }
+
+    fn is_array(&self) -> bool {
+        self.is_array()
+    }
+
+    fn entry(&mut self, key: &str, default: impl FnOnce() -> Self) -> Option<&mut Self> {
+        let rmpv::Value::Map(map) = self else {
+            return None;
+        };
+        let i = match map.iter().position(|(k, _)| k.as_str() == Some(key)) {
+            Some(i) => i,
+            None => {
+                map.push((rmpv::Value::from(key), default()));
+                map.len() - 1
+            }
+        };
+        Some(&mut map[i].1)
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Self> {
+        let rmpv::Value::Map(map) = self else {
+            return None;
+        };
+        let i = map.iter().position(|(k, _)| k.as_str() == Some(key))?;
+        Some(&mut map[i].1)
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Self> {
+        let rmpv::Value::Map(map) = self else {
+            return None;
+        };
+        let i = map.iter().position(|(k, _)| k.as_str() == Some(key))?;
+        Some(map.remove(i).1)
+    }
+
+    fn items_mut(&mut self) -> Option<&mut Vec<Self>> {
+        match self {
+            rmpv::Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    fn sort_keys(&mut self) {
+        match self {
+            rmpv::Value::Map(map) => {
+                map.sort_by(|(a, _), (b, _)| a.as_str().cmp(&b.as_str()));
+                map.iter_mut().for_each(|(_, value)| value.sort_keys());
+            }
+            rmpv::Value::Array(items) => items.iter_mut().for_each(ValueOps::sort_keys),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{Error, Parser};
+
+    #[test]
+    fn test_parse_iter_to_json() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "ignored": true }));
+
+        let actual = parser.parse_iter_to(
+            json!({ "base": 0 }),
+            [("PREFIX__LIST__1".to_string(), "b".to_string())].into_iter(),
+        )?;
+        assert_eq!(actual, json!({ "base": 0, "list": [null, "b"] }));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_parse_iter_to_yaml() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let vars = [
+            ("PREFIX__STRUCT__INT", "1"),
+            ("PREFIX__STRUCT__BOOL", "true"),
+            ("PREFIX__LIST__1", "b"),
+        ];
+
+        let actual: serde_yaml::Value = parser.parse_iter_to(
+            serde_yaml::Value::Mapping(Default::default()),
+            crate::test_util::vars(&vars),
+        )?;
+        let expected: serde_yaml::Value =
+            serde_yaml::from_str("struct: { int: 1, bool: true }\nlist: [null, b]").unwrap();
+        assert_eq!(actual, expected);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_parse_iter_to_toml() -> Result<(), Error> {
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let base: toml::Value = toml::from_str("[struct]\nstring = 'default'").unwrap();
+        let actual = parser.parse_iter_to(
+            base,
+            crate::test_util::vars(&[("PREFIX__STRUCT__INT", "1"), ("PREFIX__LIST__0", "a")]),
+        )?;
+        let expected: toml::Value =
+            toml::from_str("list = ['a']\n[struct]\nint = 1\nstring = 'default'").unwrap();
+        assert_eq!(actual, expected);
+
+        // Toml arrays can't have missing items
+        let err = parser
+            .parse_iter_to(
+                toml::Value::Table(Default::default()),
+                crate::test_util::vars(&[("PREFIX__LIST__1", "b")]),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::MissingItems { path, var: Some(var) }
+                if path.to_string() == "list[1]" && var == "PREFIX__LIST__1"
+        ));
+        assert!(err.to_string().contains("TOML"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_parse_to_cbor() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "base": 0 }))
+            .with_sort_keys(true);
+        let vars = [("PREFIX__STRUCT__INT", "1"), ("PREFIX__LIST__1", "b")];
+
+        let bytes = parser.parse_to_cbor(crate::test_util::vars(&vars))?;
+        let actual: ciborium::Value = ciborium::from_reader(bytes.as_slice()).unwrap();
+        let text = |text: &str| ciborium::Value::Text(text.to_string());
+        assert_eq!(
+            actual,
+            ciborium::Value::Map(vec![
+                (text("base"), ciborium::Value::Integer(0.into())),
+                (
+                    text("list"),
+                    ciborium::Value::Array(vec![ciborium::Value::Null, text("b")])
+                ),
+                (
+                    text("struct"),
+                    ciborium::Value::Map(vec![(text("int"), ciborium::Value::Integer(1.into()))])
+                ),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_parse_to_msgpack() -> Result<(), Error> {
+        let parser = Parser::default()
+            .with_prefix("PREFIX__")
+            .with_json(json!({ "base": 0 }))
+            .with_sort_keys(true);
+        let vars = [("PREFIX__STRUCT__INT", "1"), ("PREFIX__LIST__1", "b")];
+
+        let bytes = parser.parse_to_msgpack(crate::test_util::vars(&vars))?;
+        let actual = rmpv::decode::read_value(&mut bytes.as_slice()).unwrap();
+        assert_eq!(
+            actual,
+            rmpv::Value::Map(vec![
+                ("base".into(), 0.into()),
+                (
+                    "list".into(),
+                    rmpv::Value::Array(vec![rmpv::Value::Nil, "b".into()])
+                ),
+                (
+                    "struct".into(),
+                    rmpv::Value::Map(vec![("int".into(), 1.into())])
+                ),
+            ])
+        );
+
+        Ok(())
+    }
+}
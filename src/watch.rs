@@ -0,0 +1,143 @@
+//! Re-parsing of sources in the background to pick up configuration changes without restarts
+//! Requires the `watch` feature
+
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{source::EnvSource, Error, Parser};
+
+/// A background thread which periodically re-reads a source, re-parses it and delivers the result
+/// whenever it changes. Errors are delivered as well, once per distinct change.
+///
+/// The thread stops when the watch is stopped or dropped.
+#[derive(Debug)]
+pub struct Watch {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watch {
+    /// Start watching the source, calling `on_change` with the first result and every changed result
+    pub fn start<S, F>(parser: Parser, source: S, interval: Duration, mut on_change: F) -> Self
+    where
+        S: EnvSource + Send + 'static,
+        F: FnMut(Result<Value, Error>) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut last = None;
+
+            loop {
+                let result = source
+                    .vars()
+                    .and_then(|vars| parser.parse_iter(vars.into_iter()));
+
+                // Errors are compared by message since they can't be compared directly
+                let current = match &result {
+                    Ok(value) => Ok(value.clone()),
+                    Err(err) => Err(err.to_string()),
+                };
+                if last.as_ref() != Some(&current) {
+                    last = Some(current);
+                    on_change(result);
+                }
+
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Start watching the source, delivering the first result and every changed result through a channel
+    pub fn channel<S>(
+        parser: Parser,
+        source: S,
+        interval: Duration,
+    ) -> (Self, Receiver<Result<Value, Error>>)
+    where
+        S: EnvSource + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let watch = Self::start(parser, source, interval, move |result| {
+            // The receiver may be gone, the watch is stopped when it is dropped
+            let _ = sender.send(result);
+        });
+
+        (watch, receiver)
+    }
+
+    /// Stop watching and wait for the background thread to finish
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(handle) = self.handle.take() {
+            // A panicking callback has already been reported by the thread
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::source::FakeEnv;
+
+    #[derive(Clone, Default)]
+    struct SharedEnv(Arc<Mutex<FakeEnv>>);
+
+    impl EnvSource for SharedEnv {
+        fn vars(&self) -> Result<Vec<(String, String)>, Error> {
+            self.0.lock().expect("lock is not poisoned").vars()
+        }
+    }
+
+    #[test]
+    fn test_watch_channel() -> Result<(), Error> {
+        let env = SharedEnv::default();
+        env.0.lock().unwrap().set("PREFIX__INT", "1");
+
+        let parser = Parser::default().with_prefix("PREFIX__");
+        let (watch, changes) = Watch::channel(parser, env.clone(), Duration::from_millis(5));
+        let timeout = Duration::from_secs(5);
+
+        assert_eq!(changes.recv_timeout(timeout).unwrap()?, json!({ "int": 1 }));
+
+        env.0.lock().unwrap().set("PREFIX__INT", "2");
+        assert_eq!(changes.recv_timeout(timeout).unwrap()?, json!({ "int": 2 }));
+
+        env.0.lock().unwrap().set("PREFIX__0", "invalid");
+        assert!(changes.recv_timeout(timeout).unwrap().is_err());
+
+        watch.stop();
+        assert!(changes.recv().is_err());
+
+        Ok(())
+    }
+}
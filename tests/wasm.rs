@@ -0,0 +1,28 @@
+//! The parser running in `wasm32-unknown-unknown`, where variables are injected instead of read
+//! from the process environment. Run with `wasm-pack test --node -- --test wasm`
+#![cfg(target_arch = "wasm32")]
+
+use std::collections::BTreeMap;
+
+use env_vars_to_json::Parser;
+use serde_json::json;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_parse_map() {
+    let vars = BTreeMap::from([
+        ("PREFIX__STRUCT__INT", "1"),
+        ("PREFIX__LIST__1", "b"),
+        ("OTHER", "skipped"),
+    ]);
+
+    let actual = Parser::default()
+        .with_prefix("PREFIX__")
+        .parse_map(&vars)
+        .unwrap();
+
+    assert_eq!(
+        actual,
+        json!({ "struct": { "int": 1 }, "list": [null, "b"] })
+    );
+}